@@ -2,7 +2,7 @@ use std::{env, path::PathBuf};
 
 fn main() -> Result<(), anyhow::Error> {
     let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("protos");
-    let proto_files = ["viguno/v1/simulation.proto"]
+    let proto_files = ["viguno/v1/phenopacket.proto", "viguno/v1/simulation.proto"]
         .iter()
         .map(|f| root.join(f))
         .collect::<Vec<_>>();
@@ -21,6 +21,12 @@ fn main() -> Result<(), anyhow::Error> {
         .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
         // Skip serializing `None` values.
         .type_attribute(".", "#[serde_with::skip_serializing_none]")
+        // Phenopackets use camel case in JSON and may omit any field.
+        .type_attribute(
+            ".viguno.v1.phenopacket",
+            "#[serde(rename_all = \"camelCase\", default)]",
+        )
+        .type_attribute(".viguno.v1.phenopacket", "#[derive(utoipa::ToSchema)]")
         // Define the protobuf files to compile.
         .compile_protos(&proto_files, &[root])?;
 
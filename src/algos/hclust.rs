@@ -0,0 +1,318 @@
+//! Agglomerative hierarchical clustering with average linkage (UPGMA).
+
+/// One merge step of the clustering.
+///
+/// Following the convention of `scipy.cluster.hierarchy`, the `n` leaves have the
+/// IDs `0..n` and the cluster created by the `i`-th merge has the ID `n + i`.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Merge {
+    /// The ID of the first merged cluster.
+    pub left: usize,
+    /// The ID of the second merged cluster.
+    pub right: usize,
+    /// The distance of the merged clusters.
+    pub height: f32,
+    /// The number of leaves in the new cluster.
+    pub size: usize,
+}
+
+/// Find the nearest active neighbour `j > i` of slot `i`, ties broken by the smaller
+/// slot.
+fn nearest_neighbour(
+    dist: &[Vec<f32>],
+    slots: &[Option<(usize, usize)>],
+    i: usize,
+) -> Option<(usize, f32)> {
+    let mut best: Option<(usize, f32)> = None;
+    for j in (i + 1)..dist.len() {
+        if slots[j].is_none() {
+            continue;
+        }
+        let d = dist[i][j];
+        let closer = best.is_none_or(|(_, best_d)| d.total_cmp(&best_d).is_lt());
+        if closer {
+            best = Some((j, d));
+        }
+    }
+    best
+}
+
+/// Cluster the items with the symmetric `distances` using average linkage.
+///
+/// Returns the `n - 1` merges in order of increasing height.  Ties are broken by
+/// the smaller cluster IDs.
+///
+/// The nearest neighbour of each cluster is cached and only recomputed for the
+/// clusters whose neighbour was merged, such that this typically takes `O(n^2)`
+/// rather than `O(n^3)` time.  The distance matrix itself takes `O(n^2)` memory.
+///
+/// # Panics
+///
+/// If `distances` is not a square matrix.
+pub fn average_linkage(distances: &[Vec<f32>]) -> Vec<Merge> {
+    let n = distances.len();
+    let mut dist = distances.to_vec();
+    // For each slot in `dist`, the ID and size of the cluster it holds, if active.
+    let mut slots: Vec<Option<(usize, usize)>> = (0..n).map(|i| Some((i, 1))).collect();
+    // For each active slot `i`, the nearest active slot `j > i` and its distance.
+    let mut neighbours = (0..n)
+        .map(|i| nearest_neighbour(&dist, &slots, i))
+        .collect::<Vec<_>>();
+    let mut merges = Vec::with_capacity(n.saturating_sub(1));
+
+    for step in 0..n.saturating_sub(1) {
+        // Find the closest pair of active clusters from the cached neighbours.
+        let mut best: Option<(usize, usize, f32)> = None;
+        for (i, neighbour) in neighbours.iter().enumerate() {
+            let Some((j, d)) = *neighbour else {
+                continue;
+            };
+            let closer = best.is_none_or(|(_, _, best_d)| d.total_cmp(&best_d).is_lt());
+            if closer {
+                best = Some((i, j, d));
+            }
+        }
+        let (i, j, height) = best.expect("must have two active clusters");
+        let (id_i, size_i) = slots[i].expect("slot must be active");
+        let (id_j, size_j) = slots[j].expect("slot must be active");
+
+        // Update the distances of the merged cluster in slot `i` with the
+        // Lance-Williams formula for average linkage.
+        #[allow(clippy::cast_precision_loss)]
+        let (w_i, w_j) = (size_i as f32, size_j as f32);
+        for k in 0..n {
+            if k != i && k != j && slots[k].is_some() {
+                let d = (w_i * dist[i][k] + w_j * dist[j][k]) / (w_i + w_j);
+                dist[i][k] = d;
+                dist[k][i] = d;
+            }
+        }
+        slots[i] = Some((n + step, size_i + size_j));
+        slots[j] = None;
+        neighbours[j] = None;
+
+        // Update the cached neighbours of the slots before `i` and `j`.  Only the
+        // distances to slot `i` changed, so the other cached neighbours stay valid.
+        neighbours[i] = nearest_neighbour(&dist, &slots, i);
+        for k in 0..j {
+            if k == i || slots[k].is_none() {
+                continue;
+            }
+            match neighbours[k] {
+                Some((nn, _)) if nn == i || nn == j => {
+                    neighbours[k] = nearest_neighbour(&dist, &slots, k);
+                }
+                Some((nn, nn_d)) if k < i => {
+                    let d = dist[k][i];
+                    if (d, i) < (nn_d, nn) {
+                        neighbours[k] = Some((i, d));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        merges.push(Merge {
+            left: id_i.min(id_j),
+            right: id_i.max(id_j),
+            height,
+            size: size_i + size_j,
+        });
+    }
+
+    merges
+}
+
+/// Find the root of `x` in the union-find forest `parent`, with path halving.
+fn find(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+/// Cut the dendrogram given by `merges` of `n` leaves at `height`.
+///
+/// Returns a 0-based cluster label for each leaf, clusters are numbered in the
+/// order of their first leaf.
+pub fn cut(merges: &[Merge], n: usize, height: f32) -> Vec<usize> {
+    // Union-find over the cluster IDs, following only merges up to `height`.
+    let mut parent = (0..(n + merges.len())).collect::<Vec<_>>();
+    for (step, merge) in merges.iter().enumerate() {
+        if merge.height <= height {
+            let id = n + step;
+            let left = find(&mut parent, merge.left);
+            let right = find(&mut parent, merge.right);
+            parent[left] = id;
+            parent[right] = id;
+        }
+    }
+
+    let mut labels = Vec::with_capacity(n);
+    let mut roots: Vec<usize> = Vec::new();
+    for leaf in 0..n {
+        let root = find(&mut parent, leaf);
+        let label = if let Some(label) = roots.iter().position(|r| *r == root) {
+            label
+        } else {
+            roots.push(root);
+            roots.len() - 1
+        };
+        labels.push(label);
+    }
+    labels
+}
+
+/// Quote `label` for use in a Newick string if necessary.
+fn newick_label(label: &str) -> String {
+    if label
+        .chars()
+        .any(|c| c.is_whitespace() || "()[]':;,".contains(c))
+    {
+        format!("'{}'", label.replace('\'', "''"))
+    } else {
+        label.to_string()
+    }
+}
+
+/// Render the dendrogram given by `merges` in Newick format.
+///
+/// The leaves are named by `labels` and the branch lengths are the differences of
+/// the heights of the nodes, with leaves at height 0.
+///
+/// # Panics
+///
+/// If `merges` does not match the number of `labels`.
+pub fn to_newick(merges: &[Merge], labels: &[String]) -> String {
+    let n = labels.len();
+    let mut nodes: Vec<Option<(String, f32)>> = labels
+        .iter()
+        .map(|label| Some((newick_label(label), 0f32)))
+        .collect();
+    for merge in merges {
+        let (left, left_height) = nodes[merge.left].take().expect("node must exist");
+        let (right, right_height) = nodes[merge.right].take().expect("node must exist");
+        nodes.push(Some((
+            format!(
+                "({}:{},{}:{})",
+                left,
+                merge.height - left_height,
+                right,
+                merge.height - right_height
+            ),
+            merge.height,
+        )));
+    }
+
+    // The roots that are left, a forest only if there are no merges at all.
+    let roots = nodes
+        .into_iter()
+        .flatten()
+        .map(|(node, _)| node)
+        .collect::<Vec<_>>();
+    debug_assert!(n == 0 || roots.len() == 1 || merges.is_empty());
+    if roots.len() == 1 {
+        format!("{};", roots[0])
+    } else {
+        format!("({});", roots.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    /// Distances of four points on a line at 0, 1, 5, and 7.
+    fn distances() -> Vec<Vec<f32>> {
+        let points = [0f32, 1f32, 5f32, 7f32];
+        points
+            .iter()
+            .map(|a| points.iter().map(|b| (a - b).abs()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn average_linkage() {
+        let merges = super::average_linkage(&distances());
+        insta::assert_yaml_snapshot!(merges);
+    }
+
+    /// Naive `O(n^3)` average linkage as reference for `average_linkage`.
+    fn average_linkage_naive(distances: &[Vec<f32>]) -> Vec<super::Merge> {
+        let n = distances.len();
+        let mut dist = distances.to_vec();
+        let mut slots: Vec<Option<(usize, usize)>> = (0..n).map(|i| Some((i, 1))).collect();
+        let mut merges = Vec::new();
+        for step in 0..n.saturating_sub(1) {
+            let mut best: Option<(usize, usize, f32)> = None;
+            for i in 0..n {
+                for j in (i + 1)..n {
+                    if slots[i].is_some()
+                        && slots[j].is_some()
+                        && best.is_none_or(|(_, _, d)| dist[i][j] < d)
+                    {
+                        best = Some((i, j, dist[i][j]));
+                    }
+                }
+            }
+            let (i, j, height) = best.unwrap();
+            let ((id_i, size_i), (id_j, size_j)) = (slots[i].unwrap(), slots[j].unwrap());
+            #[allow(clippy::cast_precision_loss)]
+            let (w_i, w_j) = (size_i as f32, size_j as f32);
+            for k in 0..n {
+                if k != i && k != j && slots[k].is_some() {
+                    let d = (w_i * dist[i][k] + w_j * dist[j][k]) / (w_i + w_j);
+                    dist[i][k] = d;
+                    dist[k][i] = d;
+                }
+            }
+            slots[i] = Some((n + step, size_i + size_j));
+            slots[j] = None;
+            merges.push(super::Merge {
+                left: id_i.min(id_j),
+                right: id_i.max(id_j),
+                height,
+                size: size_i + size_j,
+            });
+        }
+        merges
+    }
+
+    #[test]
+    fn average_linkage_matches_naive() {
+        let mut rng = fastrand::Rng::with_seed(42);
+        for n in [2, 5, 17, 40] {
+            // Use few distinct values to exercise the tie breaking.
+            let mut distances = vec![vec![0f32; n]; n];
+            for (i, j) in (0..n).flat_map(|i| ((i + 1)..n).map(move |j| (i, j))) {
+                let d = f32::from(rng.u8(1..8));
+                distances[i][j] = d;
+                distances[j][i] = d;
+            }
+            assert_eq!(
+                super::average_linkage(&distances),
+                average_linkage_naive(&distances),
+                "n = {n}"
+            );
+        }
+    }
+
+    #[rstest::rstest]
+    #[case(0.5, vec![0, 1, 2, 3])]
+    #[case(1.0, vec![0, 0, 1, 2])]
+    #[case(2.0, vec![0, 0, 1, 1])]
+    #[case(10.0, vec![0, 0, 0, 0])]
+    fn cut(#[case] height: f32, #[case] expected: Vec<usize>) {
+        let merges = super::average_linkage(&distances());
+        assert_eq!(super::cut(&merges, 4, height), expected);
+    }
+
+    #[test]
+    fn to_newick() {
+        let merges = super::average_linkage(&distances());
+        let labels = ["a", "b", "c d", "e"]
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect::<Vec<_>>();
+        insta::assert_snapshot!(super::to_newick(&merges, &labels));
+    }
+}
@@ -1,3 +1,6 @@
 //! Phenotype-related algorithms.
 
+pub mod hclust;
+pub mod onset;
 pub mod phenomizer;
+pub mod stats;
@@ -0,0 +1,353 @@
+//! Compatibility of the age of onset of a patient and of candidate genes and diseases.
+
+use std::collections::HashMap;
+
+use crate::common::phenotype_hpoa::{Aspect, Disease};
+
+/// Factor for the similarity score of candidates with an incompatible onset.
+pub const INCOMPATIBLE_ONSET_FACTOR: f32 = 0.5;
+
+/// Days per year for converting ages.
+const DAYS_PER_YEAR: f64 = 365.25;
+
+/// Weeks of gestation at birth for converting gestational ages.
+const WEEKS_AT_BIRTH: f64 = 40.0;
+
+/// Convert the gestational age in `weeks` to the (negative) age in years.
+fn gestation(weeks: f64) -> f64 {
+    (weeks - WEEKS_AT_BIRTH) * 7.0 / DAYS_PER_YEAR
+}
+
+/// A range of ages of onset in years, negative for antenatal onset with birth at 40
+/// weeks of gestation.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OnsetRange {
+    /// The earliest age of onset.
+    pub start: f64,
+    /// The latest age of onset, inclusive.
+    pub end: f64,
+}
+
+impl OnsetRange {
+    /// Construct from the HPO onset term with `term_id`, e.g., `HP:0003581` for adult
+    /// onset, following the age ranges given in the term definitions.
+    ///
+    /// All terms of the subtree below Onset (`HP:0003674`) are known; returns `None`
+    /// for all other terms.
+    pub fn from_term_id(term_id: &str) -> Option<Self> {
+        let (start, end) = match term_id {
+            // Onset, i.e., any onset.
+            "HP:0003674" => (gestation(0.0), f64::INFINITY),
+            // Antenatal onset.
+            "HP:0030674" => (gestation(0.0), 0.0),
+            // Embryonal onset, up to 10 weeks of gestation.
+            "HP:0011460" => (gestation(0.0), gestation(10.0)),
+            // Fetal onset.
+            "HP:0011461" => (gestation(10.0), 0.0),
+            // Late first trimester onset.
+            "HP:0034199" => (gestation(11.0), gestation(14.0)),
+            // Second trimester onset.
+            "HP:0034198" => (gestation(14.0), gestation(28.0)),
+            // Third trimester onset.
+            "HP:0034197" => (gestation(28.0), 0.0),
+            // Congenital onset.
+            "HP:0003577" => (0.0, 0.0),
+            // Neonatal onset.
+            "HP:0003623" => (0.0, 28.0 / DAYS_PER_YEAR),
+            // Pediatric onset.
+            "HP:0410280" => (0.0, 16.0),
+            // Infantile onset.
+            "HP:0003593" => (28.0 / DAYS_PER_YEAR, 1.0),
+            // Childhood onset.
+            "HP:0011463" => (1.0, 5.0),
+            // Juvenile onset.
+            "HP:0003621" => (5.0, 16.0),
+            // Adult onset.
+            "HP:0003581" => (16.0, f64::INFINITY),
+            // Young adult onset.
+            "HP:0011462" => (16.0, 40.0),
+            // Early young adult onset.
+            "HP:0025708" => (16.0, 19.0),
+            // Intermediate young adult onset.
+            "HP:0025709" => (19.0, 25.0),
+            // Late young adult onset.
+            "HP:0025710" => (25.0, 40.0),
+            // Middle age onset.
+            "HP:0003596" => (40.0, 60.0),
+            // Late onset.
+            "HP:0003584" => (60.0, f64::INFINITY),
+            // Puerperal onset, the term definitions give no ages so the ranges of the
+            // following terms are approximations.
+            "HP:4000040" => (12.0, 55.0),
+            // Perimenopausal onset.
+            "HP:6000314" => (40.0, 55.0),
+            // Postmenopausal onset.
+            "HP:6000315" => (45.0, f64::INFINITY),
+            _ => return None,
+        };
+        Some(Self { start, end })
+    }
+
+    /// Construct from an ISO8601 duration giving the age, e.g., `P1Y6M` or `P10D`.
+    pub fn from_iso8601(age: &str) -> Option<Self> {
+        let mut rest = age.strip_prefix('P')?;
+        if rest.is_empty() {
+            return None;
+        }
+        let mut years = 0f64;
+        for (unit, factor) in [
+            ('Y', 1.0),
+            ('M', 1.0 / 12.0),
+            ('W', 7.0 / DAYS_PER_YEAR),
+            ('D', 1.0 / DAYS_PER_YEAR),
+        ] {
+            if let Some((value, tail)) = rest.split_once(unit) {
+                years += value.parse::<f64>().ok()? * factor;
+                rest = tail;
+            }
+        }
+        rest.is_empty().then_some(Self {
+            start: years,
+            end: years,
+        })
+    }
+
+    /// Parse `value` as an HPO onset term ID or an ISO8601 age.
+    ///
+    /// # Errors
+    ///
+    /// In the case that `value` is neither.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let value = value.trim();
+        Self::from_term_id(value)
+            .or_else(|| Self::from_iso8601(value))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "invalid onset, neither HPO onset term nor ISO8601 age: {}",
+                    value
+                )
+            })
+    }
+
+    /// Whether the ranges overlap.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    /// The smallest range containing both ranges.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// Return the earliest of the `onsets`, given as HPO onset term IDs or ISO8601 ages,
+/// i.e., the one whose range starts first.
+///
+/// # Errors
+///
+/// In the case that one of the `onsets` is invalid, see `OnsetRange::parse`.
+pub fn earliest<'a, I>(onsets: I) -> Result<Option<&'a str>, anyhow::Error>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut result: Option<(OnsetRange, &str)> = None;
+    for onset in onsets {
+        let range = OnsetRange::parse(onset)?;
+        if result.is_none_or(|(earliest, _)| range.start < earliest.start) {
+            result = Some((range, onset));
+        }
+    }
+    Ok(result.map(|(_, onset)| onset))
+}
+
+/// The ranges of the annotated onsets of genes and diseases.
+///
+/// The onset range of a disease covers all onsets annotated to the disease in
+/// `phenotype.hpoa`, i.e., the `onset` column and the onset terms annotated with
+/// the clinical course aspect, ignoring annotations qualified with `NOT`.  The
+/// one of a gene covers the ranges of its diseases.
+/// Candidates without annotated onset are considered compatible with any onset.
+#[derive(Debug, Clone, Default)]
+pub struct CandidateOnsets {
+    /// Onset ranges by NCBI gene ID.
+    genes: HashMap<u32, OnsetRange>,
+    /// Onset ranges by disease ID, e.g., `OMIM:616145`.
+    diseases: HashMap<String, OnsetRange>,
+}
+
+impl CandidateOnsets {
+    /// Construct from the onsets annotated to the `diseases` from `phenotype.hpoa`
+    /// and the genes of the diseases from `disease_to_genes`.
+    pub fn new<S>(diseases: &[Disease], disease_to_genes: &HashMap<String, Vec<u32>, S>) -> Self
+    where
+        S: std::hash::BuildHasher,
+    {
+        let mut result = Self::default();
+        for disease in diseases {
+            let Some(range) = disease
+                .annotations
+                .iter()
+                .filter(|annotation| !annotation.negated)
+                .flat_map(|annotation| {
+                    let term = (annotation.aspect == Aspect::ClinicalCourse)
+                        .then_some(annotation.hpo_id.as_str());
+                    annotation.onset.as_deref().into_iter().chain(term)
+                })
+                .filter_map(OnsetRange::from_term_id)
+                .reduce(|a, b| a.union(&b))
+            else {
+                continue;
+            };
+            for ncbi_gene_id in disease_to_genes
+                .get(&disease.disease_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+            {
+                result
+                    .genes
+                    .entry(*ncbi_gene_id)
+                    .and_modify(|other| *other = other.union(&range))
+                    .or_insert(range);
+            }
+            result.diseases.insert(disease.disease_id.clone(), range);
+        }
+        result
+    }
+
+    /// Return the onset range of the gene with `ncbi_gene_id`, if any.
+    pub fn gene(&self, ncbi_gene_id: u32) -> Option<&OnsetRange> {
+        self.genes.get(&ncbi_gene_id)
+    }
+
+    /// Return the onset range of the disease with `disease_id`, if any.
+    pub fn disease(&self, disease_id: &str) -> Option<&OnsetRange> {
+        self.diseases.get(disease_id)
+    }
+}
+
+/// Return whether the `patient` onset is compatible with the onset range of a
+/// candidate, `None` if the candidate has no annotated onset.
+pub fn is_compatible(patient: &OnsetRange, candidate: Option<&OnsetRange>) -> Option<bool> {
+    candidate.map(|candidate| patient.overlaps(candidate))
+}
+
+/// Return the factor for the similarity score of a candidate, i.e.,
+/// `INCOMPATIBLE_ONSET_FACTOR` if its onset is incompatible and `1.0` otherwise.
+pub fn score_factor(compatible: Option<bool>) -> f32 {
+    if compatible == Some(false) {
+        INCOMPATIBLE_ONSET_FACTOR
+    } else {
+        1f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OnsetRange;
+
+    #[rstest::rstest]
+    #[case("HP:0003623", Some((0.0, 28.0 / 365.25)))]
+    #[case("HP:0003581", Some((16.0, f64::INFINITY)))]
+    #[case("HP:0025709", Some((19.0, 25.0)))]
+    #[case("HP:0034197", Some((-12.0 * 7.0 / 365.25, 0.0)))]
+    #[case("P1Y6M", Some((1.5, 1.5)))]
+    #[case("P2W", Some((14.0 / 365.25, 14.0 / 365.25)))]
+    #[case("P", None)]
+    #[case("P1X", None)]
+    #[case("HP:0000001", None)]
+    fn parse(#[case] value: &str, #[case] expected: Option<(f64, f64)>) {
+        assert_eq!(
+            OnsetRange::parse(value).ok(),
+            expected.map(|(start, end)| OnsetRange { start, end })
+        );
+    }
+
+    #[test]
+    fn earliest() -> Result<(), anyhow::Error> {
+        assert_eq!(
+            super::earliest(["HP:0003581", "P2W", "HP:0011463"])?,
+            Some("P2W")
+        );
+        assert_eq!(
+            super::earliest(["HP:0003577", "HP:0034197"])?,
+            Some("HP:0034197")
+        );
+        assert_eq!(super::earliest([])?, None);
+        assert!(super::earliest(["HP:0003581", "soon"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn is_compatible() -> Result<(), anyhow::Error> {
+        let neonatal = OnsetRange::parse("HP:0003623")?;
+        let adult = OnsetRange::parse("HP:0003581")?;
+        let childhood = OnsetRange::parse("HP:0011463")?;
+
+        assert_eq!(super::is_compatible(&neonatal, Some(&adult)), Some(false));
+        assert_eq!(
+            super::is_compatible(&neonatal, Some(&childhood.union(&neonatal))),
+            Some(true)
+        );
+        assert_eq!(
+            super::is_compatible(&OnsetRange::parse("P30Y")?, Some(&adult)),
+            Some(true)
+        );
+        assert_eq!(super::is_compatible(&neonatal, None), None);
+        Ok(())
+    }
+
+    #[test]
+    fn candidate_onsets() -> Result<(), anyhow::Error> {
+        let tmpdir = tempdir::TempDir::new("viguno")?;
+        let path = tmpdir.path().join("phenotype.hpoa");
+        std::fs::write(
+            &path,
+            "database_id\tdisease_name\tqualifier\thpo_id\treference\tevidence\tonset\tfrequency\tsex\tmodifier\taspect\tbiocuration\n\
+             OMIM:1\tOne\t\tHP:0000347\tOMIM:1\tTAS\tHP:0003623\t\t\t\tP\tx\n\
+             OMIM:1\tOne\t\tHP:0010442\tOMIM:1\tTAS\tHP:0011463\t\t\t\tP\tx\n\
+             OMIM:2\tTwo\t\tHP:0000347\tOMIM:2\tTAS\tHP:0003581\t\t\t\tP\tx\n\
+             OMIM:3\tThree\t\tHP:0000347\tOMIM:3\tTAS\t\t\t\t\tP\tx\n\
+             OMIM:3\tThree\tNOT\tHP:0000347\tOMIM:3\tTAS\tHP:0003581\t\t\t\tP\tx\n\
+             OMIM:4\tFour\t\tHP:0034198\tOMIM:4\tTAS\t\t\t\t\tC\tx\n\
+             OMIM:4\tFour\t\tHP:0003676\tOMIM:4\tTAS\t\t\t\t\tC\tx\n\
+             OMIM:4\tFour\tNOT\tHP:0003584\tOMIM:4\tTAS\t\t\t\t\tC\tx\n",
+        )?;
+        let diseases = crate::common::phenotype_hpoa::load_diseases(&path)?;
+        let disease_to_genes = std::collections::HashMap::from([
+            ("OMIM:1".to_string(), vec![1]),
+            ("OMIM:2".to_string(), vec![1, 2]),
+        ]);
+
+        let onsets = super::CandidateOnsets::new(&diseases, &disease_to_genes);
+        assert_eq!(
+            onsets.disease("OMIM:1"),
+            Some(&OnsetRange {
+                start: 0.0,
+                end: 5.0
+            })
+        );
+        // Onsets of annotations qualified with `NOT` are ignored.
+        assert!(onsets.disease("OMIM:3").is_none());
+        // Onset terms annotated with the clinical course aspect are used.
+        assert_eq!(
+            onsets.disease("OMIM:4"),
+            OnsetRange::parse("HP:0034198").ok().as_ref()
+        );
+        assert_eq!(
+            onsets.gene(1),
+            Some(&OnsetRange {
+                start: 0.0,
+                end: f64::INFINITY
+            })
+        );
+        assert_eq!(
+            onsets.gene(2),
+            OnsetRange::parse("HP:0003581").ok().as_ref()
+        );
+        Ok(())
+    }
+}
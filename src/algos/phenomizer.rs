@@ -1,47 +1,160 @@
 //! Similarity computation using the Phenomizer method.
 
-use hpo::{
-    similarity::{Builtins, Similarity},
-    term::{HpoGroup, InformationContentKind},
-    Ontology,
-};
+use hpo::{similarity::Similarity, term::HpoGroup, HpoTermId, Ontology};
 
-// TODO: this is equivalent to `StandardCombiner::FunSimAvg` USE IT.
+use crate::common::ScoreCombiner;
 
 /// Compute symmetric similarity score.
-pub fn score(q: &HpoGroup, d: &HpoGroup, o: &Ontology) -> f32 {
-    let s = Builtins::Resnik(InformationContentKind::Gene);
-    (score_dir(q, d, o, &s) + score_dir(d, q, o, &s)) / 2.0
+///
+/// The term-term similarity is computed with `s` and the two directed scores are combined
+/// using `combiner`.  Using Resnik similarity and `ScoreCombiner::FunSimAvg` yields the
+/// original Phenomizer score.
+pub fn score(
+    q: &HpoGroup,
+    d: &HpoGroup,
+    o: &Ontology,
+    s: &impl Similarity,
+    combiner: ScoreCombiner,
+) -> f32 {
+    score_weighted(q, d, o, s, combiner, |_| 1f32)
 }
 
-/// "Directed" score part of phenomizer score.
+/// Compute symmetric similarity score with the unmatched annotated terms `d` weighted
+/// by `weight`.
+///
+/// This is the same as `score` except that in the direction from `d` to `q`, the best
+/// match of each annotated term that is absent from `q` contributes with its weight,
+/// e.g., the frequency of the term in the disease.  An annotated term is present in `q`
+/// if `q` contains the term itself or one of its descendants; such terms keep the
+/// weight `1.0`.  Thus, rare features that are absent from the patient lower the
+/// score less than obligate ones while rare features that the patient has count in
+/// full.  With `ScoreCombiner::Bma`, the sum of the weights is used in place of the
+/// number of annotated terms.
+pub fn score_weighted(
+    q: &HpoGroup,
+    d: &HpoGroup,
+    o: &Ontology,
+    s: &impl Similarity,
+    combiner: ScoreCombiner,
+    weight: impl Fn(HpoTermId) -> f32,
+) -> f32 {
+    let (score_qd, _) = score_dir(q, d, o, s, |_| 1f32);
+    let q_terms = q.iter().filter_map(|q| o.hpo(q)).collect::<Vec<_>>();
+    let is_present = |term_id: HpoTermId| {
+        q.contains(&term_id)
+            || o.hpo(term_id)
+                .is_some_and(|d| q_terms.iter().any(|q| q.child_of(&d)))
+    };
+    let (score_dq, weight_d) = score_dir(d, q, o, s, |term_id| {
+        if is_present(term_id) {
+            1f32
+        } else {
+            weight(term_id)
+        }
+    });
+    match combiner {
+        ScoreCombiner::FunSimAvg => f32::midpoint(score_qd, score_dq),
+        ScoreCombiner::FunSimMax => score_qd.max(score_dq),
+        ScoreCombiner::Bma => {
+            let len_q = len_f32(q);
+            if len_q + weight_d == 0f32 {
+                0f32
+            } else {
+                (score_qd * len_q + score_dq * weight_d) / (len_q + weight_d)
+            }
+        }
+    }
+}
+
+/// Determine the excluded terms from `xs` that are annotated in `ds`.
+///
+/// An excluded term `x` is annotated if `ds` contains `x` itself or one of its
+/// descendants.  Returns tuples of the excluded term, the best matching annotated
+/// term, and the similarity of the two, computed with `s`.
+pub fn excluded_matches(
+    xs: &HpoGroup,
+    ds: &HpoGroup,
+    o: &Ontology,
+    s: &impl Similarity,
+) -> Vec<(HpoTermId, HpoTermId, f32)> {
+    let mut result = Vec::new();
+    for x in xs {
+        let Some(x) = o.hpo(x) else {
+            continue;
+        };
+        let best = ds
+            .iter()
+            .filter_map(|d| o.hpo(d))
+            .filter(|d| d.id() == x.id() || d.child_of(&x))
+            .map(|d| (d.id(), x.similarity_score(&d, s)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((d, score)) = best {
+            result.push((x.id(), d, score));
+        }
+    }
+    result
+}
+
+/// Compute the penalty for the `matches` of the excluded terms `xs` as returned by
+/// `excluded_matches`.
+///
+/// The penalty is the sum of the similarities divided by the number of excluded
+/// terms and is to be subtracted from the result of `score`.
+pub fn excluded_penalty(xs: &HpoGroup, matches: &[(HpoTermId, HpoTermId, f32)]) -> f32 {
+    if xs.is_empty() {
+        0f32
+    } else {
+        matches.iter().map(|(_, _, score)| score).sum::<f32>() / len_f32(xs)
+    }
+}
+
+/// Return length of `group` as `f32`.
 ///
 /// # Panics
 ///
-/// If there are more query terms than fit an `f32` value.
-fn score_dir(qs: &HpoGroup, ds: &HpoGroup, o: &Ontology, s: &impl Similarity) -> f32 {
+/// If there are more terms than fit an `f32` value.
+fn len_f32(group: &HpoGroup) -> f32 {
+    // NB: we allow loss of precision in this function for the following statement.
+    let len: u16 = group.len().try_into().expect("more than 2^16 query terms");
+    len.into()
+}
+
+/// "Directed" score part of phenomizer score.
+///
+/// The best match of each `q in qs` contributes with `weight(q)`.  Returns the
+/// weighted mean and the sum of the weights of `qs`.
+fn score_dir(
+    qs: &HpoGroup,
+    ds: &HpoGroup,
+    o: &Ontology,
+    s: &impl Similarity,
+    weight: impl Fn(HpoTermId) -> f32,
+) -> (f32, f32) {
     // Handle case of empty `qs`.
     if qs.is_empty() {
-        return 0f32;
+        return (0f32, 0f32);
     }
 
     // For each `q in qs` compute max similarity to any `d in ds`.
-    let mut tmp: Vec<f32> = Vec::new();
+    let mut total = 0f32;
+    let mut total_weight = 0f32;
     for q in qs {
+        let weight = weight(q);
+        total_weight += weight;
         if let Some(q) = o.hpo(q) {
-            tmp.push(
-                ds.iter()
+            total += weight
+                * ds.iter()
                     .filter_map(|d| o.hpo(d).map(|d| q.similarity_score(&d, s)))
-                    .max_by(|a, b| a.partial_cmp(b).expect("try to compare NaN"))
-                    .unwrap_or_default(),
-            );
+                    .max_by(f32::total_cmp)
+                    .unwrap_or_default();
         }
     }
 
-    // NB: we allow loss of precision in this function for the following statement.
-    let len: u16 = qs.len().try_into().expect("more than 2^16 query terms");
-    let len: f32 = len.into();
-    tmp.iter().sum::<f32>() / len
+    if total_weight == 0f32 {
+        (0f32, 0f32)
+    } else {
+        (total / total_weight, total_weight)
+    }
 }
 
 #[cfg(test)]
@@ -49,7 +162,8 @@ mod test {
     use super::*;
     use hpo::{
         annotations::{Disease as _, OmimDiseaseId},
-        term::HpoGroup,
+        similarity::Builtins,
+        term::{HpoGroup, InformationContentKind},
         HpoTermId, Ontology,
     };
 
@@ -86,10 +200,137 @@ mod test {
             .into_iter()
             .collect::<HpoGroup>();
 
-        let score = score(&prepare(query), &hpo_marfan, &hpo);
+        let score = score(
+            &prepare(query),
+            &hpo_marfan,
+            &hpo,
+            &Builtins::Resnik(InformationContentKind::Gene),
+            ScoreCombiner::FunSimAvg,
+        );
 
         assert!((score - 1.756_347).abs() < 0.00001, "score = {score}");
 
         Ok(())
     }
+
+    #[test]
+    fn phenomizer_score_weighted() -> Result<(), anyhow::Error> {
+        let hpo = load_hpo()?;
+
+        let query = prepare(&["HP:0001533", "HP:0002705"]);
+        let omim_marfan = hpo
+            .omim_disease(&OmimDiseaseId::from(154_700))
+            .expect("marfan symdrome must be in HPO");
+        let hpo_marfan = omim_marfan
+            .to_hpo_set(&hpo)
+            .child_nodes()
+            .without_modifier()
+            .into_iter()
+            .collect::<HpoGroup>();
+        let sim = Builtins::Resnik(InformationContentKind::Gene);
+
+        // Uniform weights yield the unweighted score.
+        for combiner in [
+            ScoreCombiner::FunSimAvg,
+            ScoreCombiner::FunSimMax,
+            ScoreCombiner::Bma,
+        ] {
+            let unweighted = score(&query, &hpo_marfan, &hpo, &sim, combiner);
+            let weighted = score_weighted(&query, &hpo_marfan, &hpo, &sim, combiner, |_| 1f32);
+            assert!(
+                (unweighted - weighted).abs() < 0.00001,
+                "unweighted = {unweighted}, weighted = {weighted}"
+            );
+        }
+
+        // Down-weighting the annotated terms absent from the query raises the score.
+        let unweighted = score(&query, &hpo_marfan, &hpo, &sim, ScoreCombiner::FunSimAvg);
+        let weighted = score_weighted(
+            &query,
+            &hpo_marfan,
+            &hpo,
+            &sim,
+            ScoreCombiner::FunSimAvg,
+            |term_id| {
+                if query.contains(&term_id) {
+                    1f32
+                } else {
+                    0.025
+                }
+            },
+        );
+        assert!(weighted > unweighted, "weighted = {weighted}");
+
+        // Down-weighting an annotated term changes the score only if the term is
+        // absent from the query, having the term or a descendant counts as present.
+        let term = hpo_marfan
+            .iter()
+            .filter_map(|term_id| hpo.hpo(term_id))
+            .find(|term| {
+                term.children().next().is_some()
+                    && !query.contains(&term.id())
+                    && query
+                        .iter()
+                        .filter_map(|q| hpo.hpo(q))
+                        .all(|q| !q.child_of(term))
+            })
+            .expect("marfan syndrome must have a term with children absent from query");
+        let child = term.children().next().expect("term must have a child").id();
+        let score_with = |query: &HpoGroup, weight_term: f32| {
+            score_weighted(
+                query,
+                &hpo_marfan,
+                &hpo,
+                &sim,
+                ScoreCombiner::FunSimAvg,
+                |term_id| {
+                    if term_id == term.id() {
+                        weight_term
+                    } else {
+                        1f32
+                    }
+                },
+            )
+        };
+        for present in [HpoGroup::from(vec![term.id()]), HpoGroup::from(vec![child])] {
+            let (full, low) = (score_with(&present, 1f32), score_with(&present, 0.025));
+            assert!((full - low).abs() < 0.00001, "full = {full}, low = {low}");
+        }
+        let (full, low) = (score_with(&query, 1f32), score_with(&query, 0.025));
+        assert!((full - low).abs() > 0.00001, "full = {full}, low = {low}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn phenomizer_excluded_penalty() -> Result<(), anyhow::Error> {
+        let hpo = load_hpo()?;
+
+        let omim_marfan = hpo
+            .omim_disease(&OmimDiseaseId::from(154_700))
+            .expect("marfan symdrome must be in HPO");
+        let hpo_marfan = omim_marfan
+            .to_hpo_set(&hpo)
+            .child_nodes()
+            .without_modifier()
+            .into_iter()
+            .collect::<HpoGroup>();
+        let sim = Builtins::Resnik(InformationContentKind::Gene);
+
+        // Abnormality of the skeletal system is an ancestor of the annotated terms
+        // while polydactyly is not annotated.
+        let excluded = prepare(&["HP:0000924", "HP:0010442"]);
+        let matches = excluded_matches(&excluded, &hpo_marfan, &hpo, &sim);
+        assert_eq!(matches.len(), 1, "matches = {matches:?}");
+        assert_eq!(matches[0].0, HpoTermId::from("HP:0000924".to_string()));
+
+        let penalty = excluded_penalty(&excluded, &matches);
+        assert!(
+            (penalty - matches[0].2 / 2f32).abs() < 0.00001,
+            "penalty = {penalty}"
+        );
+        assert!(excluded_penalty(&HpoGroup::new(), &[]).abs() < f32::EPSILON);
+
+        Ok(())
+    }
 }
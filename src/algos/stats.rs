@@ -0,0 +1,165 @@
+//! Statistical helper functions.
+
+/// Adjust P-values for multiple testing using the Benjamini-Hochberg procedure.
+///
+/// The adjusted values are returned in the order of `p_values`.
+pub fn benjamini_hochberg(p_values: &[f64]) -> Vec<f64> {
+    let mut order = (0..p_values.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| p_values[*a].total_cmp(&p_values[*b]));
+
+    // NB: we allow loss of precision here as we will never have 2^52 P-values.
+    #[allow(clippy::cast_precision_loss)]
+    let n = p_values.len() as f64;
+    let mut result = vec![0f64; p_values.len()];
+    let mut prev = 1f64;
+    for (rank, idx) in order.iter().enumerate().rev() {
+        #[allow(clippy::cast_precision_loss)]
+        let adjusted = (p_values[*idx] * n / (rank + 1) as f64).min(prev);
+        result[*idx] = adjusted;
+        prev = adjusted;
+    }
+    result
+}
+
+/// Adjust P-values for multiple testing using the Bonferroni correction.
+///
+/// The adjusted values are returned in the order of `p_values`.
+pub fn bonferroni(p_values: &[f64]) -> Vec<f64> {
+    // NB: we allow loss of precision here as we will never have 2^52 P-values.
+    #[allow(clippy::cast_precision_loss)]
+    let n = p_values.len() as f64;
+    p_values.iter().map(|p| (p * n).min(1f64)).collect()
+}
+
+/// Table of the natural logarithms of `0!` to `n!`.
+///
+/// Computing `ln(k!)` takes `O(k)` time, so callers that evaluate many
+/// hypergeometric tails for the same population should build this table once and
+/// use `LnFactorials::hypergeometric_upper_tail`.
+#[derive(Debug, Clone)]
+pub struct LnFactorials {
+    /// The value of `ln(k!)` at index `k`.
+    values: Vec<f64>,
+}
+
+impl LnFactorials {
+    /// Construct the table for values up to `n!`.
+    pub fn new(n: u64) -> Self {
+        let mut values = Vec::with_capacity(usize::try_from(n).unwrap_or_default() + 1);
+        let mut acc = 0f64;
+        values.push(acc);
+        for i in 1..=n {
+            // NB: we allow loss of precision here as the terms are small integers.
+            #[allow(clippy::cast_precision_loss)]
+            let i = i as f64;
+            acc += i.ln();
+            values.push(acc);
+        }
+        Self { values }
+    }
+
+    /// Natural logarithm of `k!`.
+    ///
+    /// # Panics
+    ///
+    /// If `k` exceeds the size of the table.
+    fn ln_factorial(&self, k: u64) -> f64 {
+        self.values[usize::try_from(k).expect("k must fit into usize")]
+    }
+
+    /// Natural logarithm of the binomial coefficient `n` choose `k`.
+    fn ln_choose(&self, n: u64, k: u64) -> f64 {
+        self.ln_factorial(n) - self.ln_factorial(k) - self.ln_factorial(n - k)
+    }
+
+    /// Upper tail `P(X >= k)` of the hypergeometric distribution.
+    ///
+    /// This is the one-sided P-value of Fisher's exact test for over-representation
+    /// when drawing `draws` items from a `population` containing `successes` items
+    /// of interest and observing `k` of them.
+    ///
+    /// # Panics
+    ///
+    /// If `draws` or `successes` exceed `population` or `population` exceeds the
+    /// size of the table.
+    pub fn hypergeometric_upper_tail(
+        &self,
+        k: u64,
+        draws: u64,
+        successes: u64,
+        population: u64,
+    ) -> f64 {
+        assert!(draws <= population && successes <= population);
+        let lower = k.max((draws + successes).saturating_sub(population));
+        let upper = draws.min(successes);
+        if lower > upper {
+            return 0f64;
+        }
+        let ln_total = self.ln_choose(population, draws);
+        let p = (lower..=upper)
+            .map(|x| {
+                (self.ln_choose(successes, x) + self.ln_choose(population - successes, draws - x)
+                    - ln_total)
+                    .exp()
+            })
+            .sum::<f64>();
+        p.min(1f64)
+    }
+}
+
+/// Upper tail `P(X >= k)` of the hypergeometric distribution.
+///
+/// See `LnFactorials::hypergeometric_upper_tail`, this builds the table of
+/// logarithms for a single evaluation.
+///
+/// # Panics
+///
+/// If `draws` or `successes` exceed `population`.
+pub fn hypergeometric_upper_tail(k: u64, draws: u64, successes: u64, population: u64) -> f64 {
+    LnFactorials::new(population).hypergeometric_upper_tail(k, draws, successes, population)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn benjamini_hochberg() {
+        let adjusted = super::benjamini_hochberg(&[0.04, 0.01, 0.03, 0.5]);
+        let expected = [0.053_333, 0.04, 0.053_333, 0.5];
+        for (a, e) in adjusted.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 0.00001, "adjusted = {adjusted:?}");
+        }
+    }
+
+    #[test]
+    fn bonferroni() {
+        let adjusted = super::bonferroni(&[0.04, 0.01, 0.03, 0.5]);
+        assert_eq!(adjusted, vec![0.16, 0.04, 0.12, 1.0]);
+    }
+
+    #[test]
+    fn hypergeometric_upper_tail() {
+        // Compare to `scipy.stats.hypergeom.sf(k - 1, population, successes, draws)`.
+        let cases = [
+            ((3, 5, 4, 20), 0.031_992),
+            ((1, 5, 4, 20), 0.718_266),
+            ((0, 5, 4, 20), 1.0),
+            ((4, 5, 4, 20), 0.001_032),
+            ((5, 5, 4, 20), 0.0),
+        ];
+        for ((k, draws, successes, population), expected) in cases {
+            let p = super::hypergeometric_upper_tail(k, draws, successes, population);
+            assert!((p - expected).abs() < 0.00001, "k = {k}, p = {p}");
+        }
+    }
+
+    #[test]
+    fn ln_factorials() {
+        let table = super::LnFactorials::new(100);
+        assert!(table.ln_factorial(0).abs() < 1e-12);
+        assert!(table.ln_factorial(1).abs() < 1e-12);
+        assert!((table.ln_factorial(5) - 120f64.ln()).abs() < 1e-9);
+        // A table for a larger population gives the same tails.
+        let p = table.hypergeometric_upper_tail(3, 5, 4, 20);
+        assert!((p - 0.031_992).abs() < 0.00001, "p = {p}");
+    }
+}
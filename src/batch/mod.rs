@@ -0,0 +1,297 @@
+//! Batch scoring of many cases against the same gene set.
+
+use std::collections::HashMap;
+use std::io::{BufRead as _, Write as _};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use clap::Parser;
+use hpo::{annotations::Gene, term::HpoGroup, HpoTermId, Ontology};
+use rayon::prelude::*;
+
+use crate::query::{
+    query_result::HpoSimTermGeneResult, run_query, ResultLimits, ScoreWeighting, ScoringArgs,
+};
+use crate::simulate::store::{load_store, Store};
+
+/// Command line arguments for `batch` sub command.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Score many cases against the same genes", long_about = None)]
+pub struct Args {
+    /// Path to the directory with the HPO files.
+    #[arg(long, required = true)]
+    pub path_hpo_dir: String,
+
+    /// Path to the cases file, either JSONL with one `{"case_id": .., "terms": [..]}`
+    /// record per line or, if the name ends in `.tsv`, a TSV file with the header
+    /// `case_id` and `terms` and comma-separated terms.  Excluded terms can be given
+    /// in `excluded_terms`.
+    #[arg(long, required = true)]
+    pub path_cases: String,
+    /// Path to JSON file with the genes to rank, defaults to all genes with annotations.
+    #[arg(long)]
+    pub path_genes_json: Option<String>,
+    /// Path to the output JSONL file, defaults to stdout.
+    #[arg(long)]
+    pub path_output: Option<String>,
+    /// Optional path to the directory with the simulation store for P-values.
+    #[arg(long)]
+    pub path_simulation_dir: Option<String>,
+    /// Number of threads to use, defaults to number of CPUs.
+    #[arg(long)]
+    pub num_threads: Option<usize>,
+
+    /// Configuration of the similarity computation.
+    #[command(flatten)]
+    pub scoring: ScoringArgs,
+    /// Limits on the reported genes.
+    #[command(flatten)]
+    pub limits: ResultLimits,
+}
+
+/// A case to score.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct Case {
+    /// The case identifier.
+    pub case_id: String,
+    /// The HPO term IDs of the case.
+    pub terms: Vec<String>,
+    /// The HPO term IDs explicitly excluded in the case.
+    #[serde(default = "Vec::default", skip_serializing_if = "Vec::is_empty")]
+    pub excluded_terms: Vec<String>,
+}
+
+/// Record in the TSV cases file.
+#[derive(Debug, serde::Deserialize)]
+struct TsvCase {
+    /// The case identifier.
+    case_id: String,
+    /// The comma-separated HPO term IDs of the case.
+    terms: String,
+    /// The optional comma-separated excluded HPO term IDs of the case.
+    #[serde(default)]
+    excluded_terms: String,
+}
+
+/// Result for one case.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct CaseResult {
+    /// The case identifier.
+    pub case_id: String,
+    /// The gene scores for the case.
+    pub result: HpoSimTermGeneResult,
+}
+
+/// Load the cases from `path`, see `Args::path_cases` for the supported formats.
+///
+/// # Errors
+///
+/// In the case that the file could not be read or parsed.
+pub fn load_cases<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<Case>, anyhow::Error> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|ext| ext == "tsv") {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(true)
+            .from_path(path)?;
+        let mut cases = Vec::new();
+        for result in rdr.deserialize() {
+            let TsvCase {
+                case_id,
+                terms,
+                excluded_terms,
+            } = result?;
+            let split = |terms: &str| {
+                terms
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|term| !term.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            };
+            cases.push(Case {
+                case_id,
+                terms: split(&terms),
+                excluded_terms: split(&excluded_terms),
+            });
+        }
+        Ok(cases)
+    } else {
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut cases = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                cases.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(cases)
+    }
+}
+
+/// Translate the observed and excluded terms of `case` into `HpoGroup`s, skipping
+/// terms that are not in the ontology.
+pub fn case_terms(case: &Case, hpo: &Ontology) -> (HpoGroup, HpoGroup) {
+    let to_hpo_group = |terms: &[String]| {
+        let mut group = HpoGroup::new();
+        for term in terms {
+            if let Some(term) = hpo.hpo(HpoTermId::from(term.clone())) {
+                group.insert(term.id());
+            } else {
+                tracing::warn!("case {}: skipping unknown term {}", &case.case_id, term);
+            }
+        }
+        group
+    };
+    (
+        to_hpo_group(&case.terms),
+        to_hpo_group(&case.excluded_terms),
+    )
+}
+
+/// Score one case with `run_query`, skipping terms that are not in the ontology.
+///
+/// # Errors
+///
+/// In the case that there is a problem with query execution.
+pub fn score_case<S>(
+    case: &Case,
+    genes: Option<&[&Gene]>,
+    hpo: &Ontology,
+    ncbi_to_hgnc: &HashMap<u32, String, S>,
+    scoring: ScoringArgs,
+    limits: ResultLimits,
+    simulation: Option<&Store>,
+) -> Result<CaseResult, anyhow::Error>
+where
+    S: std::hash::BuildHasher + Sync,
+{
+    let (patient, excluded) = case_terms(case, hpo);
+
+    Ok(CaseResult {
+        case_id: case.case_id.clone(),
+        result: run_query(
+            &patient,
+            &excluded,
+            genes,
+            hpo,
+            ncbi_to_hgnc,
+            scoring,
+            limits,
+            simulation,
+            ScoreWeighting::default(),
+        )?,
+    })
+}
+
+/// Main entry point for `batch` sub command.
+///
+/// # Errors
+///
+/// In the case of query execution or I/O failure.
+pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
+    tracing::info!("args_common = {:?}", &args_common);
+    tracing::info!("args = {:?}", &args);
+
+    if let Some(log::Level::Trace | log::Level::Debug) = args_common.verbose.log_level() {
+        std::env::set_var("RUST_LOG", "debug");
+        env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    }
+
+    if let Some(num_threads) = args.num_threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()?;
+    }
+
+    tracing::info!("Loading HPO...");
+    let before_loading = Instant::now();
+    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
+    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+
+    tracing::info!("Loading cases...");
+    let before_load_cases = Instant::now();
+    let cases = load_cases(&args.path_cases)?;
+    tracing::info!(
+        "... done loading {} cases in {:?}",
+        cases.len(),
+        before_load_cases.elapsed()
+    );
+
+    let genes = if let Some(path_genes_json) = &args.path_genes_json {
+        tracing::info!("Loading genes...");
+        let before_load_genes = Instant::now();
+        let genes_json = std::fs::read_to_string(path_genes_json)?;
+        let genes: Vec<crate::query::Gene> = serde_json::from_str(&genes_json)?;
+        let genes = genes
+            .iter()
+            .filter_map(|g| {
+                let mapped = hpo.gene_by_name(&g.gene_symbol);
+                if mapped.is_none() {
+                    tracing::warn!("gene {} not found in HPO annotations", &g.gene_symbol);
+                }
+                mapped
+            })
+            .collect::<Vec<_>>();
+        tracing::info!(
+            "... done loading genes in {:?}",
+            before_load_genes.elapsed()
+        );
+        Some(genes)
+    } else {
+        None
+    };
+
+    tracing::info!("Loading HGNC xlink...");
+    let before_load_xlink = Instant::now();
+    let path_hgnc_xlink = format!("{}/hgnc_xlink.tsv", args.path_hpo_dir);
+    let ncbi_to_hgnc = crate::common::hgnc_xlink::load_ncbi_to_hgnc(path_hgnc_xlink)?;
+    tracing::info!(
+        "... done loading HGNC xlink in {:?}",
+        before_load_xlink.elapsed()
+    );
+
+    let simulation = args
+        .path_simulation_dir
+        .as_ref()
+        .map(|path| load_store(path, &hpo))
+        .transpose()?;
+
+    tracing::info!("Scoring {} cases...", cases.len());
+    let before_scoring = Instant::now();
+    let output: Box<dyn std::io::Write + Send> = if let Some(path_output) = &args.path_output {
+        Box::new(std::fs::File::create(path_output)?)
+    } else {
+        Box::new(std::io::stdout())
+    };
+    // Results are written as they become available, so the order of the output
+    // does not necessarily match the input.
+    let output = Mutex::new(std::io::BufWriter::new(output));
+    cases
+        .par_iter()
+        .try_for_each(|case| -> Result<(), anyhow::Error> {
+            let result = score_case(
+                case,
+                genes.as_deref(),
+                &hpo,
+                &ncbi_to_hgnc,
+                args.scoring,
+                args.limits,
+                simulation.as_ref(),
+            )?;
+            let line = serde_json::to_string(&result)?;
+            let mut output = output
+                .lock()
+                .map_err(|e| anyhow::anyhow!("could not lock output: {}", e))?;
+            writeln!(output, "{line}")?;
+            Ok(())
+        })?;
+    output
+        .into_inner()
+        .map_err(|e| anyhow::anyhow!("could not unwrap output: {}", e))?
+        .flush()?;
+    tracing::info!("... done scoring in {:?}", before_scoring.elapsed());
+
+    tracing::info!("All done. Have a nice day!");
+    Ok(())
+}
@@ -0,0 +1,340 @@
+//! Phenotype-based hierarchical clustering of the cases in a cohort.
+
+use std::collections::HashMap;
+use std::io::Write as _;
+use std::time::Instant;
+
+use clap::Parser;
+use hpo::{term::HpoGroup, HpoTermId, Ontology};
+
+use crate::algos::{hclust, stats};
+use crate::batch::{case_terms, load_cases, Case};
+use crate::cohort::{similarity_matrix, SimilarityMatrix};
+use crate::query::ScoringArgs;
+
+/// Command line arguments for `cluster` sub command.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Cluster the cases of a cohort by phenotype", long_about = None)]
+pub struct Args {
+    /// Path to the directory with the HPO files.
+    #[arg(long, required = true)]
+    pub path_hpo_dir: String,
+
+    /// Path to the cases file, in the same formats as for `batch`.
+    #[arg(long, required = true)]
+    pub path_cases: String,
+    /// Prefix of the output files, writes `{prefix}.nwk` with the dendrogram,
+    /// `{prefix}.clusters.tsv` with the cluster assignments, and `{prefix}.terms.tsv`
+    /// with the enriched terms of each cluster.
+    #[arg(long, required = true)]
+    pub path_output_prefix: String,
+    /// The height at which to cut the dendrogram, the distance of two cases is one
+    /// minus their normalized similarity and thus in `[0, 1]`.
+    #[arg(long, default_value_t = 0.5)]
+    pub cut_height: f32,
+    /// The maximal number of enriched terms to report per cluster.
+    #[arg(long, default_value_t = 10)]
+    pub max_terms: usize,
+    /// Number of threads to use, defaults to number of CPUs.
+    #[arg(long)]
+    pub num_threads: Option<usize>,
+
+    /// Configuration of the similarity computation.
+    #[command(flatten)]
+    pub scoring: ScoringArgs,
+}
+
+/// Convert the similarity `matrix` into distances.
+///
+/// The similarity of two cases is normalized by the geometric mean of their
+/// self-similarities and the distance is one minus the normalized similarity,
+/// clamped to `[0, 1]`.  Cases without self-similarity have distance one to all
+/// other cases.
+pub fn distance_matrix(matrix: &SimilarityMatrix) -> Vec<Vec<f32>> {
+    let scores = &matrix.scores;
+    (0..scores.len())
+        .map(|i| {
+            (0..scores.len())
+                .map(|j| {
+                    if i == j {
+                        return 0f32;
+                    }
+                    let norm = (scores[i][i] * scores[j][j]).sqrt();
+                    if norm > 0f32 {
+                        (1f32 - scores[i][j] / norm).clamp(0f32, 1f32)
+                    } else {
+                        1f32
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// An HPO term that is over-represented in a cluster.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EnrichedTerm {
+    /// The 0-based cluster label.
+    pub cluster: usize,
+    /// The HPO term's ID.
+    pub term_id: String,
+    /// The HPO term's name.
+    pub name: String,
+    /// The number of cases in the cluster annotated with the term.
+    pub cluster_count: usize,
+    /// The number of cases in the cohort annotated with the term.
+    pub cohort_count: usize,
+    /// The P-value of the one-sided hypergeometric test.
+    pub p_value: f64,
+    /// The P-value adjusted for the terms tested in the cluster (Benjamini-Hochberg).
+    pub q_value: f64,
+}
+
+/// Compute the most enriched terms of each cluster given by `labels`.
+///
+/// A case is annotated with its observed terms and all their ancestors.  For each
+/// cluster, the terms of its cases are tested for over-representation compared to
+/// the whole cohort and the (at most) `max_terms` terms with the smallest P-values
+/// are returned, by cluster and P-value.
+pub fn enriched_terms(
+    cases: &[Case],
+    labels: &[usize],
+    hpo: &Ontology,
+    max_terms: usize,
+) -> Vec<EnrichedTerm> {
+    let case_groups = cases
+        .iter()
+        .map(|case| {
+            let mut group = HpoGroup::new();
+            for term in case_terms(case, hpo).0.iter().filter_map(|id| hpo.hpo(id)) {
+                group.insert(term.id());
+                for parent_id in term.all_parent_ids() {
+                    group.insert(parent_id);
+                }
+            }
+            group
+        })
+        .collect::<Vec<_>>();
+
+    let mut cohort_counts: HashMap<HpoTermId, usize> = HashMap::new();
+    for group in &case_groups {
+        for term_id in group {
+            *cohort_counts.entry(term_id).or_default() += 1;
+        }
+    }
+
+    let num_clusters = labels.iter().max().map_or(0, |max| max + 1);
+    let ln_factorials = stats::LnFactorials::new(cases.len() as u64);
+    let mut result = Vec::new();
+    for cluster in 0..num_clusters {
+        let members = labels
+            .iter()
+            .enumerate()
+            .filter(|(_, label)| **label == cluster)
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        let mut cluster_counts: HashMap<HpoTermId, usize> = HashMap::new();
+        for idx in &members {
+            for term_id in &case_groups[*idx] {
+                *cluster_counts.entry(term_id).or_default() += 1;
+            }
+        }
+
+        let mut entries = cluster_counts
+            .into_iter()
+            .map(|(term_id, cluster_count)| {
+                let cohort_count = cohort_counts[&term_id];
+                let p_value = ln_factorials.hypergeometric_upper_tail(
+                    cluster_count as u64,
+                    members.len() as u64,
+                    cohort_count as u64,
+                    cases.len() as u64,
+                );
+                (term_id, cluster_count, cohort_count, p_value)
+            })
+            .collect::<Vec<_>>();
+        let q_values = stats::benjamini_hochberg(&entries.iter().map(|e| e.3).collect::<Vec<_>>());
+
+        let mut cluster_result = entries
+            .drain(..)
+            .zip(q_values)
+            .map(
+                |((term_id, cluster_count, cohort_count, p_value), q_value)| EnrichedTerm {
+                    cluster,
+                    term_id: term_id.to_string(),
+                    name: hpo
+                        .hpo(term_id)
+                        .map(|term| term.name().to_string())
+                        .unwrap_or_default(),
+                    cluster_count,
+                    cohort_count,
+                    p_value,
+                    q_value,
+                },
+            )
+            .collect::<Vec<_>>();
+        cluster_result.sort_by(|a, b| {
+            a.p_value
+                .total_cmp(&b.p_value)
+                .then_with(|| b.cluster_count.cmp(&a.cluster_count))
+                .then_with(|| a.term_id.cmp(&b.term_id))
+        });
+        cluster_result.truncate(max_terms);
+        result.append(&mut cluster_result);
+    }
+
+    result
+}
+
+/// Main entry point for `cluster` sub command.
+///
+/// # Errors
+///
+/// In the case of query execution or I/O failure.
+pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
+    tracing::info!("args_common = {:?}", &args_common);
+    tracing::info!("args = {:?}", &args);
+
+    if let Some(num_threads) = args.num_threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()?;
+    }
+
+    tracing::info!("Loading HPO...");
+    let before_loading = Instant::now();
+    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
+    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+
+    tracing::info!("Loading cases...");
+    let before_load_cases = Instant::now();
+    let cases = load_cases(&args.path_cases)?;
+    tracing::info!(
+        "... done loading {} cases in {:?}",
+        cases.len(),
+        before_load_cases.elapsed()
+    );
+
+    tracing::info!("Clustering cases...");
+    let before_clustering = Instant::now();
+    let matrix = similarity_matrix(&cases, &hpo, args.scoring);
+    let merges = hclust::average_linkage(&distance_matrix(&matrix));
+    let labels = hclust::cut(&merges, cases.len(), args.cut_height);
+    let terms = enriched_terms(&cases, &labels, &hpo, args.max_terms);
+    tracing::info!(
+        "... done clustering into {} clusters in {:?}",
+        labels.iter().max().map_or(0, |max| max + 1),
+        before_clustering.elapsed()
+    );
+
+    let create = |suffix: &str| -> Result<std::io::BufWriter<std::fs::File>, anyhow::Error> {
+        let path = format!("{}{}", &args.path_output_prefix, suffix);
+        Ok(std::io::BufWriter::new(
+            std::fs::File::create(&path)
+                .map_err(|e| anyhow::anyhow!("could not create {}: {}", path, e))?,
+        ))
+    };
+
+    let mut writer = create(".nwk")?;
+    writeln!(writer, "{}", hclust::to_newick(&merges, &matrix.case_ids))?;
+    writer.flush()?;
+
+    let mut writer = create(".clusters.tsv")?;
+    writeln!(writer, "case_id\tcluster")?;
+    for (case_id, label) in matrix.case_ids.iter().zip(labels.iter()) {
+        writeln!(writer, "{case_id}\t{label}")?;
+    }
+    writer.flush()?;
+
+    let mut writer = create(".terms.tsv")?;
+    writeln!(
+        writer,
+        "cluster\tterm_id\tname\tcluster_count\tcohort_count\tp_value\tq_value"
+    )?;
+    for term in &terms {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            term.cluster,
+            term.term_id,
+            term.name,
+            term.cluster_count,
+            term.cohort_count,
+            term.p_value,
+            term.q_value
+        )?;
+    }
+    writer.flush()?;
+
+    tracing::info!("All done. Have a nice day!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::batch::Case;
+    use crate::cohort::SimilarityMatrix;
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    #[test]
+    fn distance_matrix() {
+        let matrix = SimilarityMatrix {
+            case_ids: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            scores: vec![
+                vec![4.0, 1.0, 0.0],
+                vec![1.0, 1.0, 0.0],
+                vec![0.0, 0.0, 0.0],
+            ],
+        };
+        assert_eq!(
+            super::distance_matrix(&matrix),
+            vec![
+                vec![0.0, 0.5, 1.0],
+                vec![0.5, 0.0, 1.0],
+                vec![1.0, 1.0, 0.0],
+            ]
+        );
+    }
+
+    #[rstest::rstest]
+    fn enriched_terms(web_server_data: &Arc<crate::server::run::WebServerData>) {
+        let case = |case_id: &str, terms: &[&str]| Case {
+            case_id: case_id.to_string(),
+            terms: terms.iter().map(|term| (*term).to_string()).collect(),
+            excluded_terms: Vec::new(),
+        };
+        // Two cases with polydactyly and two cases with micrognathia.
+        let cases = [
+            case("a-1", &["HP:0001162"]),
+            case("a-2", &["HP:0001162", "HP:0010442"]),
+            case("b-1", &["HP:0000347"]),
+            case("b-2", &["HP:0000347"]),
+        ];
+        let labels = [0, 0, 1, 1];
+
+        let hpo = &web_server_data.ontology;
+
+        let terms = super::enriched_terms(&cases, &labels, hpo, 1);
+        assert_eq!(
+            terms.iter().map(|term| term.cluster).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+
+        let terms = super::enriched_terms(&cases, &labels, hpo, usize::MAX);
+        assert!(terms
+            .windows(2)
+            .all(|pair| pair[0].cluster < pair[1].cluster
+                || (pair[0].cluster == pair[1].cluster && pair[0].p_value <= pair[1].p_value)));
+        for (cluster, term_id) in [(0, "HP:0001162"), (1, "HP:0000347")] {
+            let term = terms
+                .iter()
+                .find(|term| term.cluster == cluster && term.term_id == term_id)
+                .expect("term must be enriched");
+            assert_eq!((term.cluster_count, term.cohort_count), (2, 2));
+            // P(X >= 2) when drawing 2 of 4 cases with 2 successes.
+            assert!((term.p_value - 1.0 / 6.0).abs() < 1e-9, "{term:?}");
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Pairwise phenotypic similarity of the cases in a cohort.
+
+use std::str::FromStr;
+use std::time::Instant;
+
+use clap::Parser;
+use hpo::{similarity::Builtins, term::HpoGroup, Ontology};
+use rayon::prelude::*;
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::algos::phenomizer;
+use crate::batch::{case_terms, load_cases, Case};
+use crate::common::to_pairwise_sim;
+use crate::query::ScoringArgs;
+
+/// Command line arguments for `cohort` sub command.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Compute the similarity matrix of a cohort", long_about = None)]
+pub struct Args {
+    /// Path to the directory with the HPO files.
+    #[arg(long, required = true)]
+    pub path_hpo_dir: String,
+
+    /// Path to the cases file, in the same formats as for `batch`.
+    #[arg(long, required = true)]
+    pub path_cases: String,
+    /// Path to the output file, defaults to stdout.
+    #[arg(long)]
+    pub path_output: Option<String>,
+    /// The output format.
+    #[arg(long, default_value_t = MatrixFormat::default())]
+    pub output_format: MatrixFormat,
+    /// Number of threads to use, defaults to number of CPUs.
+    #[arg(long)]
+    pub num_threads: Option<usize>,
+
+    /// Configuration of the similarity computation.
+    #[command(flatten)]
+    pub scoring: ScoringArgs,
+}
+
+/// The output format of the similarity matrix.
+#[derive(Default, Debug, Clone, Copy, EnumIter, PartialEq, Eq, derive_more::Display)]
+pub enum MatrixFormat {
+    /// TSV with a header row and the case IDs in the first column.
+    #[default]
+    #[display("tsv")]
+    Tsv,
+    /// The `SimilarityMatrix` as JSON.
+    #[display("json")]
+    Json,
+    /// Numpy `.npy` file with the `f32` scores in the order of the cases file.
+    #[display("npy")]
+    Npy,
+}
+
+impl FromStr for MatrixFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MatrixFormat::iter()
+            .find(|m| m.to_string().as_str().eq(s))
+            .ok_or(anyhow::anyhow!("unknown matrix format: {}", s))
+    }
+}
+
+/// The pairwise similarities of the cases of a cohort.
+///
+/// The entry `scores[i][j]` is the similarity of the cases `case_ids[i]` and
+/// `case_ids[j]`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct SimilarityMatrix {
+    /// The case identifiers, one per row and column.
+    pub case_ids: Vec<String>,
+    /// The similarity scores, one row per case.
+    pub scores: Vec<Vec<f32>>,
+}
+
+/// Compute the similarity matrix of `cases`.
+///
+/// Only the observed terms are used, excluded terms are ignored.  As all score
+/// combiners are symmetric, only the upper triangle is computed, in parallel.
+pub fn similarity_matrix(cases: &[Case], hpo: &Ontology, scoring: ScoringArgs) -> SimilarityMatrix {
+    let sim: Builtins = to_pairwise_sim(scoring.similarity, scoring.ic_base);
+    let groups = cases
+        .iter()
+        .map(|case| case_terms(case, hpo).0)
+        .collect::<Vec<HpoGroup>>();
+
+    let n = cases.len();
+    let upper = (0..n)
+        .into_par_iter()
+        .flat_map_iter(|i| (i..n).map(move |j| (i, j)))
+        .map(|(i, j)| {
+            let score = phenomizer::score(&groups[i], &groups[j], hpo, &sim, scoring.combiner);
+            (i, j, score)
+        })
+        .collect::<Vec<_>>();
+
+    let mut scores = vec![vec![0f32; n]; n];
+    for (i, j, score) in upper {
+        scores[i][j] = score;
+        scores[j][i] = score;
+    }
+
+    SimilarityMatrix {
+        case_ids: cases.iter().map(|case| case.case_id.clone()).collect(),
+        scores,
+    }
+}
+
+/// Write `matrix` to `writer` in the given `format`.
+///
+/// # Errors
+///
+/// In the case that writing fails.
+pub fn write_matrix<W: std::io::Write>(
+    matrix: &SimilarityMatrix,
+    format: MatrixFormat,
+    mut writer: W,
+) -> Result<(), anyhow::Error> {
+    match format {
+        MatrixFormat::Tsv => {
+            write!(writer, "case_id")?;
+            for case_id in &matrix.case_ids {
+                write!(writer, "\t{case_id}")?;
+            }
+            writeln!(writer)?;
+            for (case_id, row) in matrix.case_ids.iter().zip(matrix.scores.iter()) {
+                write!(writer, "{case_id}")?;
+                for score in row {
+                    write!(writer, "\t{score}")?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        MatrixFormat::Json => {
+            serde_json::to_writer(&mut writer, matrix)?;
+            writeln!(writer)?;
+        }
+        MatrixFormat::Npy => {
+            // Format version 1.0, see the `numpy.lib.format` documentation.
+            let n = matrix.case_ids.len();
+            let mut header =
+                format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({n}, {n}), }}");
+            // Pad with spaces so that magic string, version, header length, and header
+            // (terminated by a newline) are a multiple of 64 bytes.
+            let preamble_len = 6 + 2 + 2;
+            while (preamble_len + header.len() + 1) % 64 != 0 {
+                header.push(' ');
+            }
+            header.push('\n');
+            let header_len = u16::try_from(header.len())
+                .map_err(|e| anyhow::anyhow!("npy header too long: {}", e))?;
+
+            writer.write_all(b"\x93NUMPY\x01\x00")?;
+            writer.write_all(&header_len.to_le_bytes())?;
+            writer.write_all(header.as_bytes())?;
+            for score in matrix.scores.iter().flatten() {
+                writer.write_all(&score.to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Main entry point for `cohort` sub command.
+///
+/// # Errors
+///
+/// In the case of query execution or I/O failure.
+pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
+    tracing::info!("args_common = {:?}", &args_common);
+    tracing::info!("args = {:?}", &args);
+
+    if let Some(num_threads) = args.num_threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()?;
+    }
+
+    tracing::info!("Loading HPO...");
+    let before_loading = Instant::now();
+    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
+    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+
+    tracing::info!("Loading cases...");
+    let before_load_cases = Instant::now();
+    let cases = load_cases(&args.path_cases)?;
+    tracing::info!(
+        "... done loading {} cases in {:?}",
+        cases.len(),
+        before_load_cases.elapsed()
+    );
+
+    tracing::info!("Computing similarity matrix...");
+    let before_matrix = Instant::now();
+    let matrix = similarity_matrix(&cases, &hpo, args.scoring);
+    tracing::info!(
+        "... done computing similarity matrix in {:?}",
+        before_matrix.elapsed()
+    );
+
+    let output: Box<dyn std::io::Write> = if let Some(path_output) = &args.path_output {
+        Box::new(std::io::BufWriter::new(std::fs::File::create(path_output)?))
+    } else {
+        Box::new(std::io::stdout())
+    };
+    write_matrix(&matrix, args.output_format, output)?;
+
+    tracing::info!("All done. Have a nice day!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MatrixFormat, SimilarityMatrix};
+
+    fn matrix() -> SimilarityMatrix {
+        SimilarityMatrix {
+            case_ids: vec!["case-1".to_string(), "case-2".to_string()],
+            scores: vec![vec![2.0, 0.5], vec![0.5, 1.25]],
+        }
+    }
+
+    #[test]
+    fn write_matrix_tsv() -> Result<(), anyhow::Error> {
+        let mut buf = Vec::new();
+        super::write_matrix(&matrix(), MatrixFormat::Tsv, &mut buf)?;
+
+        insta::assert_snapshot!(String::from_utf8(buf)?);
+        Ok(())
+    }
+
+    #[test]
+    fn write_matrix_npy() -> Result<(), anyhow::Error> {
+        let mut buf = Vec::new();
+        super::write_matrix(&matrix(), MatrixFormat::Npy, &mut buf)?;
+
+        assert_eq!(&buf[..8], b"\x93NUMPY\x01\x00");
+        let header_len = usize::from(u16::from_le_bytes([buf[8], buf[9]]));
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&buf[10..10 + header_len])?;
+        assert_eq!(
+            header.trim_end(),
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 2), }"
+        );
+        let data = buf[10 + header_len..]
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect::<Vec<_>>();
+        assert_eq!(data, vec![2.0, 0.5, 0.5, 1.25]);
+        Ok(())
+    }
+}
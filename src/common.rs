@@ -71,6 +71,9 @@ pub enum IcBasedOn {
     /// Compute information content based on OMIM disease.
     #[display("omim")]
     Omim,
+    /// Compute information content based on ORPHA disease.
+    #[display("orpha")]
+    Orpha,
 }
 
 impl FromStr for IcBasedOn {
@@ -131,12 +134,24 @@ pub enum SimilarityMethod {
     Resnik,
 }
 
-/// Convert to pairwise similarity.
-pub fn to_pairwise_sim(sim: SimilarityMethod, ic_based_on: IcBasedOn) -> Builtins {
-    let kind = match ic_based_on {
+/// Helper for skipping serialization of `false` flags.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Convert to the information content kind of the `hpo` crate.
+pub fn to_ic_kind(ic_based_on: IcBasedOn) -> InformationContentKind {
+    match ic_based_on {
         IcBasedOn::Gene => InformationContentKind::Gene,
         IcBasedOn::Omim => InformationContentKind::Omim,
-    };
+        IcBasedOn::Orpha => InformationContentKind::Orpha,
+    }
+}
+
+/// Convert to pairwise similarity.
+pub fn to_pairwise_sim(sim: SimilarityMethod, ic_based_on: IcBasedOn) -> Builtins {
+    let kind = to_ic_kind(ic_based_on);
     match sim {
         SimilarityMethod::DistanceGene => Builtins::Distance(kind),
         SimilarityMethod::GraphIc => Builtins::GraphIc(kind),
@@ -212,6 +227,55 @@ impl FromStr for ScoreCombiner {
     }
 }
 
+/// Enum for representing the correction of P-values for multiple testing.
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    derive_more::Display,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum PValueCorrection {
+    /// Bonferroni correction of the family-wise error rate.
+    #[display("bonferroni")]
+    Bonferroni,
+    /// Benjamini-Hochberg correction of the false discovery rate.
+    #[default]
+    #[display("benjamini-hochberg")]
+    BenjaminiHochberg,
+}
+
+impl PValueCorrection {
+    /// Adjust `p_values` for multiple testing, in the order of `p_values`.
+    pub fn adjust(self, p_values: &[f64]) -> Vec<f64> {
+        match self {
+            PValueCorrection::Bonferroni => crate::algos::stats::bonferroni(p_values),
+            PValueCorrection::BenjaminiHochberg => {
+                crate::algos::stats::benjamini_hochberg(p_values)
+            }
+        }
+    }
+}
+
+impl FromStr for PValueCorrection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PValueCorrection::iter()
+            .find(|m| m.to_string().as_str().eq(s))
+            .ok_or(anyhow::anyhow!("unknown P-value correction: {}", s))
+    }
+}
+
 /// The version of `viguno` package.
 #[cfg(not(test))]
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -264,6 +328,32 @@ pub mod hgnc_xlink {
         pub ncgi_gene_id: Option<u32>,
         /// Gene symbol.
         pub gene_symbol: String,
+        /// Gene name, optional column.
+        #[serde(default)]
+        pub name: Option<String>,
+        /// Alias symbols separated by `|`, optional column.
+        #[serde(default)]
+        pub alias_symbol: Option<String>,
+        /// Previous symbols separated by `|`, optional column.
+        #[serde(default)]
+        pub prev_symbol: Option<String>,
+    }
+
+    /// The symbols and name of a gene.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub struct GeneNames {
+        /// NCBI gene ID.
+        pub ncbi_gene_id: u32,
+        /// HGNC gene ID.
+        pub hgnc_id: String,
+        /// Current gene symbol.
+        pub symbol: String,
+        /// Gene name.
+        pub name: Option<String>,
+        /// Alias symbols.
+        pub alias_symbols: Vec<String>,
+        /// Previous symbols.
+        pub prev_symbols: Vec<String>,
     }
 
     /// Read the `hgnc_xlink.tsv` file using the `csv` crate via serde.
@@ -301,6 +391,43 @@ pub mod hgnc_xlink {
         Ok(map)
     }
 
+    /// Split the `|`-separated symbols in `value`.
+    fn split_symbols(value: Option<&str>) -> Vec<String> {
+        value
+            .unwrap_or_default()
+            .split('|')
+            .map(str::trim)
+            .filter(|symbol| !symbol.is_empty())
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Read the names of the genes with NCBI gene ID from `hgnc_xlink.tsv`, sorted by
+    /// NCBI gene ID.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the file could not be read.
+    pub fn load_gene_names<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Vec<GeneNames>, anyhow::Error> {
+        let mut result = load_entries(&path)?
+            .into_iter()
+            .filter_map(|entry| {
+                Some(GeneNames {
+                    ncbi_gene_id: entry.ncgi_gene_id?,
+                    alias_symbols: split_symbols(entry.alias_symbol.as_deref()),
+                    prev_symbols: split_symbols(entry.prev_symbol.as_deref()),
+                    hgnc_id: entry.hgnc_id,
+                    symbol: entry.gene_symbol,
+                    name: entry.name.filter(|name| !name.is_empty()),
+                })
+            })
+            .collect::<Vec<_>>();
+        result.sort_by_key(|gene| gene.ncbi_gene_id);
+        Ok(result)
+    }
+
     /// Uility function to make the inverse of a `HashMap`.
     pub fn inverse_hashmap<K, V, S>(map: &HashMap<K, V, S>) -> HashMap<V, K, S>
     where
@@ -310,4 +437,512 @@ pub mod hgnc_xlink {
     {
         map.iter().map(|(k, v)| (v.clone(), k.clone())).collect()
     }
+
+    #[cfg(test)]
+    mod test {
+        #[rstest::rstest]
+        #[case::with_names(
+            "with_names",
+            "hgnc_id\tensembl_gene_id\tentrez_id\tgene_symbol\tname\talias_symbol\tprev_symbol\n\
+             HGNC:12403\tENSG00000155657\t7273\tTTN\ttitin\tCMH9|CMD1G|TMD\t\n\
+             HGNC:28191\tENSG00000173715\t79703\tTOP6BL\tTOP6B like initiator of meiotic double strand breaks\tMGC13125\tC11orf80\n"
+        )]
+        #[case::without_names(
+            "without_names",
+            "hgnc_id\tensembl_gene_id\tentrez_id\tgene_symbol\n\
+             HGNC:12403\tENSG00000155657\t7273\tTTN\n\
+             HGNC:28191\tENSG00000173715\t79703\tTOP6BL\n"
+        )]
+        fn load_gene_names(
+            #[case] suffix: &str,
+            #[case] contents: &str,
+        ) -> Result<(), anyhow::Error> {
+            let tmpdir = tempdir::TempDir::new("viguno")?;
+            let path = tmpdir.path().join("hgnc_xlink.tsv");
+            std::fs::write(&path, contents)?;
+
+            let result = super::load_gene_names(&path)?;
+            insta::with_settings!({snapshot_suffix => suffix}, {
+                insta::assert_yaml_snapshot!(result);
+            });
+            Ok(())
+        }
+    }
+}
+
+/// Code related to the `genes_to_phenotype.txt` file.
+pub mod genes_to_phenotype {
+    use std::collections::HashMap;
+
+    /// Data structure for representing an entry of the table.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Entry {
+        /// NCBI gene ID.
+        pub ncbi_gene_id: u32,
+        /// Gene symbol.
+        pub gene_symbol: String,
+        /// HPO term ID.
+        pub hpo_id: String,
+        /// HPO term name.
+        pub hpo_name: String,
+        /// Frequency of the term in the disease.
+        pub frequency: String,
+        /// Disease ID, e.g., `OMIM:616145` or `ORPHA:1234`.
+        pub disease_id: String,
+    }
+
+    /// Read the `genes_to_phenotype.txt` file using the `csv` crate via serde.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the file could not be read.
+    pub fn load_entries<P: AsRef<std::path::Path>>(path: &P) -> Result<Vec<Entry>, anyhow::Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(true)
+            .from_path(path.as_ref())?;
+        let mut entries = Vec::new();
+        for result in rdr.deserialize() {
+            let entry: Entry = result?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Read the `genes_to_phenotype.txt` into a map from disease ID to sorted NCBI gene IDs.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the file could not be read.
+    pub fn load_disease_to_genes<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<HashMap<String, Vec<u32>>, anyhow::Error> {
+        let mut map: HashMap<String, Vec<u32>> = HashMap::new();
+        for entry in load_entries(&path)? {
+            map.entry(entry.disease_id)
+                .or_default()
+                .push(entry.ncbi_gene_id);
+        }
+        for ncbi_gene_ids in map.values_mut() {
+            ncbi_gene_ids.sort_unstable();
+            ncbi_gene_ids.dedup();
+        }
+        Ok(map)
+    }
+}
+
+/// Code related to the `phenotype.hpoa` file.
+pub mod phenotype_hpoa {
+    use std::collections::{BTreeMap, HashMap};
+    use std::str::FromStr;
+
+    use strum::{EnumIter, IntoEnumIterator};
+
+    /// Data structure for representing an entry of the table.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Entry {
+        /// Disease ID, e.g., `OMIM:616145`, `ORPHA:1234`, or `DECIPHER:1`.
+        pub database_id: String,
+        /// Disease name.
+        pub disease_name: String,
+        /// `NOT` if the term is explicitly excluded in the disease.
+        pub qualifier: String,
+        /// HPO term ID.
+        pub hpo_id: String,
+        /// Source of the annotation, e.g., `PMID:1234`.
+        pub reference: String,
+        /// Evidence code, one of `IEA`, `PCS`, and `TAS`.
+        pub evidence: String,
+        /// Onset of the term in the disease as an HPO term ID.
+        pub onset: String,
+        /// Frequency of the term in the disease.
+        pub frequency: String,
+        /// Sex specificity of the annotation.
+        pub sex: String,
+        /// Modifier terms of the annotation.
+        pub modifier: String,
+        /// Sub ontology of the term, e.g., `P` for phenotypic abnormality.
+        pub aspect: String,
+        /// Biocuration information.
+        pub biocuration: String,
+    }
+
+    /// Evidence code of an annotation.
+    #[derive(
+        Debug,
+        Clone,
+        Copy,
+        EnumIter,
+        PartialEq,
+        Eq,
+        derive_more::Display,
+        serde::Serialize,
+        serde::Deserialize,
+    )]
+    #[serde(rename_all = "UPPERCASE")]
+    pub enum Evidence {
+        /// Inferred from electronic annotation.
+        #[display("IEA")]
+        Iea,
+        /// Published clinical study.
+        #[display("PCS")]
+        Pcs,
+        /// Traceable author statement.
+        #[display("TAS")]
+        Tas,
+    }
+
+    impl FromStr for Evidence {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Evidence::iter()
+                .find(|m| m.to_string().as_str().eq(s))
+                .ok_or(anyhow::anyhow!("unknown evidence code: {}", s))
+        }
+    }
+
+    /// Sex specificity of an annotation.
+    #[derive(
+        Debug,
+        Clone,
+        Copy,
+        EnumIter,
+        PartialEq,
+        Eq,
+        derive_more::Display,
+        serde::Serialize,
+        serde::Deserialize,
+    )]
+    #[serde(rename_all = "UPPERCASE")]
+    pub enum Sex {
+        /// Only males are affected.
+        #[display("MALE")]
+        Male,
+        /// Only females are affected.
+        #[display("FEMALE")]
+        Female,
+    }
+
+    impl FromStr for Sex {
+        type Err = anyhow::Error;
+
+        /// Parse case-insensitively, e.g., `female` as `Sex::Female`.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Sex::iter()
+                .find(|m| m.to_string().eq_ignore_ascii_case(s))
+                .ok_or(anyhow::anyhow!("unknown sex: {}", s))
+        }
+    }
+
+    /// Sub ontology of an annotated term.
+    #[derive(
+        Debug,
+        Clone,
+        Copy,
+        EnumIter,
+        PartialEq,
+        Eq,
+        derive_more::Display,
+        serde::Serialize,
+        serde::Deserialize,
+    )]
+    pub enum Aspect {
+        /// Phenotypic abnormality.
+        #[serde(rename = "P")]
+        #[display("P")]
+        Phenotype,
+        /// Mode of inheritance.
+        #[serde(rename = "I")]
+        #[display("I")]
+        Inheritance,
+        /// Clinical course, e.g., onset.
+        #[serde(rename = "C")]
+        #[display("C")]
+        ClinicalCourse,
+        /// Clinical modifier.
+        #[serde(rename = "M")]
+        #[display("M")]
+        ClinicalModifier,
+        /// Past medical history.
+        #[serde(rename = "H")]
+        #[display("H")]
+        PastMedicalHistory,
+    }
+
+    impl FromStr for Aspect {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Aspect::iter()
+                .find(|m| m.to_string().as_str().eq(s))
+                .ok_or(anyhow::anyhow!("unknown aspect: {}", s))
+        }
+    }
+
+    /// The association of a disease with an HPO term, as given in one row of the
+    /// `phenotype.hpoa` file.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub struct Annotation {
+        /// HPO term ID.
+        pub hpo_id: String,
+        /// Whether the term is explicitly excluded in the disease, i.e., qualified
+        /// with `NOT`.
+        #[serde(default, skip_serializing_if = "crate::common::is_false")]
+        pub negated: bool,
+        /// Evidence code.
+        pub evidence: Evidence,
+        /// Onset of the term in the disease as an HPO term ID.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub onset: Option<String>,
+        /// Frequency of the term in the disease, see `parse_frequency`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub frequency: Option<String>,
+        /// Sex specificity of the annotation.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub sex: Option<Sex>,
+        /// Modifier terms of the annotation.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub modifiers: Vec<String>,
+        /// Sub ontology of the term.
+        pub aspect: Aspect,
+        /// Sources of the annotation, e.g., `PMID:1234`.
+        pub references: Vec<String>,
+    }
+
+    impl Annotation {
+        /// Construct from the `entry` of the `phenotype.hpoa` file.
+        ///
+        /// # Errors
+        ///
+        /// In the case that the evidence, sex, or aspect is unknown.
+        pub fn from_entry(entry: &Entry) -> Result<Self, anyhow::Error> {
+            let non_empty = |value: &str| {
+                let value = value.trim();
+                (!value.is_empty()).then(|| value.to_string())
+            };
+            let split = |value: &str| {
+                value
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            };
+            Ok(Self {
+                hpo_id: entry.hpo_id.clone(),
+                negated: entry.qualifier == "NOT",
+                evidence: entry.evidence.trim().parse()?,
+                onset: non_empty(&entry.onset),
+                frequency: non_empty(&entry.frequency),
+                sex: non_empty(&entry.sex).map(|sex| sex.parse()).transpose()?,
+                modifiers: split(&entry.modifier),
+                aspect: entry.aspect.trim().parse()?,
+                references: split(&entry.reference),
+            })
+        }
+    }
+
+    /// A disease with its names and HPO terms, of any namespace.
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    pub struct Disease {
+        /// Disease ID, e.g., `OMIM:616145`, `ORPHA:1388`, or `DECIPHER:1`.
+        pub disease_id: String,
+        /// The preferred disease name.
+        pub name: String,
+        /// Alternative titles of the disease.
+        pub alt_titles: Vec<String>,
+        /// The sorted IDs of the HPO terms annotated to the disease, without the ones
+        /// qualified with `NOT`.
+        pub hpo_terms: Vec<String>,
+        /// The frequencies of the HPO terms in the disease, for the terms with a known
+        /// frequency, see `parse_frequency`.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub frequencies: BTreeMap<String, f32>,
+        /// The annotations of the disease in the order of the file, including the ones
+        /// qualified with `NOT`.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub annotations: Vec<Annotation>,
+    }
+
+    impl Disease {
+        /// The namespace of the disease ID, e.g., `OMIM`.
+        pub fn source(&self) -> &str {
+            self.disease_id
+                .split_once(':')
+                .map_or("", |(source, _)| source)
+        }
+    }
+
+    /// Parse the `frequency` column of `phenotype.hpoa` into a relative frequency.
+    ///
+    /// The frequency terms below `HP:0040279` are mapped to the middle of their range,
+    /// e.g., "Frequent (30-79%)" to `0.545`, and fractions `n/m` and percentages `x%`
+    /// are mapped to their value.  Returns `None` for empty or unknown values.
+    pub fn parse_frequency(frequency: &str) -> Option<f32> {
+        match frequency.trim() {
+            "" => None,
+            // Obligate (100%).
+            "HP:0040280" => Some(1.0),
+            // Very frequent (80-99%).
+            "HP:0040281" => Some(0.895),
+            // Frequent (30-79%).
+            "HP:0040282" => Some(0.545),
+            // Occasional (5-29%).
+            "HP:0040283" => Some(0.17),
+            // Very rare (1-4%).
+            "HP:0040284" => Some(0.025),
+            // Excluded (0%).
+            "HP:0040285" => Some(0.0),
+            frequency => {
+                if let Some((n, m)) = frequency.split_once('/') {
+                    let n = n.trim().parse::<f32>().ok()?;
+                    let m = m.trim().parse::<f32>().ok()?;
+                    (m > 0f32).then(|| (n / m).clamp(0f32, 1f32))
+                } else if let Some(percent) = frequency.strip_suffix('%') {
+                    let percent = percent.trim().parse::<f32>().ok()?;
+                    Some((percent / 100f32).clamp(0f32, 1f32))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Read the `phenotype.hpoa` file using the `csv` crate via serde.
+    ///
+    /// Leading comment lines starting with `#` are skipped.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the file could not be read.
+    pub fn load_entries<P: AsRef<std::path::Path>>(path: &P) -> Result<Vec<Entry>, anyhow::Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(true)
+            .comment(Some(b'#'))
+            .quoting(false)
+            .from_path(path.as_ref())?;
+        let mut entries = Vec::new();
+        for result in rdr.deserialize() {
+            let entry: Entry = result?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Read the diseases of all namespaces from the `phenotype.hpoa` file, sorted by
+    /// disease ID.
+    ///
+    /// Names are split at `;` and the first name seen for a disease is the preferred
+    /// one, all other distinct names are alternative titles.  If a term is annotated
+    /// several times with a known frequency, the highest one is retained.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the file could not be read or has unknown values.
+    pub fn load_diseases<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Vec<Disease>, anyhow::Error> {
+        // Collect all names in `alt_titles` first and pick the preferred one below.
+        let mut map: HashMap<String, Disease> = HashMap::new();
+        for entry in load_entries(&path)? {
+            let disease = map
+                .entry(entry.database_id.clone())
+                .or_insert_with(|| Disease {
+                    disease_id: entry.database_id.clone(),
+                    name: String::new(),
+                    alt_titles: Vec::new(),
+                    hpo_terms: Vec::new(),
+                    frequencies: BTreeMap::new(),
+                    annotations: Vec::new(),
+                });
+            disease.annotations.push(Annotation::from_entry(&entry)?);
+            for name in entry.disease_name.split(';').map(str::trim) {
+                if !name.is_empty() && !disease.alt_titles.iter().any(|other| other == name) {
+                    disease.alt_titles.push(name.to_string());
+                }
+            }
+            if entry.qualifier != "NOT" {
+                if let Some(frequency) = parse_frequency(&entry.frequency) {
+                    let value = disease
+                        .frequencies
+                        .entry(entry.hpo_id.clone())
+                        .or_insert(frequency);
+                    *value = value.max(frequency);
+                }
+                disease.hpo_terms.push(entry.hpo_id);
+            }
+        }
+
+        let mut result = map
+            .into_values()
+            .filter_map(|mut disease| {
+                if disease.alt_titles.is_empty() {
+                    return None;
+                }
+                disease.name = disease.alt_titles.remove(0);
+                disease.hpo_terms.sort();
+                disease.hpo_terms.dedup();
+                Some(disease)
+            })
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| a.disease_id.cmp(&b.disease_id));
+        Ok(result)
+    }
+
+    #[cfg(test)]
+    mod test {
+        #[test]
+        fn load_diseases() -> Result<(), anyhow::Error> {
+            let tmpdir = tempdir::TempDir::new("viguno")?;
+            let path = tmpdir.path().join("phenotype.hpoa");
+            std::fs::write(
+                &path,
+                "#description: \"HPO annotations for rare diseases\"\n\
+                 #version: 2024-08-13\n\
+                 database_id\tdisease_name\tqualifier\thpo_id\treference\tevidence\tonset\tfrequency\tsex\tmodifier\taspect\tbiocuration\n\
+                 OMIM:616145\tCatel-Manzke syndrome\t\tHP:0000347\tOMIM:616145\tTAS\t\t\t\t\tP\tHPO:probinson[2015-05-01]\n\
+                 OMIM:616145\tCatel-Manzke syndrome; Hyperphalangy-clinodactyly of index finger with Pierre Robin syndrome\t\tHP:0010442\tOMIM:616145\tTAS\t\t\t\t\tP\tHPO:probinson[2015-05-01]\n\
+                 ORPHA:1388\tCatel-Manzke syndrome\t\tHP:0000347\tORPHA:1388\tTAS\t\tHP:0040281\t\t\tP\tORPHA:orphadata[2024-06-27]\n\
+                 ORPHA:1388\tCatel-Manzke syndrome\tNOT\tHP:0001250\tORPHA:1388\tTAS\t\t\t\t\tP\tORPHA:orphadata[2024-06-27]\n\
+                 DECIPHER:1\tWolf-Hirschhorn Syndrome\t\tHP:0000252\tDECIPHER:1;PMID:12345\tIEA\tHP:0003577\t\tFEMALE\tHP:0012828\tP\tHPO:skoehler[2013-05-29]\n",
+            )?;
+
+            insta::assert_yaml_snapshot!(super::load_diseases(&path)?);
+            Ok(())
+        }
+
+        #[rstest::rstest]
+        #[case("", None)]
+        #[case("HP:0040280", Some(1.0))]
+        #[case("HP:0040284", Some(0.025))]
+        #[case("HP:0040285", Some(0.0))]
+        #[case("3/4", Some(0.75))]
+        #[case("3/0", None)]
+        #[case("25%", Some(0.25))]
+        #[case("HP:0000001", None)]
+        fn parse_frequency(#[case] frequency: &str, #[case] expected: Option<f32>) {
+            assert_eq!(super::parse_frequency(frequency), expected);
+        }
+
+        #[test]
+        fn parse_annotation_enums() {
+            assert_eq!(
+                "TAS".parse::<super::Evidence>().ok(),
+                Some(super::Evidence::Tas)
+            );
+            assert!("XYZ".parse::<super::Evidence>().is_err());
+            assert_eq!(
+                "female".parse::<super::Sex>().ok(),
+                Some(super::Sex::Female)
+            );
+            assert_eq!("MALE".parse::<super::Sex>().ok(), Some(super::Sex::Male));
+            assert_eq!(
+                "C".parse::<super::Aspect>().ok(),
+                Some(super::Aspect::ClinicalCourse)
+            );
+            assert!("p".parse::<super::Aspect>().is_err());
+        }
+    }
 }
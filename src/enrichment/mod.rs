@@ -0,0 +1,371 @@
+//! Enrichment analysis of HPO terms in gene sets and of genes for term sets.
+
+use std::collections::HashMap;
+use std::io::Write as _;
+use std::time::Instant;
+
+use clap::Parser;
+use hpo::{
+    annotations::{AnnotationId, Gene, GeneId},
+    term::HpoGroup,
+    HpoTermId, Ontology,
+};
+use rayon::prelude::*;
+
+use crate::algos::stats;
+use crate::common::PValueCorrection;
+
+/// Command line arguments for `enrichment` sub command.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Compute the enrichment of HPO terms in a gene set", long_about = None)]
+pub struct Args {
+    /// Path to the directory with the HPO files.
+    #[arg(long, required = true)]
+    pub path_hpo_dir: String,
+
+    /// The genes of the gene set, given as NCBI gene IDs, HGNC IDs, or symbols.
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub genes: Vec<String>,
+    /// The correction for multiple testing.
+    #[arg(long, default_value_t = PValueCorrection::default())]
+    pub correction: PValueCorrection,
+    /// Number of top terms to report, defaults to all enriched terms.
+    #[arg(long)]
+    pub max_results: Option<usize>,
+
+    /// Path to the output TSV file, defaults to stdout.
+    #[arg(long)]
+    pub path_output: Option<String>,
+}
+
+/// Resolve a gene by its NCBI gene ID, HGNC ID, or symbol.
+pub fn resolve_gene<'a, S>(
+    identifier: &str,
+    hpo: &'a Ontology,
+    hgnc_to_ncbi: &HashMap<String, u32, S>,
+) -> Option<&'a Gene>
+where
+    S: std::hash::BuildHasher,
+{
+    if let Ok(ncbi_gene_id) = identifier.parse::<u32>() {
+        hpo.gene(&GeneId::from(ncbi_gene_id))
+    } else if let Some(ncbi_gene_id) = hgnc_to_ncbi.get(identifier) {
+        hpo.gene(&GeneId::from(*ncbi_gene_id))
+    } else {
+        hpo.gene_by_name(identifier)
+    }
+}
+
+/// The terms annotated to `gene`, propagated to all their ancestors.
+fn propagated_terms(gene: &Gene, hpo: &Ontology) -> HpoGroup {
+    let mut group = HpoGroup::new();
+    for term in gene
+        .hpo_terms()
+        .iter()
+        .filter_map(|term_id| hpo.hpo(term_id))
+    {
+        group.insert(term.id());
+        for parent_id in term.all_parent_ids() {
+            group.insert(parent_id);
+        }
+    }
+    group
+}
+
+/// Enrichment of one HPO term in a gene set.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TermEnrichment {
+    /// The HPO term's ID.
+    pub term_id: String,
+    /// The HPO term's name.
+    pub name: String,
+    /// The number of genes of the gene set annotated with the term.
+    pub count: usize,
+    /// The number of all genes annotated with the term.
+    pub background_count: usize,
+    /// The P-value of the one-sided hypergeometric test.
+    pub p_value: f64,
+    /// The P-value adjusted for multiple testing.
+    pub adjusted_p_value: f64,
+    /// The symbols of the genes of the gene set annotated with the term.
+    pub gene_symbols: Vec<String>,
+}
+
+/// Compute the enrichment of HPO terms in `genes` against all genes in `hpo`.
+///
+/// The annotations of the genes are propagated to the ancestors of the annotated
+/// terms.  All terms annotated to at least one gene of `genes` are tested for
+/// over-representation and the result is sorted by P-value.
+pub fn term_enrichment(
+    genes: &[&Gene],
+    hpo: &Ontology,
+    correction: PValueCorrection,
+) -> Vec<TermEnrichment> {
+    let mut genes = genes.to_vec();
+    genes.sort_by_key(|gene| gene.id().as_u32());
+    genes.dedup_by_key(|gene| gene.id().as_u32());
+
+    // Collect the gene symbols for each term annotated in the gene set.
+    let mut term_genes: HashMap<HpoTermId, Vec<String>> = HashMap::new();
+    for gene in &genes {
+        for term_id in &propagated_terms(gene, hpo) {
+            term_genes
+                .entry(term_id)
+                .or_default()
+                .push(gene.symbol().to_string());
+        }
+    }
+
+    // The gene annotations of the terms in `hpo` are already propagated.
+    let population = hpo.genes().count() as u64;
+    let ln_factorials = stats::LnFactorials::new(population);
+    let mut tested = term_genes
+        .into_iter()
+        .filter_map(|(term_id, mut gene_symbols)| {
+            let term = hpo.hpo(term_id)?;
+            let background_count = term.gene_ids().len();
+            let p_value = ln_factorials.hypergeometric_upper_tail(
+                gene_symbols.len() as u64,
+                genes.len() as u64,
+                background_count as u64,
+                population,
+            );
+            gene_symbols.sort();
+            Some(TermEnrichment {
+                term_id: term_id.to_string(),
+                name: term.name().to_string(),
+                count: gene_symbols.len(),
+                background_count,
+                p_value,
+                adjusted_p_value: p_value,
+                gene_symbols,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let adjusted = correction.adjust(&tested.iter().map(|t| t.p_value).collect::<Vec<_>>());
+    for (entry, adjusted_p_value) in tested.iter_mut().zip(adjusted) {
+        entry.adjusted_p_value = adjusted_p_value;
+    }
+
+    tested.sort_by(|a, b| {
+        a.p_value
+            .total_cmp(&b.p_value)
+            .then_with(|| b.count.cmp(&a.count))
+            .then_with(|| a.term_id.cmp(&b.term_id))
+    });
+    tested
+}
+
+/// Enrichment of a patient's terms in the annotations of one gene.
+#[derive(Debug, Clone)]
+pub struct GeneEnrichment<'a> {
+    /// The tested gene.
+    pub gene: &'a Gene,
+    /// The patient's terms annotated to the gene, directly or via descendants.
+    pub matched: HpoGroup,
+    /// The P-value of the one-sided hypergeometric test.
+    pub p_value: f64,
+    /// The P-value adjusted for multiple testing with Benjamini-Hochberg.
+    pub adjusted_p_value: f64,
+}
+
+/// Test each of `genes` (all genes in `hpo` if `None`) for over-representation of
+/// the `patient` terms in its annotations.
+///
+/// The patient's terms and the annotations of the genes are propagated to their
+/// ancestors.  The background are all terms annotated to at least one gene in
+/// `hpo`, the drawn terms are those of the patient, and the terms of interest
+/// are those of the gene.  The result is sorted by P-value and gene ID.
+pub fn gene_enrichment<'a>(
+    patient: &HpoGroup,
+    genes: Option<&[&'a Gene]>,
+    hpo: &'a Ontology,
+) -> Vec<GeneEnrichment<'a>> {
+    let population = hpo
+        .hpos()
+        .filter(|term| !term.gene_ids().is_empty())
+        .count() as u64;
+    let ln_factorials = stats::LnFactorials::new(population);
+
+    let mut patient_terms = HpoGroup::new();
+    for term in patient.iter().filter_map(|term_id| hpo.hpo(term_id)) {
+        if term.gene_ids().is_empty() {
+            continue;
+        }
+        patient_terms.insert(term.id());
+        for parent_id in term.all_parent_ids() {
+            patient_terms.insert(parent_id);
+        }
+    }
+
+    let mut genes = if let Some(genes) = genes {
+        genes.to_vec()
+    } else {
+        hpo.genes().collect::<Vec<_>>()
+    };
+    genes.sort_by_key(|gene| gene.id().as_u32());
+    genes.dedup_by_key(|gene| gene.id().as_u32());
+
+    let mut tested = genes
+        .into_par_iter()
+        .map(|gene| {
+            let gene_terms = propagated_terms(gene, hpo);
+            let overlap = patient_terms
+                .iter()
+                .filter(|term_id| gene_terms.contains(term_id))
+                .count();
+            let p_value = ln_factorials.hypergeometric_upper_tail(
+                overlap as u64,
+                patient_terms.len() as u64,
+                gene_terms.len() as u64,
+                population,
+            );
+            let matched = patient
+                .iter()
+                .filter(|term_id| gene_terms.contains(term_id))
+                .collect::<HpoGroup>();
+            GeneEnrichment {
+                gene,
+                matched,
+                p_value,
+                adjusted_p_value: p_value,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let adjusted = stats::benjamini_hochberg(&tested.iter().map(|t| t.p_value).collect::<Vec<_>>());
+    for (entry, adjusted_p_value) in tested.iter_mut().zip(adjusted) {
+        entry.adjusted_p_value = adjusted_p_value;
+    }
+
+    tested.sort_by(|a, b| {
+        a.p_value
+            .total_cmp(&b.p_value)
+            .then_with(|| a.gene.id().as_u32().cmp(&b.gene.id().as_u32()))
+    });
+    tested
+}
+
+/// Main entry point for `enrichment` sub command.
+///
+/// # Errors
+///
+/// In the case of query execution or I/O failure.
+pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
+    tracing::info!("args_common = {:?}", &args_common);
+    tracing::info!("args = {:?}", &args);
+
+    tracing::info!("Loading HPO...");
+    let before_loading = Instant::now();
+    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
+    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+
+    tracing::info!("Loading HGNC xlink...");
+    let before_load_xlink = Instant::now();
+    let path_hgnc_xlink = format!("{}/hgnc_xlink.tsv", args.path_hpo_dir);
+    let ncbi_to_hgnc = crate::common::hgnc_xlink::load_ncbi_to_hgnc(path_hgnc_xlink)?;
+    let hgnc_to_ncbi = crate::common::hgnc_xlink::inverse_hashmap(&ncbi_to_hgnc);
+    tracing::info!(
+        "... done loading HGNC xlink in {:?}",
+        before_load_xlink.elapsed()
+    );
+
+    let mut genes = Vec::new();
+    for identifier in &args.genes {
+        if let Some(gene) = resolve_gene(identifier, &hpo, &hgnc_to_ncbi) {
+            genes.push(gene);
+        } else {
+            tracing::warn!("skipping unknown gene {}", identifier);
+        }
+    }
+
+    tracing::info!("Computing enrichment...");
+    let before_enrichment = Instant::now();
+    let mut result = term_enrichment(&genes, &hpo, args.correction);
+    if let Some(max_results) = args.max_results {
+        result.truncate(max_results);
+    }
+    tracing::info!(
+        "... done computing enrichment in {:?}",
+        before_enrichment.elapsed()
+    );
+
+    let mut writer: Box<dyn std::io::Write> = if let Some(path_output) = &args.path_output {
+        Box::new(std::io::BufWriter::new(std::fs::File::create(path_output)?))
+    } else {
+        Box::new(std::io::stdout())
+    };
+    writeln!(
+        writer,
+        "term_id\tname\tcount\tbackground_count\tp_value\tadjusted_p_value\tgene_symbols"
+    )?;
+    for entry in &result {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            entry.term_id,
+            entry.name,
+            entry.count,
+            entry.background_count,
+            entry.p_value,
+            entry.adjusted_p_value,
+            entry.gene_symbols.join(",")
+        )?;
+    }
+    writer.flush()?;
+
+    tracing::info!("All done. Have a nice day!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::common::PValueCorrection;
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    #[rstest::rstest]
+    fn term_enrichment(web_server_data: &Arc<crate::server::run::WebServerData>) {
+        let hpo = &web_server_data.ontology;
+        let genes = ["TGDS", "HGNC:12403", "2200", "NOT-A-GENE"]
+            .iter()
+            .filter_map(|identifier| {
+                super::resolve_gene(identifier, hpo, &web_server_data.hgnc_to_ncbi)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(genes.len(), 3);
+
+        let mut result = super::term_enrichment(&genes, hpo, PValueCorrection::default());
+        result.truncate(10);
+        insta::assert_yaml_snapshot!(result);
+    }
+
+    #[rstest::rstest]
+    fn gene_enrichment(web_server_data: &Arc<crate::server::run::WebServerData>) {
+        let hpo = &web_server_data.ontology;
+        let patient = ["HP:0010442", "HP:0000347"]
+            .iter()
+            .map(|term_id| hpo::HpoTermId::from((*term_id).to_string()))
+            .collect::<hpo::term::HpoGroup>();
+
+        let result = super::gene_enrichment(&patient, None, hpo)
+            .into_iter()
+            .take(10)
+            .map(|entry| {
+                (
+                    entry.gene.symbol().to_string(),
+                    entry
+                        .matched
+                        .iter()
+                        .map(|t| t.to_string())
+                        .collect::<Vec<_>>(),
+                    entry.p_value,
+                    entry.adjusted_p_value,
+                )
+            })
+            .collect::<Vec<_>>();
+        insta::assert_yaml_snapshot!(result);
+    }
+}
@@ -1,26 +1,90 @@
 //! Full text index for OBO documents using tantivy.
 
-use tantivy::schema::Schema;
+use std::path::Path;
 
-/// Encapsulation of a Tantivy index.
+use clap::Parser;
+use tantivy::schema::{Schema, Value as _};
+
+use crate::common::{hgnc_xlink::GeneNames, phenotype_hpoa::Disease};
+
+/// Name of the index directory in the HPO directory.
+pub const INDEX_DIR_NAME: &str = "index";
+/// Name of the file with the `IndexInfo` in the index directory.
+pub const INFO_FILE_NAME: &str = "viguno-index.json";
+/// Version of the index layout, to be incremented on changes to the schema.
+pub const INDEX_VERSION: u32 = 6;
+/// Name of the file with the parsed diseases in the index directory.
+pub const DISEASES_FILE_NAME: &str = "diseases.json";
+/// Name of the HPO term index directory in the index directory.
+pub const TERMS_DIR_NAME: &str = "terms";
+/// Name of the disease name index directory in the index directory.
+pub const DISEASES_DIR_NAME: &str = "diseases";
+/// Name of the gene index directory in the index directory.
+pub const GENES_DIR_NAME: &str = "genes";
+/// Name of the tokenizer that lower-cases but does not split, e.g., for gene symbols.
+const RAW_LOWERCASE_TOKENIZER: &str = "raw_lowercase";
+
+/// Command line arguments for `index build` sub command.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Build the full text index", long_about = None)]
+pub struct Args {
+    /// Path to the directory with the HPO files.
+    #[arg(long, required = true)]
+    pub path_hpo_dir: String,
+    /// Path to the output index directory, defaults to `index` in the HPO directory
+    /// where `server run` picks it up.
+    #[arg(long)]
+    pub path_out_dir: Option<String>,
+    /// Overwrite an existing index directory.
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+}
+
+/// Information on a persisted index, written next to the tantivy files.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct IndexInfo {
+    /// Version of the index layout, see `INDEX_VERSION`.
+    pub index_version: u32,
+    /// The HPO version that the index was built from.
+    pub hpo_version: String,
+}
+
+/// Encapsulation of the Tantivy indices.
 ///
-/// The index lives in a directory and will only persist as long as the lifetime of this struct.
+/// There is one index each for the HPO terms, the disease names, and the genes
+/// with their symbols and names.  The indices
+/// either live in a temporary directory and will only persist as long as the
+/// lifetime of this struct or were opened read-only from a directory built with
+/// `build`.
+#[allow(clippy::struct_field_names)] // field names match the public accessors
 pub struct Index {
-    /// The index directory.
+    /// The temporary index directory, if any.
     #[allow(dead_code)]
-    tmpdir: tempdir::TempDir, // keep around for RAII
-    /// The HPO document.
-    hpo_doc: fastobo::ast::OboDoc,
-    /// The index.
+    tmpdir: Option<tempdir::TempDir>, // keep around for RAII
+    /// The HPO document, if the index was created with `new`.
+    hpo_doc: Option<fastobo::ast::OboDoc>,
+    /// The HPO term index.
     index: tantivy::Index,
-    /// The index schema.
+    /// The HPO term index schema.
     schema: tantivy::schema::Schema,
-    /// The single reader.
+    /// The single reader of the HPO term index.
     reader: tantivy::IndexReader,
+    /// The disease name index.
+    disease_index: tantivy::Index,
+    /// The disease name index schema.
+    disease_schema: tantivy::schema::Schema,
+    /// The single reader of the disease name index.
+    disease_reader: tantivy::IndexReader,
+    /// The gene index.
+    gene_index: tantivy::Index,
+    /// The gene index schema.
+    gene_schema: tantivy::schema::Schema,
+    /// The single reader of the gene index.
+    gene_reader: tantivy::IndexReader,
 }
 
 /// Convert ident to String.
-fn ident_to_string(ident: &fastobo::ast::Ident) -> String {
+pub(crate) fn ident_to_string(ident: &fastobo::ast::Ident) -> String {
     match ident {
         fastobo::ast::Ident::Prefixed(val) => format!("{}:{}", val.prefix(), val.local()),
         fastobo::ast::Ident::Unprefixed(val) => val.as_str().to_string(),
@@ -28,41 +92,159 @@ fn ident_to_string(ident: &fastobo::ast::Ident) -> String {
     }
 }
 
+/// Quote `query` as a phrase for the query parser.
+///
+/// Quotes and backslashes would end the phrase early or escape the closing quote,
+/// they are dropped as the tokenizers ignore them anyway.
+fn to_phrase(query: &str) -> String {
+    format!("\"{}\"", query.replace(['"', '\\'], " ").trim())
+}
+
 // Code for creating an `Index`.
 impl Index {
-    /// Create a new index from an OBO document.
+    /// Create a new index from an OBO document, the disease names, and the gene names
+    /// in a temporary directory.
     ///
     /// # Args
     ///
     /// * `hpo_doc` - The OBO document to index.
+    /// * `diseases` - The disease names to index.
+    /// * `genes` - The gene names to index.
     ///
     /// # Errors
     ///
     /// In the case that the index cannot be created.
-    pub fn new(hpo_doc: fastobo::ast::OboDoc) -> Result<Self, anyhow::Error> {
-        let schema = Self::build_schema();
+    pub fn new(
+        hpo_doc: &fastobo::ast::OboDoc,
+        diseases: &[Disease],
+        genes: &[GeneNames],
+    ) -> Result<Self, anyhow::Error> {
         let tmpdir = tempdir::TempDir::new("viguno")?;
-        let index = tantivy::Index::create_in_dir(tmpdir.path(), schema.clone())?;
+        Self::create_in(tmpdir.path(), hpo_doc, diseases, genes)?;
+        let path = tmpdir.path().to_path_buf();
+        Self::open_in(&path, Some(tmpdir), Some(hpo_doc.clone()))
+    }
 
-        let mut index_writer = index.writer(100_000_000).map_err(|e| {
-            anyhow::anyhow!(
-                "Error creating tantivy index writer for directory {:?}: {}",
-                tmpdir.path(),
-                e
-            )
-        })?;
-        Self::write_hpo_index(&hpo_doc, &schema, &mut index_writer).map_err(|e| {
-            anyhow::anyhow!(
-                "Error writing HPO index for directory {:?}: {}",
-                tmpdir.path(),
-                e
-            )
+    /// Build the index from an OBO document, the disease names, and the gene names in
+    /// the directory `path` for later use with `open`.
+    ///
+    /// The diseases are also stored in the directory so that `read_diseases` can load
+    /// them without parsing `phenotype.hpoa` again.
+    ///
+    /// # Args
+    ///
+    /// * `hpo_doc` - The OBO document to index.
+    /// * `diseases` - The disease names to index.
+    /// * `genes` - The gene names to index.
+    /// * `hpo_version` - The HPO version to record in the `IndexInfo`.
+    /// * `path` - The index directory, must not exist yet.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the index cannot be created.
+    pub fn build<P: AsRef<Path>>(
+        hpo_doc: &fastobo::ast::OboDoc,
+        diseases: &[Disease],
+        genes: &[GeneNames],
+        hpo_version: &str,
+        path: P,
+    ) -> Result<(), anyhow::Error> {
+        std::fs::create_dir(path.as_ref()).map_err(|e| {
+            anyhow::anyhow!("Error creating index directory {:?}: {}", path.as_ref(), e)
         })?;
+        Self::create_in(path.as_ref(), hpo_doc, diseases, genes)?;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(
+            path.as_ref().join(DISEASES_FILE_NAME),
+        )?);
+        serde_json::to_writer(&mut writer, diseases)?;
+        std::io::Write::flush(&mut writer)?;
 
-        let reader = index
-            .reader_builder()
-            .reload_policy(tantivy::ReloadPolicy::Manual)
-            .try_into()?;
+        let info = IndexInfo {
+            index_version: INDEX_VERSION,
+            hpo_version: hpo_version.to_string(),
+        };
+        std::fs::write(
+            path.as_ref().join(INFO_FILE_NAME),
+            serde_json::to_string_pretty(&info)?,
+        )?;
+        Ok(())
+    }
+
+    /// Open the index previously built with `build` in `path` read-only.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the index cannot be opened or was built for another index
+    /// layout or HPO version than `hpo_version`.
+    pub fn open<P: AsRef<Path>>(path: P, hpo_version: &str) -> Result<Self, anyhow::Error> {
+        let info: IndexInfo = serde_json::from_str(
+            &std::fs::read_to_string(path.as_ref().join(INFO_FILE_NAME)).map_err(|e| {
+                anyhow::anyhow!("Error reading index info in {:?}: {}", path.as_ref(), e)
+            })?,
+        )?;
+        if info.index_version != INDEX_VERSION {
+            anyhow::bail!(
+                "index in {:?} has version {}, expected {}",
+                path.as_ref(),
+                info.index_version,
+                INDEX_VERSION
+            );
+        }
+        if info.hpo_version != hpo_version {
+            anyhow::bail!(
+                "index in {:?} is for HPO {}, but HPO is {}",
+                path.as_ref(),
+                info.hpo_version,
+                hpo_version
+            );
+        }
+
+        Self::open_in(path.as_ref(), None, None)
+    }
+
+    /// Read the diseases stored by `build` in the index directory `path`.
+    ///
+    /// Call `open` first to check the index version.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the diseases cannot be read.
+    pub fn read_diseases<P: AsRef<Path>>(path: P) -> Result<Vec<Disease>, anyhow::Error> {
+        let reader = std::io::BufReader::new(
+            std::fs::File::open(path.as_ref().join(DISEASES_FILE_NAME)).map_err(|e| {
+                anyhow::anyhow!("Error reading diseases in {:?}: {}", path.as_ref(), e)
+            })?,
+        );
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Open the indices in the directory `path`, checking their schemas.
+    fn open_in(
+        path: &Path,
+        tmpdir: Option<tempdir::TempDir>,
+        hpo_doc: Option<fastobo::ast::OboDoc>,
+    ) -> Result<Self, anyhow::Error> {
+        let open_index = |name: &str, schema: &Schema| -> Result<tantivy::Index, anyhow::Error> {
+            let path = path.join(name);
+            let index = tantivy::Index::open_in_dir(&path).map_err(|e| {
+                anyhow::anyhow!("Error opening tantivy index in {:?}: {}", &path, e)
+            })?;
+            Self::register_tokenizers(&index);
+            if index.schema() != *schema {
+                anyhow::bail!("index in {:?} has an unexpected schema", &path);
+            }
+            Ok(index)
+        };
+
+        let schema = Self::build_schema();
+        let index = open_index(TERMS_DIR_NAME, &schema)?;
+        let reader = Self::open_reader(&index)?;
+        let disease_schema = Self::build_disease_schema();
+        let disease_index = open_index(DISEASES_DIR_NAME, &disease_schema)?;
+        let disease_reader = Self::open_reader(&disease_index)?;
+        let gene_schema = Self::build_gene_schema();
+        let gene_index = open_index(GENES_DIR_NAME, &gene_schema)?;
+        let gene_reader = Self::open_reader(&gene_index)?;
 
         Ok(Self {
             tmpdir,
@@ -70,23 +252,230 @@ impl Index {
             index,
             schema,
             reader,
+            disease_index,
+            disease_schema,
+            disease_reader,
+            gene_index,
+            gene_schema,
+            gene_reader,
         })
     }
 
+    /// Create the indices from `hpo_doc`, `diseases`, and `genes` in the existing
+    /// directory `path`.
+    fn create_in(
+        path: &Path,
+        hpo_doc: &fastobo::ast::OboDoc,
+        diseases: &[Disease],
+        genes: &[GeneNames],
+    ) -> Result<(), anyhow::Error> {
+        let create_writer =
+            |name: &str, schema: &Schema| -> Result<tantivy::IndexWriter, anyhow::Error> {
+                let path = path.join(name);
+                std::fs::create_dir(&path).map_err(|e| {
+                    anyhow::anyhow!("Error creating index directory {:?}: {}", &path, e)
+                })?;
+                let index = tantivy::Index::create_in_dir(&path, schema.clone())?;
+                Self::register_tokenizers(&index);
+                index.writer(100_000_000).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Error creating tantivy index writer for directory {:?}: {}",
+                        &path,
+                        e
+                    )
+                })
+            };
+
+        let schema = Self::build_schema();
+        let mut index_writer = create_writer(TERMS_DIR_NAME, &schema)?;
+        Self::write_hpo_index(hpo_doc, &schema, &mut index_writer).map_err(|e| {
+            anyhow::anyhow!("Error writing HPO index for directory {:?}: {}", path, e)
+        })?;
+        index_writer
+            .wait_merging_threads()
+            .map_err(|e| anyhow::anyhow!("Error finishing tantivy index writer: {}", e))?;
+
+        let schema = Self::build_disease_schema();
+        let mut index_writer = create_writer(DISEASES_DIR_NAME, &schema)?;
+        Self::write_disease_index(diseases, &schema, &mut index_writer).map_err(|e| {
+            anyhow::anyhow!(
+                "Error writing disease index for directory {:?}: {}",
+                path,
+                e
+            )
+        })?;
+        index_writer
+            .wait_merging_threads()
+            .map_err(|e| anyhow::anyhow!("Error finishing tantivy index writer: {}", e))?;
+
+        let schema = Self::build_gene_schema();
+        let mut index_writer = create_writer(GENES_DIR_NAME, &schema)?;
+        Self::write_gene_index(genes, &schema, &mut index_writer).map_err(|e| {
+            anyhow::anyhow!("Error writing gene index for directory {:?}: {}", path, e)
+        })?;
+        index_writer
+            .wait_merging_threads()
+            .map_err(|e| anyhow::anyhow!("Error finishing tantivy index writer: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Register the custom tokenizers with `index`, must happen before writing or
+    /// searching.
+    fn register_tokenizers(index: &tantivy::Index) {
+        use tantivy::tokenizer::{LowerCaser, RawTokenizer, TextAnalyzer};
+
+        index.tokenizers().register(
+            RAW_LOWERCASE_TOKENIZER,
+            TextAnalyzer::builder(RawTokenizer::default())
+                .filter(LowerCaser)
+                .build(),
+        );
+    }
+
+    /// Open the single reader of `index`.
+    fn open_reader(index: &tantivy::Index) -> Result<tantivy::IndexReader, anyhow::Error> {
+        Ok(index
+            .reader_builder()
+            .reload_policy(tantivy::ReloadPolicy::Manual)
+            .try_into()?)
+    }
+
     /// Build the tantivy schema for the HPO.
+    ///
+    /// The field `name_raw` holds the whole name as its lower-cased words separated
+    /// by single spaces, see `split_words`, for prefix matching of partial input.
     fn build_schema() -> Schema {
-        use tantivy::schema::{STORED, STRING, TEXT};
+        use tantivy::schema::{
+            IndexRecordOption, TextFieldIndexing, TextOptions, STORED, STRING, TEXT,
+        };
+
+        let raw_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(RAW_LOWERCASE_TOKENIZER)
+                .set_index_option(IndexRecordOption::Basic),
+        );
 
         let mut schema_builder = Schema::builder();
         schema_builder.add_text_field("term_id", STRING | STORED);
         schema_builder.add_text_field("alt_id", STRING | STORED);
         schema_builder.add_text_field("name", TEXT | STORED);
+        schema_builder.add_text_field("name_raw", raw_options);
         schema_builder.add_text_field("def", TEXT | STORED);
         schema_builder.add_text_field("synonym", TEXT | STORED);
         schema_builder.add_text_field("xref", STRING | STORED);
         schema_builder.build()
     }
 
+    /// Build the tantivy schema for the disease names.
+    fn build_disease_schema() -> Schema {
+        use tantivy::schema::{STORED, STRING, TEXT};
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("disease_id", STRING | STORED);
+        schema_builder.add_text_field("source", STRING | STORED);
+        schema_builder.add_text_field("name", TEXT | STORED);
+        schema_builder.add_text_field("alt_title", TEXT | STORED);
+        schema_builder.build()
+    }
+
+    /// Index the disease names, the source is the prefix of the disease ID, e.g.,
+    /// `OMIM`.
+    fn write_disease_index(
+        diseases: &[Disease],
+        schema: &tantivy::schema::Schema,
+        index_writer: &mut tantivy::IndexWriter,
+    ) -> Result<(), anyhow::Error> {
+        for disease in diseases {
+            let mut doc = tantivy::TantivyDocument::default();
+            doc.add_field_value(schema.get_field("disease_id")?, disease.disease_id.as_str());
+            if let Some((source, _)) = disease.disease_id.split_once(':') {
+                doc.add_field_value(schema.get_field("source")?, source);
+            }
+            doc.add_field_value(schema.get_field("name")?, disease.name.as_str());
+            for alt_title in &disease.alt_titles {
+                doc.add_field_value(schema.get_field("alt_title")?, alt_title.as_str());
+            }
+
+            index_writer.add_document(doc).map_err(|e| {
+                anyhow::anyhow!(
+                    "Error adding document to tantivy index writer: {}",
+                    e.to_string()
+                )
+            })?;
+        }
+
+        index_writer
+            .commit()
+            .map_err(|e| anyhow::anyhow!("Error committing tantivy index writer: {}", e))?;
+        Ok(())
+    }
+
+    /// Build the tantivy schema for the genes.
+    ///
+    /// Gene symbols are matched case-insensitively as a whole, the gene names are
+    /// tokenized as text.
+    fn build_gene_schema() -> Schema {
+        use tantivy::schema::{
+            IndexRecordOption, TextFieldIndexing, TextOptions, STORED, STRING, TEXT,
+        };
+
+        let symbol_options = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(RAW_LOWERCASE_TOKENIZER)
+                    .set_index_option(IndexRecordOption::WithFreqs),
+            )
+            .set_stored();
+
+        let mut schema_builder = Schema::builder();
+        schema_builder.add_text_field("ncbi_gene_id", STRING | STORED);
+        schema_builder.add_text_field("hgnc_id", STRING | STORED);
+        schema_builder.add_text_field("symbol", symbol_options.clone());
+        schema_builder.add_text_field("alias_symbol", symbol_options.clone());
+        schema_builder.add_text_field("prev_symbol", symbol_options);
+        schema_builder.add_text_field("name", TEXT | STORED);
+        schema_builder.build()
+    }
+
+    /// Index the gene symbols and names.
+    fn write_gene_index(
+        genes: &[GeneNames],
+        schema: &tantivy::schema::Schema,
+        index_writer: &mut tantivy::IndexWriter,
+    ) -> Result<(), anyhow::Error> {
+        for gene in genes {
+            let mut doc = tantivy::TantivyDocument::default();
+            doc.add_field_value(
+                schema.get_field("ncbi_gene_id")?,
+                gene.ncbi_gene_id.to_string(),
+            );
+            doc.add_field_value(schema.get_field("hgnc_id")?, gene.hgnc_id.as_str());
+            doc.add_field_value(schema.get_field("symbol")?, gene.symbol.as_str());
+            for alias_symbol in &gene.alias_symbols {
+                doc.add_field_value(schema.get_field("alias_symbol")?, alias_symbol.as_str());
+            }
+            for prev_symbol in &gene.prev_symbols {
+                doc.add_field_value(schema.get_field("prev_symbol")?, prev_symbol.as_str());
+            }
+            if let Some(name) = &gene.name {
+                doc.add_field_value(schema.get_field("name")?, name.as_str());
+            }
+
+            index_writer.add_document(doc).map_err(|e| {
+                anyhow::anyhow!(
+                    "Error adding document to tantivy index writer: {}",
+                    e.to_string()
+                )
+            })?;
+        }
+
+        index_writer
+            .commit()
+            .map_err(|e| anyhow::anyhow!("Error committing tantivy index writer: {}", e))?;
+        Ok(())
+    }
+
     /// Index the HPO document.
     fn write_hpo_index(
         hpo_doc: &fastobo::ast::OboDoc,
@@ -113,6 +502,12 @@ impl Index {
                 match line {
                     fastobo::ast::TermClause::Name(name) => {
                         doc.add_field_value(schema.get_field("name")?, name.as_str());
+                        let name_raw = split_words(name.as_str())
+                            .into_iter()
+                            .map(|(_, _, word)| word)
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        doc.add_field_value(schema.get_field("name_raw")?, name_raw.as_str());
                     }
                     fastobo::ast::TermClause::AltId(alt_id) => {
                         doc.add_field_value(
@@ -170,22 +565,688 @@ impl Index {
 // Accessor code.
 impl Index {
     /// Get the HPO document.
-    pub fn hpo_doc(&self) -> &fastobo::ast::OboDoc {
-        &self.hpo_doc
+    ///
+    /// Only available for indices created with `new`, the document is not kept for
+    /// indices opened with `open`.
+    #[deprecated(note = "not available for indices opened with `open`, use `fastobo` directly")]
+    pub fn hpo_doc(&self) -> Option<&fastobo::ast::OboDoc> {
+        self.hpo_doc.as_ref()
     }
 
-    /// Get the index.
+    /// Get the HPO term index.
     pub fn index(&self) -> &tantivy::Index {
         &self.index
     }
 
-    /// Get the schema.
+    /// Get the HPO term schema.
     pub fn schema(&self) -> &tantivy::schema::Schema {
         &self.schema
     }
 
-    /// Get the reader.
+    /// Get the HPO term reader.
     pub fn reader(&self) -> &tantivy::IndexReader {
         &self.reader
     }
+
+    /// Get the disease name index.
+    pub fn disease_index(&self) -> &tantivy::Index {
+        &self.disease_index
+    }
+
+    /// Get the disease name schema.
+    pub fn disease_schema(&self) -> &tantivy::schema::Schema {
+        &self.disease_schema
+    }
+
+    /// Get the disease name reader.
+    pub fn disease_reader(&self) -> &tantivy::IndexReader {
+        &self.disease_reader
+    }
+
+    /// Get the gene index.
+    pub fn gene_index(&self) -> &tantivy::Index {
+        &self.gene_index
+    }
+
+    /// Get the gene schema.
+    pub fn gene_schema(&self) -> &tantivy::schema::Schema {
+        &self.gene_schema
+    }
+
+    /// Get the gene reader.
+    pub fn gene_reader(&self) -> &tantivy::IndexReader {
+        &self.gene_reader
+    }
+}
+
+// Search code.
+impl Index {
+    /// Search the disease name index for `query` with relevance ranking.
+    ///
+    /// The query is matched against the disease IDs and, with fuzzy matching, against
+    /// the names and alternative titles, where matches in the names rank higher.  If
+    /// `source` is given then only diseases with this ID prefix, e.g., `OMIM`, are
+    /// considered.  Returns the IDs and scores of the (at most) `max_results` best
+    /// matching diseases, best match first.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the query cannot be parsed or the search fails.
+    pub fn search_diseases(
+        &self,
+        query: &str,
+        source: Option<&str>,
+        max_results: usize,
+    ) -> Result<Vec<(String, f32)>, anyhow::Error> {
+        let field_disease_id = self.disease_schema.get_field("disease_id")?;
+        let field_source = self.disease_schema.get_field("source")?;
+        let field_name = self.disease_schema.get_field("name")?;
+        let field_alt_title = self.disease_schema.get_field("alt_title")?;
+
+        let query_parser = {
+            let mut query_parser = tantivy::query::QueryParser::for_index(
+                &self.disease_index,
+                vec![field_disease_id, field_name, field_alt_title],
+            );
+            query_parser.set_conjunction_by_default();
+            query_parser.set_field_boost(field_name, 2.0);
+            query_parser.set_field_fuzzy(field_name, true, 1, true);
+            query_parser.set_field_fuzzy(field_alt_title, true, 1, true);
+            query_parser
+        };
+        // Disease IDs are matched as a whole, in names punctuation such as the dash in
+        // "Catel-Manzke" would yield phrase queries without fuzzy matching.
+        let query = if query.contains(':') {
+            to_phrase(query)
+        } else {
+            query
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+                .collect()
+        };
+        let mut index_query = query_parser
+            .parse_query(&query)
+            .map_err(|e| anyhow::anyhow!("Error parsing query: {}", e))?;
+        if let Some(source) = source {
+            let source_query = tantivy::query::TermQuery::new(
+                tantivy::Term::from_field_text(field_source, source),
+                tantivy::schema::IndexRecordOption::Basic,
+            );
+            index_query = Box::new(tantivy::query::BooleanQuery::new(vec![
+                (tantivy::query::Occur::Must, index_query),
+                (tantivy::query::Occur::Must, Box::new(source_query)),
+            ]));
+        }
+
+        let searcher = self.disease_reader.searcher();
+        let top_docs = searcher
+            .search(
+                &index_query,
+                &tantivy::collector::TopDocs::with_limit(max_results),
+            )
+            .map_err(|e| anyhow::anyhow!("Error searching index: {}", e))?;
+
+        let mut result = Vec::new();
+        for (score, doc_address) in top_docs {
+            let doc = searcher
+                .doc::<tantivy::TantivyDocument>(doc_address)
+                .map_err(|e| anyhow::anyhow!("Error retrieving document: {}", e))?;
+            let disease_id = doc
+                .get_first(field_disease_id)
+                .and_then(|value| value.as_value().as_str())
+                .ok_or_else(|| anyhow::anyhow!("Document has no `disease_id` field"))?;
+            result.push((disease_id.to_string(), score));
+        }
+        Ok(result)
+    }
+}
+
+/// A candidate term for autocompletion, see `Index::complete_terms`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermCandidate {
+    /// The HPO term's ID.
+    pub term_id: String,
+    /// The HPO term's name.
+    pub name: String,
+    /// The HPO term's synonyms.
+    pub synonyms: Vec<String>,
+}
+
+/// Split `text` into lower-case words with their start and end character offsets.
+///
+/// Words are separated by non-alphanumeric characters, as with the tantivy tokenizer
+/// used for the text fields.
+pub fn split_words(text: &str) -> Vec<(usize, usize, String)> {
+    let mut result = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (offset, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() {
+            current
+                .get_or_insert_with(|| (offset, String::new()))
+                .1
+                .extend(c.to_lowercase());
+        } else if let Some((start, word)) = current.take() {
+            result.push((start, offset, word));
+        }
+    }
+    if let Some((start, word)) = current {
+        result.push((start, text.chars().count(), word));
+    }
+    result
+}
+
+// Autocompletion code.
+impl Index {
+    /// Find the candidate terms for completing the partial input `words`.
+    ///
+    /// A term is a candidate if each word occurs in its name or synonyms, the last
+    /// word only as a prefix if `last_is_prefix`.  The (at most) `limit` candidates
+    /// are sorted by their BM25 score, boosted for terms whose name starts with the
+    /// input words.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the search fails.
+    pub fn complete_terms(
+        &self,
+        words: &[String],
+        last_is_prefix: bool,
+        limit: usize,
+    ) -> Result<Vec<TermCandidate>, anyhow::Error> {
+        use tantivy::query::{BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, Query, TermQuery};
+
+        let field_term_id = self.schema.get_field("term_id")?;
+        let field_name = self.schema.get_field("name")?;
+        let field_name_raw = self.schema.get_field("name_raw")?;
+        let field_synonym = self.schema.get_field("synonym")?;
+
+        let word_query = |field, word: &str, is_prefix: bool| -> Box<dyn Query> {
+            let term = tantivy::Term::from_field_text(field, word);
+            if is_prefix {
+                Box::new(FuzzyTermQuery::new_prefix(term, 0, false))
+            } else {
+                Box::new(TermQuery::new(
+                    term,
+                    tantivy::schema::IndexRecordOption::Basic,
+                ))
+            }
+        };
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for (i, word) in words.iter().enumerate() {
+            let is_prefix = last_is_prefix && i + 1 == words.len();
+            clauses.push((
+                Occur::Must,
+                Box::new(BooleanQuery::new(vec![
+                    (Occur::Should, word_query(field_name, word, is_prefix)),
+                    (Occur::Should, word_query(field_synonym, word, is_prefix)),
+                ])),
+            ));
+        }
+        if clauses.is_empty() {
+            return Ok(Vec::new());
+        }
+        clauses.push((
+            Occur::Should,
+            Box::new(BoostQuery::new(
+                word_query(field_name_raw, &words.join(" "), true),
+                10.0,
+            )),
+        ));
+        let index_query = BooleanQuery::new(clauses);
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher
+            .search(
+                &index_query,
+                &tantivy::collector::TopDocs::with_limit(limit),
+            )
+            .map_err(|e| anyhow::anyhow!("Error searching index: {}", e))?;
+
+        let mut result = Vec::new();
+        for (_score, doc_address) in top_docs {
+            let doc = searcher
+                .doc::<tantivy::TantivyDocument>(doc_address)
+                .map_err(|e| anyhow::anyhow!("Error retrieving document: {}", e))?;
+            let get_all = |field| {
+                doc.get_all(field)
+                    .filter_map(|value| value.as_str())
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            };
+            let term_id = get_all(field_term_id)
+                .pop()
+                .ok_or_else(|| anyhow::anyhow!("Document has no `term_id` field"))?;
+            result.push(TermCandidate {
+                term_id,
+                name: get_all(field_name).pop().unwrap_or_default(),
+                synonyms: get_all(field_synonym),
+            });
+        }
+        Ok(result)
+    }
+}
+
+// Gene search code.
+impl Index {
+    /// Search the gene index for `query` with relevance ranking.
+    ///
+    /// The query is matched against the NCBI and HGNC gene IDs, as a prefix against the
+    /// current, alias, and previous symbols, and with fuzzy matching against the gene
+    /// names.  Exact symbol matches rank highest, followed by previous and alias
+    /// symbols.  Returns the NCBI gene IDs and scores of the (at most) `max_results`
+    /// best matching genes, best match first.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the query cannot be parsed or the search fails.
+    pub fn search_genes(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Result<Vec<(u32, f32)>, anyhow::Error> {
+        use tantivy::query::{BooleanQuery, BoostQuery, Occur, Query, TermQuery};
+
+        let field_ncbi_gene_id = self.gene_schema.get_field("ncbi_gene_id")?;
+        let field_hgnc_id = self.gene_schema.get_field("hgnc_id")?;
+        let field_symbol = self.gene_schema.get_field("symbol")?;
+        let field_alias_symbol = self.gene_schema.get_field("alias_symbol")?;
+        let field_prev_symbol = self.gene_schema.get_field("prev_symbol")?;
+        let field_name = self.gene_schema.get_field("name")?;
+
+        let query_parser = {
+            let mut query_parser = tantivy::query::QueryParser::for_index(
+                &self.gene_index,
+                vec![
+                    field_ncbi_gene_id,
+                    field_hgnc_id,
+                    field_symbol,
+                    field_alias_symbol,
+                    field_prev_symbol,
+                    field_name,
+                ],
+            );
+            query_parser.set_conjunction_by_default();
+            query_parser.set_field_fuzzy(field_symbol, true, 0, false);
+            query_parser.set_field_fuzzy(field_alias_symbol, true, 0, false);
+            query_parser.set_field_fuzzy(field_prev_symbol, true, 0, false);
+            query_parser.set_field_fuzzy(field_name, true, 1, true);
+            query_parser
+        };
+        // Quote HGNC IDs, strip query syntax such as a leading dash (exclusion) from
+        // symbols.
+        let query = query.trim();
+        let parser_query = if query.contains(':') {
+            to_phrase(query)
+        } else {
+            query
+                .split(|c: char| !(c.is_alphanumeric() || c == '-'))
+                .map(|word| word.trim_matches('-'))
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        if parser_query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let parsed_query = query_parser
+            .parse_query(&parser_query)
+            .map_err(|e| anyhow::anyhow!("Error parsing query: {}", e))?;
+
+        // Rank exact symbol matches of the whole query first.
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, parsed_query)];
+        for (field, boost) in [
+            (field_symbol, 4.0),
+            (field_prev_symbol, 2.0),
+            (field_alias_symbol, 1.0),
+        ] {
+            clauses.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(
+                        tantivy::Term::from_field_text(field, &query.to_lowercase()),
+                        tantivy::schema::IndexRecordOption::Basic,
+                    )),
+                    boost,
+                )),
+            ));
+        }
+        let index_query = BooleanQuery::new(clauses);
+
+        let searcher = self.gene_reader.searcher();
+        let top_docs = searcher
+            .search(
+                &index_query,
+                &tantivy::collector::TopDocs::with_limit(max_results),
+            )
+            .map_err(|e| anyhow::anyhow!("Error searching index: {}", e))?;
+
+        let mut result = Vec::new();
+        for (score, doc_address) in top_docs {
+            let doc = searcher
+                .doc::<tantivy::TantivyDocument>(doc_address)
+                .map_err(|e| anyhow::anyhow!("Error retrieving document: {}", e))?;
+            let ncbi_gene_id = doc
+                .get_first(field_ncbi_gene_id)
+                .and_then(|value| value.as_value().as_str())
+                .ok_or_else(|| anyhow::anyhow!("Document has no `ncbi_gene_id` field"))?;
+            result.push((ncbi_gene_id.parse()?, score));
+        }
+        Ok(result)
+    }
+
+    /// Resolve the outdated gene `symbol` to the NCBI gene ID of the current gene.
+    ///
+    /// Previous symbols take precedence over alias symbols and the symbol is only
+    /// resolved if it is unambiguous.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the search fails.
+    pub fn resolve_gene_symbol(&self, symbol: &str) -> Result<Option<u32>, anyhow::Error> {
+        let field_ncbi_gene_id = self.gene_schema.get_field("ncbi_gene_id")?;
+        let searcher = self.gene_reader.searcher();
+        for field_name in ["prev_symbol", "alias_symbol"] {
+            let query = tantivy::query::TermQuery::new(
+                tantivy::Term::from_field_text(
+                    self.gene_schema.get_field(field_name)?,
+                    &symbol.trim().to_lowercase(),
+                ),
+                tantivy::schema::IndexRecordOption::Basic,
+            );
+            let top_docs = searcher
+                .search(&query, &tantivy::collector::TopDocs::with_limit(2))
+                .map_err(|e| anyhow::anyhow!("Error searching index: {}", e))?;
+            match top_docs.as_slice() {
+                [] => (),
+                [(_, doc_address)] => {
+                    let doc = searcher
+                        .doc::<tantivy::TantivyDocument>(*doc_address)
+                        .map_err(|e| anyhow::anyhow!("Error retrieving document: {}", e))?;
+                    let ncbi_gene_id = doc
+                        .get_first(field_ncbi_gene_id)
+                        .and_then(|value| value.as_value().as_str())
+                        .ok_or_else(|| anyhow::anyhow!("Document has no `ncbi_gene_id` field"))?;
+                    return Ok(Some(ncbi_gene_id.parse()?));
+                }
+                _ => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Load the OBO document from the HPO directory `path_hpo_dir`.
+///
+/// # Errors
+///
+/// In the case that the file could not be read or parsed.
+pub fn load_hpo_doc(path_hpo_dir: &str) -> Result<fastobo::ast::OboDoc, anyhow::Error> {
+    tracing::info!("Loading HPO OBO...");
+    let before_load_obo = std::time::Instant::now();
+    let hpo_doc = fastobo::from_file(format!("{}/{}", path_hpo_dir, "hp.obo"))
+        .map_err(|e| anyhow::anyhow!("Error loading HPO OBO: {}", e))?;
+    tracing::info!(
+        "... done loading HPO OBO in {:?}",
+        before_load_obo.elapsed()
+    );
+    Ok(hpo_doc)
+}
+
+/// Load the diseases from `phenotype.hpoa` in the HPO directory `path_hpo_dir`.
+///
+/// # Errors
+///
+/// In the case that the file could not be read.
+pub fn load_diseases(path_hpo_dir: &str) -> Result<Vec<Disease>, anyhow::Error> {
+    tracing::info!("Loading diseases...");
+    let before_load_diseases = std::time::Instant::now();
+    let diseases = crate::common::phenotype_hpoa::load_diseases(format!(
+        "{}/{}",
+        path_hpo_dir, "phenotype.hpoa"
+    ))
+    .map_err(|e| anyhow::anyhow!("Error loading diseases: {}", e))?;
+    tracing::info!(
+        "... done loading {} diseases in {:?}",
+        diseases.len(),
+        before_load_diseases.elapsed()
+    );
+    Ok(diseases)
+}
+
+/// Load the gene names from `hgnc_xlink.tsv` in the HPO directory `path_hpo_dir`.
+///
+/// # Errors
+///
+/// In the case that the file could not be read.
+pub fn load_genes(path_hpo_dir: &str) -> Result<Vec<GeneNames>, anyhow::Error> {
+    tracing::info!("Loading gene names...");
+    let before_load_genes = std::time::Instant::now();
+    let genes = crate::common::hgnc_xlink::load_gene_names(format!(
+        "{}/{}",
+        path_hpo_dir, "hgnc_xlink.tsv"
+    ))
+    .map_err(|e| anyhow::anyhow!("Error loading gene names: {}", e))?;
+    tracing::info!(
+        "... done loading {} gene names in {:?}",
+        genes.len(),
+        before_load_genes.elapsed()
+    );
+    Ok(genes)
+}
+
+/// Main entry point for `index build` sub command.
+///
+/// # Errors
+///
+/// In the case that the index could not be built.
+pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
+    tracing::info!("args_common = {:?}", &args_common);
+    tracing::info!("args = {:?}", &args);
+
+    tracing::info!("Loading HPO...");
+    let before_loading = std::time::Instant::now();
+    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
+    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+    let hpo_doc = load_hpo_doc(&args.path_hpo_dir)?;
+    let diseases = load_diseases(&args.path_hpo_dir)?;
+    let genes = load_genes(&args.path_hpo_dir)?;
+
+    let path_out_dir = args
+        .path_out_dir
+        .clone()
+        .unwrap_or_else(|| format!("{}/{}", &args.path_hpo_dir, INDEX_DIR_NAME));
+    if Path::new(&path_out_dir).exists() {
+        if args.force {
+            tracing::info!("Removing existing index in {}", &path_out_dir);
+            std::fs::remove_dir_all(&path_out_dir)?;
+        } else {
+            anyhow::bail!(
+                "index directory {} exists, use --force to overwrite",
+                &path_out_dir
+            );
+        }
+    }
+
+    tracing::info!("Indexing OBO, disease names, and gene names...");
+    let before_index_obo = std::time::Instant::now();
+    Index::build(
+        &hpo_doc,
+        &diseases,
+        &genes,
+        &hpo.hpo_version(),
+        &path_out_dir,
+    )?;
+    tracing::info!(
+        "... done indexing OBO, disease names, and gene names into {} in {:?}",
+        &path_out_dir,
+        before_index_obo.elapsed()
+    );
+
+    tracing::info!("All done. Have a nice day!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn split_words() {
+        assert_eq!(
+            super::split_words("Catel-Manzke  syndrome"),
+            vec![
+                (0, 5, "catel".to_string()),
+                (6, 12, "manzke".to_string()),
+                (14, 22, "syndrome".to_string()),
+            ]
+        );
+        assert!(super::split_words(" - ").is_empty());
+    }
+
+    #[test]
+    fn complete_terms_name_start() -> Result<(), anyhow::Error> {
+        let hpo_doc: fastobo::ast::OboDoc = "format-version: 1.4\n\n\
+            [Term]\nid: HP:0000001\nname: All\n\n\
+            [Term]\nid: HP:0000002\nname: Nail of toe\nis_a: HP:0000001\n\n\
+            [Term]\nid: HP:0000003\nname: Toe-nail abnormality of the foot\nis_a: HP:0000001\n"
+            .parse()?;
+        let index = super::Index::new(&hpo_doc, &[], &[])?;
+
+        // The shorter name scores higher with BM25 but the name starting with the
+        // input, including punctuation, is boosted.
+        let words = vec!["toe".to_string(), "na".to_string()];
+        let term_ids = index
+            .complete_terms(&words, true, 1)?
+            .into_iter()
+            .map(|candidate| candidate.term_id)
+            .collect::<Vec<_>>();
+        assert_eq!(term_ids, vec!["HP:0000003"]);
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_gene_symbol() -> Result<(), anyhow::Error> {
+        let hpo_doc: fastobo::ast::OboDoc = "format-version: 1.4\n\n\
+            [Term]\nid: HP:0000001\nname: All\n"
+            .parse()?;
+        let tmpdir = tempdir::TempDir::new("viguno")?;
+        let path = tmpdir.path().join("hgnc_xlink.tsv");
+        std::fs::write(
+            &path,
+            "hgnc_id\tensembl_gene_id\tentrez_id\tgene_symbol\tname\talias_symbol\tprev_symbol\n\
+             HGNC:12403\tENSG00000155657\t7273\tTTN\ttitin\tCMD1G|TMD|SHARED\t\n\
+             HGNC:28191\tENSG00000173715\t79703\tTOP6BL\tTOP6B like\tMGC13125|SHARED\tC11orf80\n\
+             HGNC:1\t\t1\tNEW1\t\t\tOLD\n\
+             HGNC:2\t\t2\tNEW2\t\tOLD\t\n",
+        )?;
+        let genes = crate::common::hgnc_xlink::load_gene_names(&path)?;
+        let index = super::Index::new(&hpo_doc, &[], &genes)?;
+
+        // Previous and alias symbols are resolved case-insensitively.
+        assert_eq!(index.resolve_gene_symbol("C11orf80")?, Some(79703));
+        assert_eq!(index.resolve_gene_symbol(" c11orf80 ")?, Some(79703));
+        assert_eq!(index.resolve_gene_symbol("CMD1G")?, Some(7273));
+        // Previous symbols take precedence over alias symbols.
+        assert_eq!(index.resolve_gene_symbol("OLD")?, Some(1));
+        // Ambiguous, current, and unknown symbols are not resolved.
+        assert_eq!(index.resolve_gene_symbol("SHARED")?, None);
+        assert_eq!(index.resolve_gene_symbol("TTN")?, None);
+        assert_eq!(index.resolve_gene_symbol("NOT-A-GENE")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn build_and_open() -> Result<(), anyhow::Error> {
+        let hpo_doc: fastobo::ast::OboDoc = "format-version: 1.4\n\n\
+            [Term]\nid: HP:0000001\nname: All\n\n\
+            [Term]\nid: HP:0000118\nname: Phenotypic abnormality\nis_a: HP:0000001\n"
+            .parse()?;
+        let diseases = vec![crate::common::phenotype_hpoa::Disease {
+            disease_id: "OMIM:616145".to_string(),
+            name: "Catel-Manzke syndrome".to_string(),
+            alt_titles: Vec::new(),
+            hpo_terms: vec!["HP:0000118".to_string()],
+            frequencies: std::collections::BTreeMap::new(),
+            annotations: Vec::new(),
+        }];
+        let genes = vec![
+            crate::common::hgnc_xlink::GeneNames {
+                ncbi_gene_id: 7273,
+                hgnc_id: "HGNC:12403".to_string(),
+                symbol: "TTN".to_string(),
+                name: Some("titin".to_string()),
+                alias_symbols: vec!["CMD1G".to_string()],
+                prev_symbols: Vec::new(),
+            },
+            crate::common::hgnc_xlink::GeneNames {
+                ncbi_gene_id: 79703,
+                hgnc_id: "HGNC:28191".to_string(),
+                symbol: "TOP6BL".to_string(),
+                name: Some("TOP6B like initiator of meiotic double strand breaks".to_string()),
+                alias_symbols: Vec::new(),
+                prev_symbols: vec!["C11orf80".to_string()],
+            },
+        ];
+        let tmpdir = tempdir::TempDir::new("viguno")?;
+        let path = tmpdir.path().join(super::INDEX_DIR_NAME);
+        super::Index::build(&hpo_doc, &diseases, &genes, "2024-01-01", &path)?;
+
+        let index = super::Index::open(&path, "2024-01-01")?;
+        assert_eq!(super::Index::read_diseases(&path)?, diseases);
+        assert_eq!(index.reader().searcher().num_docs(), 2);
+        let term_ids =
+            |words: &[&str], last_is_prefix: bool| -> Result<Vec<String>, anyhow::Error> {
+                let words = words
+                    .iter()
+                    .map(|word| (*word).to_string())
+                    .collect::<Vec<_>>();
+                Ok(index
+                    .complete_terms(&words, last_is_prefix, 10)?
+                    .into_iter()
+                    .map(|candidate| candidate.term_id)
+                    .collect())
+            };
+        assert_eq!(term_ids(&["phenotypic", "abn"], true)?, vec!["HP:0000118"]);
+        assert!(term_ids(&["phenotypic", "abn"], false)?.is_empty());
+        assert_eq!(index.disease_reader().searcher().num_docs(), 1);
+        let hits = index.search_diseases("Catel-Manzk syndrom", Some("OMIM"), 10)?;
+        assert_eq!(
+            hits.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(),
+            vec!["OMIM:616145"]
+        );
+        assert_eq!(index.search_diseases("OMIM:616145", None, 10)?.len(), 1);
+        assert_eq!(index.search_diseases("OMIM:616145\"", None, 10)?.len(), 1);
+        assert!(index.search_diseases("OMIM:\\\"", None, 10)?.is_empty());
+
+        assert_eq!(index.gene_reader().searcher().num_docs(), 2);
+        let gene_ids = |query: &str| -> Result<Vec<u32>, anyhow::Error> {
+            Ok(index
+                .search_genes(query, 10)?
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect())
+        };
+        assert_eq!(gene_ids("c11orf80")?, vec![79703]);
+        assert_eq!(gene_ids("C11orf")?, vec![79703]);
+        assert_eq!(gene_ids("titn")?, vec![7273]);
+        assert_eq!(gene_ids("HGNC:12403")?, vec![7273]);
+        assert_eq!(gene_ids("\"HGNC:12403\"")?, vec![7273]);
+        assert_eq!(index.resolve_gene_symbol("C11orf80")?, Some(79703));
+        assert_eq!(index.resolve_gene_symbol("TTN")?, None);
+        assert!(index
+            .search_diseases("Catel-Manzke syndrome", Some("ORPHA"), 10)?
+            .is_empty());
+
+        #[allow(deprecated)]
+        {
+            assert!(index.hpo_doc().is_none());
+            let index = super::Index::new(&hpo_doc, &diseases, &genes)?;
+            assert_eq!(index.hpo_doc(), Some(&hpo_doc));
+        }
+
+        let err = super::Index::open(&path, "2024-02-02")
+            .err()
+            .expect("must fail for other HPO version");
+        assert!(err.to_string().contains("is for HPO 2024-01-01"));
+        Ok(())
+    }
 }
@@ -5,12 +5,18 @@
 #![warn(missing_docs)]
 
 pub mod algos;
+pub mod batch;
+pub mod cluster;
+pub mod cohort;
 pub mod common;
 pub mod convert;
+pub mod enrichment;
 pub mod index;
+pub mod mondo;
 pub mod pbs;
 pub mod query;
 pub mod server;
+pub mod simulate;
 
 use clap::{Parser, Subcommand};
 
@@ -38,9 +44,30 @@ struct Cli {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 enum Commands {
+    Batch(crate::batch::Args),
+    Cluster(crate::cluster::Args),
+    Cohort(crate::cohort::Args),
     Convert(crate::convert::Args),
+    Enrichment(crate::enrichment::Args),
+    Index(Index),
     Query(crate::query::Args),
     Server(Server),
+    Simulate(crate::simulate::Args),
+}
+
+/// Clap sub command below "index".
+#[derive(Debug, Parser)]
+struct Index {
+    /// The sub command to run
+    #[clap(subcommand)]
+    pub command: IndexSubCommands,
+}
+
+/// Sub commands for "index".
+#[derive(Debug, Subcommand)]
+enum IndexSubCommands {
+    /// Build the full text index.
+    Build(crate::index::Args),
 }
 
 /// Clap sub command below "server".
@@ -82,9 +109,26 @@ fn main() -> Result<(), anyhow::Error> {
     // Install collector and go into sub commands.
     tracing::subscriber::with_default(collector, || {
         match &cli.command {
+            Commands::Batch(args) => {
+                batch::run(&cli.common, args)?;
+            }
+            Commands::Cluster(args) => {
+                cluster::run(&cli.common, args)?;
+            }
+            Commands::Cohort(args) => {
+                cohort::run(&cli.common, args)?;
+            }
             Commands::Convert(args) => {
                 convert::run(&cli.common, args)?;
             }
+            Commands::Enrichment(args) => {
+                enrichment::run(&cli.common, args)?;
+            }
+            Commands::Index(cmd_index) => match &cmd_index.command {
+                IndexSubCommands::Build(args) => {
+                    index::run(&cli.common, args)?;
+                }
+            },
             Commands::Query(args) => {
                 query::run(&cli.common, args)?;
             }
@@ -96,6 +140,9 @@ fn main() -> Result<(), anyhow::Error> {
                     server::schema::run(&cli.common, args)?;
                 }
             },
+            Commands::Simulate(args) => {
+                simulate::run(&cli.common, args)?;
+            }
         }
 
         Ok::<(), anyhow::Error>(())
@@ -0,0 +1,244 @@
+//! Mapping of MONDO diseases to OMIM and ORPHA diseases.
+//!
+//! The mapping is read from the optional `mondo.obo` file in the HPO directory.  Only
+//! cross-references qualified with `source="MONDO:equivalentTo"` are used.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use crate::index::ident_to_string;
+
+/// Name of the MONDO OBO file in the HPO directory.
+pub const MONDO_FILE_NAME: &str = "mondo.obo";
+
+/// The qualifier value marking a cross-reference as an equivalence.
+const EQUIVALENT_TO: &str = "MONDO:equivalentTo";
+
+/// A MONDO disease.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MondoTerm {
+    /// The MONDO ID, e.g., `MONDO:0007739`.
+    pub mondo_id: String,
+    /// The MONDO disease name.
+    pub name: String,
+    /// The IDs of the parent MONDO diseases.
+    pub parents: Vec<String>,
+    /// The sorted IDs of the equivalent OMIM and ORPHA diseases, e.g., `OMIM:616145`.
+    pub xrefs: Vec<String>,
+}
+
+/// The MONDO diseases with their hierarchy and mapping to OMIM and ORPHA.
+#[derive(Debug, Clone, Default)]
+pub struct Mondo {
+    /// The non-obsolete terms by MONDO ID.
+    terms: HashMap<String, MondoTerm>,
+    /// The IDs of the child terms by MONDO ID.
+    children: HashMap<String, Vec<String>>,
+    /// The MONDO IDs by OMIM or ORPHA disease ID.
+    xref_mondo_ids: HashMap<String, Vec<String>>,
+}
+
+/// Normalize the cross-reference `xref` to an OMIM or ORPHA disease ID, if any.
+fn normalize_xref(xref: &str) -> Option<String> {
+    let (prefix, local) = xref.split_once(':')?;
+    match prefix {
+        "OMIM" => Some(format!("OMIM:{local}")),
+        "Orphanet" | "ORPHA" => Some(format!("ORPHA:{local}")),
+        _ => None,
+    }
+}
+
+impl Mondo {
+    /// Load the MONDO OBO file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the file could not be read or parsed.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+        let doc = fastobo::from_file(path.as_ref())
+            .map_err(|e| anyhow::anyhow!("Error loading MONDO OBO {:?}: {}", path.as_ref(), e))?;
+        Ok(Self::from_doc(&doc))
+    }
+
+    /// Build the mapping from the MONDO OBO document `doc`.
+    pub fn from_doc(doc: &fastobo::ast::OboDoc) -> Self {
+        let mut result = Self::default();
+        for term_frame in doc
+            .entities()
+            .iter()
+            .filter_map(fastobo::ast::EntityFrame::as_term)
+        {
+            let mut term = MondoTerm {
+                mondo_id: ident_to_string(term_frame.id().as_inner().as_ref()),
+                name: String::new(),
+                parents: Vec::new(),
+                xrefs: Vec::new(),
+            };
+            let mut is_obsolete = false;
+            for line in term_frame.clauses() {
+                match line.as_inner() {
+                    fastobo::ast::TermClause::Name(name) => {
+                        term.name = name.as_str().to_string();
+                    }
+                    fastobo::ast::TermClause::IsA(parent) => {
+                        term.parents.push(ident_to_string(parent.as_ref().as_ref()));
+                    }
+                    fastobo::ast::TermClause::Xref(xref) => {
+                        let is_equivalent = line.qualifiers().is_some_and(|qualifiers| {
+                            qualifiers.iter().any(|qualifier| {
+                                qualifier.key().to_string() == "source"
+                                    && qualifier.value().as_str() == EQUIVALENT_TO
+                            })
+                        });
+                        if is_equivalent {
+                            if let Some(xref) = normalize_xref(&ident_to_string(xref.id())) {
+                                term.xrefs.push(xref);
+                            }
+                        }
+                    }
+                    fastobo::ast::TermClause::IsObsolete(value) => is_obsolete = *value,
+                    _ => (),
+                }
+            }
+            if is_obsolete {
+                continue;
+            }
+            term.xrefs.sort();
+            term.xrefs.dedup();
+
+            for parent in &term.parents {
+                result
+                    .children
+                    .entry(parent.clone())
+                    .or_default()
+                    .push(term.mondo_id.clone());
+            }
+            for xref in &term.xrefs {
+                result
+                    .xref_mondo_ids
+                    .entry(xref.clone())
+                    .or_default()
+                    .push(term.mondo_id.clone());
+            }
+            result.terms.insert(term.mondo_id.clone(), term);
+        }
+        for mondo_ids in result.xref_mondo_ids.values_mut() {
+            mondo_ids.sort();
+        }
+        result
+    }
+
+    /// Return the term with `mondo_id`, if any.
+    pub fn term(&self, mondo_id: &str) -> Option<&MondoTerm> {
+        self.terms.get(mondo_id)
+    }
+
+    /// Return the sorted MONDO IDs equivalent to the OMIM or ORPHA `disease_id`.
+    pub fn mondo_ids(&self, disease_id: &str) -> &[String] {
+        self.xref_mondo_ids
+            .get(disease_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Return the sorted OMIM and ORPHA disease IDs grouped under `mondo_id`.
+    ///
+    /// These are the equivalents of the term itself and of all its descendants, e.g.,
+    /// all OMIM subtypes of a MONDO disease.
+    pub fn grouped_diseases(&self, mondo_id: &str) -> Vec<String> {
+        let mut seen = HashSet::from([mondo_id.to_string()]);
+        let mut queue = VecDeque::from([mondo_id.to_string()]);
+        let mut result = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            if let Some(term) = self.terms.get(&current) {
+                result.extend(term.xrefs.iter().cloned());
+            }
+            for child in self.children.get(&current).into_iter().flatten() {
+                if seen.insert(child.clone()) {
+                    queue.push_back(child.clone());
+                }
+            }
+        }
+        result.sort();
+        result.dedup();
+        result
+    }
+}
+
+/// Load the MONDO mapping from the HPO directory `path_hpo_dir` if it contains the
+/// MONDO OBO file.
+///
+/// # Errors
+///
+/// In the case that the file exists but could not be read or parsed.
+pub fn load_optional(path_hpo_dir: &str) -> Result<Option<Mondo>, anyhow::Error> {
+    let path = Path::new(path_hpo_dir).join(MONDO_FILE_NAME);
+    if !path.exists() {
+        tracing::info!(
+            "No {} in {}, MONDO is not available",
+            MONDO_FILE_NAME,
+            path_hpo_dir
+        );
+        return Ok(None);
+    }
+
+    tracing::info!("Loading MONDO...");
+    let before_load_mondo = std::time::Instant::now();
+    let mondo = Mondo::load(&path)?;
+    tracing::info!(
+        "... done loading {} MONDO terms in {:?}",
+        mondo.terms.len(),
+        before_load_mondo.elapsed()
+    );
+    Ok(Some(mondo))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    /// A MONDO excerpt with a grouping term and two subtypes.
+    pub fn mondo() -> Result<super::Mondo, anyhow::Error> {
+        let doc: fastobo::ast::OboDoc = "format-version: 1.2\n\n\
+            [Term]\nid: MONDO:0000001\nname: disease\n\n\
+            [Term]\nid: MONDO:0015159\nname: multiple congenital anomalies/dysmorphic syndrome\n\
+            is_a: MONDO:0000001\n\n\
+            [Term]\nid: MONDO:0014553\nname: Catel-Manzke syndrome\n\
+            is_a: MONDO:0015159\n\
+            xref: OMIM:616145 {source=\"MONDO:equivalentTo\"}\n\
+            xref: Orphanet:1388 {source=\"MONDO:equivalentTo\"}\n\
+            xref: UMLS:C1844887 {source=\"MONDO:equivalentTo\"}\n\
+            xref: OMIM:302380 {source=\"MONDO:relatedTo\"}\n\n\
+            [Term]\nid: MONDO:0100001\nname: obsolete thing\n\
+            xref: OMIM:100000 {source=\"MONDO:equivalentTo\"}\n\
+            is_obsolete: true\n"
+            .parse()?;
+        Ok(super::Mondo::from_doc(&doc))
+    }
+
+    #[test]
+    fn term() -> Result<(), anyhow::Error> {
+        let mondo = mondo()?;
+        insta::assert_yaml_snapshot!(mondo.term("MONDO:0014553"));
+        assert!(mondo.term("MONDO:0100001").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn mondo_ids() -> Result<(), anyhow::Error> {
+        let mondo = mondo()?;
+        assert_eq!(mondo.mondo_ids("ORPHA:1388"), ["MONDO:0014553"]);
+        assert!(mondo.mondo_ids("OMIM:302380").is_empty());
+        assert!(mondo.mondo_ids("OMIM:100000").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn grouped_diseases() -> Result<(), anyhow::Error> {
+        let mondo = mondo()?;
+        assert_eq!(
+            mondo.grouped_diseases("MONDO:0000001"),
+            vec!["OMIM:616145", "ORPHA:1388"]
+        );
+        assert!(mondo.grouped_diseases("MONDO:9999999").is_empty());
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
 //! Data structures for (de-)serialization as generated by `prost-build`.
 
+/// Data structures generated by prost from phenopacket.
+pub mod phenopacket {
+    include!(concat!(env!("OUT_DIR"), "/viguno.v1.phenopacket.rs"));
+}
+
 /// Data structures generated by prost from simulation.
 pub mod simulation {
     include!(concat!(env!("OUT_DIR"), "/viguno.v1.simulation.rs"));
@@ -1,15 +1,29 @@
 //! Code for ranking genes on the command line.
 
-use hpo::similarity::Builtins;
+pub mod output;
+pub mod phenopacket;
+
+use hpo::similarity::{Builtins, Similarity};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::time::Instant;
 
 use clap::Parser;
-use hpo::{annotations::AnnotationId, term::HpoGroup, HpoTermId, Ontology};
+use hpo::{
+    annotations::{AnnotationId, Disease as _, GeneId, OmimDiseaseId, OrphaDiseaseId},
+    term::HpoGroup,
+    HpoTermId, Ontology,
+};
+use strum::{EnumIter, IntoEnumIterator};
 
-use crate::algos::phenomizer;
-use crate::query::query_result::HpoSimTermGeneTermDetails;
+use crate::algos::onset::{self, CandidateOnsets, OnsetRange};
+use crate::algos::{phenomizer, stats};
+use crate::common::{to_pairwise_sim, IcBasedOn, ScoreCombiner, SimilarityMethod};
+use crate::query::output::OutputFormat;
+use crate::query::query_result::{HpoSimTermDiseaseTermDetails, HpoSimTermGeneTermDetails};
+use crate::simulate::store::{load_store, Store};
 
 /// Command line arguments for `query` command.
 #[derive(Parser, Debug)]
@@ -19,12 +33,255 @@ pub struct Args {
     #[arg(long, required = true)]
     pub path_hpo_dir: String,
 
-    /// Path to JSON file with the genes to rank.
+    /// What to rank, genes or diseases.
+    #[arg(long, default_value_t = QueryMode::default())]
+    pub mode: QueryMode,
+    /// How to rank the genes in `--mode genes`.
+    #[arg(long, default_value_t = RankingMethod::default())]
+    pub method: RankingMethod,
+    /// Path to JSON file with the genes to rank in `--mode genes`, defaults to all
+    /// genes with annotations.
+    #[arg(long)]
+    pub path_genes_json: Option<String>,
+    /// Optional path to JSON file with the diseases to rank in `--mode diseases`,
+    /// defaults to all OMIM and ORPHA diseases.
+    #[arg(long)]
+    pub path_diseases_json: Option<String>,
+    /// Path to JSON file with HPO IDs of patient, optionally with the `onset` of the
+    /// terms as HPO onset term ID or ISO8601 age.
+    #[arg(long, required_unless_present = "path_phenopacket")]
+    pub path_terms_json: Option<String>,
+    /// Path to JSON file with HPO IDs explicitly excluded in the patient.
+    #[arg(long, conflicts_with = "path_phenopacket")]
+    pub path_excluded_terms_json: Option<String>,
+    /// Path to GA4GH phenopacket of patient as an alternative to `--path-terms-json`,
+    /// read as JSON if the name ends in `.json` and as protobuf otherwise.  The onsets
+    /// of the observed features are used like the ones in `--path-terms-json`.
+    #[arg(long, conflicts_with = "path_terms_json")]
+    pub path_phenopacket: Option<String>,
+    /// Use the genes from the phenopacket's interpretations as the genes to rank.
+    #[arg(long, default_value_t = false, requires = "path_phenopacket")]
+    pub phenopacket_genes: bool,
+    /// Weight the candidates' terms absent from the patient by their frequency from
+    /// `phenotype.hpoa` when ranking by similarity.
+    #[arg(long, default_value_t = false)]
+    pub frequency_weighted: bool,
+    /// Optional path to the directory with the simulation store for P-values.
     #[arg(long)]
-    pub path_genes_json: String,
-    /// Path to JSON file with HPO IDs of patient.
+    pub path_simulation_dir: Option<String>,
+
+    /// Path to the output file, defaults to stdout.
     #[arg(long)]
-    pub path_terms_json: String,
+    pub output: Option<String>,
+    /// The output format.
+    #[arg(long, default_value_t = OutputFormat::default())]
+    pub output_format: OutputFormat,
+    /// Write one TSV row per result entry and term with the term details.
+    #[arg(long, default_value_t = false)]
+    pub with_term_details: bool,
+
+    /// Configuration of the similarity computation.
+    #[command(flatten)]
+    pub scoring: ScoringArgs,
+    /// Limits on the reported results.
+    #[command(flatten)]
+    pub limits: ResultLimits,
+}
+
+/// What to rank in the `query` command.
+#[derive(Default, Debug, Clone, Copy, EnumIter, PartialEq, Eq, derive_more::Display)]
+pub enum QueryMode {
+    /// Rank the genes from `--path-genes-json`.
+    #[default]
+    #[display("genes")]
+    Genes,
+    /// Rank OMIM and ORPHA diseases.
+    #[display("diseases")]
+    Diseases,
+}
+
+impl FromStr for QueryMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QueryMode::iter()
+            .find(|m| m.to_string().as_str().eq(s))
+            .ok_or(anyhow::anyhow!("unknown query mode: {}", s))
+    }
+}
+
+/// How to rank the genes in the `query` command.
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    EnumIter,
+    PartialEq,
+    Eq,
+    derive_more::Display,
+    Serialize,
+    Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum RankingMethod {
+    /// Rank by phenotypic similarity, see `run_query`.
+    #[default]
+    #[display("similarity")]
+    Similarity,
+    /// Rank by over-representation of the patient's terms in the gene's
+    /// annotations, see `run_enrichment_query`.
+    #[display("enrichment")]
+    Enrichment,
+}
+
+impl RankingMethod {
+    /// Whether this is the default similarity-based ranking.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn is_similarity(&self) -> bool {
+        *self == RankingMethod::Similarity
+    }
+}
+
+impl FromStr for RankingMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RankingMethod::iter()
+            .find(|m| m.to_string().as_str().eq(s))
+            .ok_or(anyhow::anyhow!("unknown ranking method: {}", s))
+    }
+}
+
+/// Configuration of the similarity computation in `run_query`.
+#[derive(clap::Args, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScoringArgs {
+    /// What should information content be based on.
+    #[arg(long, default_value_t = IcBasedOn::default())]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[arg(long, default_value_t = SimilarityMethod::default())]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[arg(long, default_value_t = ScoreCombiner::default())]
+    pub combiner: ScoreCombiner,
+}
+
+/// Limits on the genes reported by `run_query`.
+#[derive(clap::Args, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResultLimits {
+    /// Number of top results to report, defaults to all genes and 100 diseases.
+    #[arg(long)]
+    pub max_results: Option<usize>,
+    /// Minimal raw score of the reported genes.
+    #[arg(long)]
+    pub min_score: Option<f32>,
+}
+
+/// Frequencies of the annotated HPO terms of genes and diseases for frequency-aware
+/// scoring, see `phenomizer::score_weighted`.
+///
+/// Terms without a known frequency are weighted with `1.0`.
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationFrequencies {
+    /// Term frequencies by NCBI gene ID.
+    genes: HashMap<u32, HashMap<HpoTermId, f32>>,
+    /// Term frequencies by disease ID, e.g., `OMIM:616145`.
+    diseases: HashMap<String, HashMap<HpoTermId, f32>>,
+}
+
+impl AnnotationFrequencies {
+    /// Construct from the frequencies of the `diseases` from `phenotype.hpoa`.
+    ///
+    /// The frequency of a term in a gene is the highest frequency of the term in the
+    /// gene's diseases from `disease_to_genes`, where terms without a known frequency
+    /// count as obligate.
+    pub fn new<S>(
+        diseases: &[crate::common::phenotype_hpoa::Disease],
+        disease_to_genes: &HashMap<String, Vec<u32>, S>,
+    ) -> Self
+    where
+        S: std::hash::BuildHasher,
+    {
+        let mut result = Self::default();
+        for disease in diseases {
+            let frequencies = disease
+                .hpo_terms
+                .iter()
+                .filter_map(|term_id| {
+                    let frequency = disease.frequencies.get(term_id).copied().unwrap_or(1f32);
+                    Some((HpoTermId::try_from(term_id.as_str()).ok()?, frequency))
+                })
+                .collect::<HashMap<_, _>>();
+            for ncbi_gene_id in disease_to_genes
+                .get(&disease.disease_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+            {
+                let gene_frequencies = result.genes.entry(*ncbi_gene_id).or_default();
+                for (term_id, frequency) in &frequencies {
+                    let value = gene_frequencies.entry(*term_id).or_insert(*frequency);
+                    *value = value.max(*frequency);
+                }
+            }
+            result.diseases.insert(
+                disease.disease_id.clone(),
+                frequencies
+                    .into_iter()
+                    .filter(|(_, frequency)| *frequency < 1f32)
+                    .collect(),
+            );
+        }
+        for gene_frequencies in result.genes.values_mut() {
+            gene_frequencies.retain(|_, frequency| *frequency < 1f32);
+        }
+        result
+    }
+
+    /// Return the weight of `term_id` in the gene with `ncbi_gene_id`.
+    pub fn gene_weight(&self, ncbi_gene_id: u32, term_id: HpoTermId) -> f32 {
+        self.genes
+            .get(&ncbi_gene_id)
+            .and_then(|frequencies| frequencies.get(&term_id))
+            .copied()
+            .unwrap_or(1f32)
+    }
+
+    /// Return the weight of `term_id` in the disease with `disease_id`.
+    pub fn disease_weight(&self, disease_id: &str, term_id: HpoTermId) -> f32 {
+        self.diseases
+            .get(disease_id)
+            .and_then(|frequencies| frequencies.get(&term_id))
+            .copied()
+            .unwrap_or(1f32)
+    }
+}
+
+/// Optional weighting of the similarity scores in `run_query` and `run_disease_query`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScoreWeighting<'a> {
+    /// Annotation frequencies to weight the candidates' terms with, see
+    /// `phenomizer::score_weighted`.
+    pub frequencies: Option<&'a AnnotationFrequencies>,
+    /// The patient's onset as HPO onset term ID or ISO8601 age and the onsets of the
+    /// candidates, the similarity of candidates with incompatible onset is multiplied
+    /// with `onset::INCOMPATIBLE_ONSET_FACTOR`.
+    pub onset: Option<(&'a str, &'a CandidateOnsets)>,
+}
+
+impl ScoreWeighting<'_> {
+    /// Whether no weighting is applied.
+    pub fn is_none(&self) -> bool {
+        self.frequencies.is_none() && self.onset.is_none()
+    }
+
+    /// Parse the patient's onset, if any.
+    fn patient_onset(&self) -> Result<Option<(OnsetRange, &CandidateOnsets)>, anyhow::Error> {
+        self.onset
+            .map(|(onset, candidates)| Ok((OnsetRange::parse(onset)?, candidates)))
+            .transpose()
+    }
 }
 
 /// Struct for loading a gene from JSON.
@@ -34,6 +291,13 @@ pub struct Gene {
     pub gene_symbol: String,
 }
 
+/// Struct for loading a disease from JSON.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Disease {
+    /// The disease ID, e.g., `OMIM:616145` or `ORPHA:1234`.
+    pub disease_id: String,
+}
+
 /// Struct for loading an HPO term from JSON.
 #[derive(Serialize, Deserialize, utoipa::ToSchema, Debug, Clone)]
 pub struct HpoTerm {
@@ -42,11 +306,15 @@ pub struct HpoTerm {
     /// The term name (optional).
     #[serde(default = "Option::default")]
     pub term_name: Option<String>,
+    /// The onset of the term in the patient as HPO onset term ID or ISO8601 age,
+    /// e.g., `HP:0003623` or `P2W` (optional).
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub onset: Option<String>,
 }
 
 /// Query result records.
 pub mod query_result {
-    use crate::common::Version;
+    use crate::common::{IcBasedOn, ScoreCombiner, SimilarityMethod, Version};
 
     use super::HpoTerm;
 
@@ -77,8 +345,37 @@ pub mod query_result {
     pub struct HpoSimTermGeneQuery {
         /// The query HPO terms.
         pub terms: Vec<HpoTerm>,
-        /// The gene list to score.
+        /// The HPO terms explicitly excluded in the patient.
+        #[serde(default = "Vec::default", skip_serializing_if = "Vec::is_empty")]
+        pub excluded_terms: Vec<HpoTerm>,
+        /// The gene list to score, empty if all genes were scored.
         pub genes: Vec<Gene>,
+        /// How the genes were ranked.
+        #[serde(default, skip_serializing_if = "super::RankingMethod::is_similarity")]
+        pub method: super::RankingMethod,
+        /// What should information content be based on, not used when ranking by
+        /// enrichment.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub ic_base: Option<IcBasedOn>,
+        /// The similarity method to use, not used when ranking by enrichment.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub similarity: Option<SimilarityMethod>,
+        /// The score combiner, not used when ranking by enrichment.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub combiner: Option<ScoreCombiner>,
+        /// Whether the annotated terms absent from the query were weighted by their
+        /// frequency.
+        #[serde(default, skip_serializing_if = "crate::common::is_false")]
+        pub frequency_weighted: bool,
+        /// The patient's onset that candidates were checked against.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub onset: Option<String>,
+        /// The maximal number of genes to report.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub max_results: Option<usize>,
+        /// The minimal raw score of the reported genes.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub min_score: Option<f32>,
     }
 
     /// Result container data structure.
@@ -97,8 +394,20 @@ pub mod query_result {
     pub struct HpoSimTermGeneResultEntry {
         /// The gene symbol.
         pub gene_symbol: String,
-        /// The raw Phenomizer score.
+        /// The raw score as computed with the configured similarity method and combiner,
+        /// or `-log10` of the P-value when ranking by enrichment.
         pub raw_score: f32,
+        /// The empirical P-value, if simulation results are available, or the P-value
+        /// of the hypergeometric test when ranking by enrichment.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub p_value: Option<f64>,
+        /// The P-value adjusted for multiple testing with Benjamini-Hochberg.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub adjusted_p_value: Option<f64>,
+        /// Whether the annotated onset is compatible with the patient's onset, only if
+        /// both are known.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub onset_compatible: Option<bool>,
         /// Details on individual terms.
         #[serde(default = "Option::default")]
         pub terms: Option<Vec<HpoSimTermGeneTermDetails>>,
@@ -111,20 +420,216 @@ pub mod query_result {
         pub term_query: Option<HpoTerm>,
         /// The gene's HPO term.
         pub term_gene: HpoTerm,
-        /// The similarity score.
+        /// The similarity score, negative for the penalty of an excluded term.
+        pub score: f32,
+        /// Whether `term_query` is an excluded term annotated to the gene.
+        #[serde(default, skip_serializing_if = "crate::common::is_false")]
+        pub excluded: bool,
+    }
+
+    /// The performed disease query.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+    pub struct HpoSimTermDiseaseQuery {
+        /// The query HPO terms.
+        pub terms: Vec<HpoTerm>,
+        /// The HPO terms explicitly excluded in the patient.
+        #[serde(default = "Vec::default", skip_serializing_if = "Vec::is_empty")]
+        pub excluded_terms: Vec<HpoTerm>,
+        /// The disease IDs to score, all OMIM and ORPHA diseases if not given.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub disease_ids: Option<Vec<String>>,
+        /// The maximal number of diseases to report.
+        pub max_results: usize,
+        /// What should information content be based on.
+        #[serde(default = "IcBasedOn::default")]
+        pub ic_base: IcBasedOn,
+        /// The similarity method to use.
+        #[serde(default = "SimilarityMethod::default")]
+        pub similarity: SimilarityMethod,
+        /// The score combiner.
+        #[serde(default = "ScoreCombiner::default")]
+        pub combiner: ScoreCombiner,
+        /// Whether the annotated terms absent from the query were weighted by their
+        /// frequency.
+        #[serde(default, skip_serializing_if = "crate::common::is_false")]
+        pub frequency_weighted: bool,
+        /// The patient's onset that candidates were checked against.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub onset: Option<String>,
+    }
+
+    /// Result container data structure for the disease ranking.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+    pub struct HpoSimTermDiseaseResult {
+        /// Version information.
+        pub version: Version,
+        /// The original query records.
+        pub query: HpoSimTermDiseaseQuery,
+        /// The resulting records for the top-scoring diseases.
+        pub result: Vec<HpoSimTermDiseaseResultEntry>,
+    }
+
+    /// Store score for a disease with information on individual terms.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+    pub struct HpoSimTermDiseaseResultEntry {
+        /// The disease ID, e.g., `OMIM:616145`.
+        pub disease_id: String,
+        /// The disease name.
+        pub name: String,
+        /// The raw score as computed with the configured similarity method and combiner.
+        pub raw_score: f32,
+        /// Whether the annotated onset is compatible with the patient's onset, only if
+        /// both are known.
+        #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+        pub onset_compatible: Option<bool>,
+        /// Details on individual terms.
+        #[serde(default = "Option::default")]
+        pub terms: Option<Vec<HpoSimTermDiseaseTermDetails>>,
+        /// The genes associated with the disease.
+        pub genes: Vec<Gene>,
+    }
+
+    /// Detailed term scores for a disease.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+    pub struct HpoSimTermDiseaseTermDetails {
+        /// The query HPO term.
+        pub term_query: Option<HpoTerm>,
+        /// The disease's HPO term.
+        pub term_disease: HpoTerm,
+        /// The similarity score, negative for the penalty of an excluded term.
         pub score: f32,
+        /// Whether `term_query` is an excluded term annotated to the disease.
+        #[serde(default, skip_serializing_if = "crate::common::is_false")]
+        pub excluded: bool,
     }
 }
 
+/// Convert the term IDs in `terms` into `HpoTerm`s with names.
+///
+/// # Panics
+///
+/// In the case that a term lookup fails.
+fn to_hpo_terms(terms: &HpoGroup, hpo: &Ontology) -> Vec<HpoTerm> {
+    terms
+        .iter()
+        .map(|t| {
+            let term = hpo.hpo(t).expect("could not resolve HPO term");
+            HpoTerm {
+                term_id: term.id().to_string(),
+                term_name: Some(term.name().to_string()),
+                onset: None,
+            }
+        })
+        .collect()
+}
+
+/// For each term in `annotated`, determine the query term from `patient` with the
+/// highest similarity.
+///
+/// Returns tuples of the best query term (if the score is positive), the annotated
+/// term, and the score, sorted by descending score.
+///
+/// # Panics
+///
+/// In the case that a term lookup fails.
+fn best_matches(
+    patient: &HpoGroup,
+    annotated: &HpoGroup,
+    hpo: &Ontology,
+    sim: &impl Similarity,
+) -> Vec<(Option<HpoTerm>, HpoTerm, f32)> {
+    let mut result = annotated
+        .iter()
+        .map(|annotated_term_id| {
+            let annotated_term = hpo
+                .hpo(annotated_term_id)
+                .expect("annotated HPO term not found");
+            let (best_term, best_score) = patient
+                .iter()
+                .map(|query_term_id| {
+                    let query_term = hpo.hpo(query_term_id).expect("query HPO term not found");
+                    let score = sim.calculate(&annotated_term, &query_term);
+                    (Some(query_term), score)
+                })
+                .max_by(|(_, score1), (_, score2)| score1.total_cmp(score2))
+                .unwrap_or((None, 0.0));
+
+            let term_query = best_term
+                .filter(|_| best_score > 0.0)
+                .map(|best_term| HpoTerm {
+                    term_id: best_term.id().to_string(),
+                    term_name: Some(best_term.name().to_string()),
+                    onset: None,
+                });
+
+            (
+                term_query,
+                HpoTerm {
+                    term_id: annotated_term.id().to_string(),
+                    term_name: Some(annotated_term.name().to_string()),
+                    onset: None,
+                },
+                best_score,
+            )
+        })
+        .collect::<Vec<_>>();
+    result.sort_by(|a, b| b.2.total_cmp(&a.2));
+    result
+}
+
+/// Compute the matches of the `excluded` terms in `annotated` and the resulting penalty.
+///
+/// Returns the penalty and tuples of excluded term, annotated term, and the negated
+/// similarity for use in the term details.
+///
+/// # Panics
+///
+/// In the case that a term lookup fails.
+fn excluded_matches(
+    excluded: &HpoGroup,
+    annotated: &HpoGroup,
+    hpo: &Ontology,
+    sim: &impl Similarity,
+) -> (f32, Vec<(HpoTerm, HpoTerm, f32)>) {
+    let to_hpo_term = |term_id| {
+        let term = hpo.hpo(term_id).expect("could not resolve HPO term");
+        HpoTerm {
+            term_id: term.id().to_string(),
+            term_name: Some(term.name().to_string()),
+            onset: None,
+        }
+    };
+    let matches = phenomizer::excluded_matches(excluded, annotated, hpo, sim);
+    let penalty = phenomizer::excluded_penalty(excluded, &matches);
+    let details = matches
+        .into_iter()
+        .map(|(term_excluded, term_annotated, score)| {
+            (
+                to_hpo_term(term_excluded),
+                to_hpo_term(term_annotated),
+                -score,
+            )
+        })
+        .collect();
+    (penalty, details)
+}
+
 /// Run the actual phenotypic similarity query for patient terms and list of
 /// genes.
 ///
 /// # Arguments
 ///
 /// * `patient`: The query/patient HPO terms.
-/// * `genes`: The list of genes to score.
+/// * `excluded`: The HPO terms explicitly excluded in the patient, genes annotated
+///   with these terms or their descendants are penalized.
+/// * `genes`: The list of genes to score, all genes with annotations if `None`.
 /// * `hpo`: The HPO ontology.
-/// * `db`: The `RocksDB` instance for the Resnik P-values.
+/// * `ncbi_to_hgnc`: Map from NCBI gene ID to HGNC gene ID.
+/// * `scoring`: Configuration of the similarity computation.
+/// * `limits`: Limits on the reported genes.
+/// * `simulation`: Optional simulation store for computing P-values, only used if it
+///   was created with the same `scoring` and without `weighting`.
+/// * `weighting`: Optional weighting of the scores by annotation frequency and onset.
 ///
 /// # Returns
 ///
@@ -137,199 +642,713 @@ pub mod query_result {
 /// # Panics
 ///
 /// In the case that a term or database lookup fails.
-#[allow(clippy::cast_possible_truncation)]
-#[allow(clippy::cast_precision_loss)]
-#[allow(clippy::too_many_lines)]
+#[allow(clippy::too_many_lines, clippy::too_many_arguments)]
 pub fn run_query<S>(
     patient: &HpoGroup,
-    genes: &Vec<&hpo::annotations::Gene>,
+    excluded: &HpoGroup,
+    genes: Option<&[&hpo::annotations::Gene]>,
     hpo: &Ontology,
     ncbi_to_hgnc: &HashMap<u32, String, S>,
+    scoring: ScoringArgs,
+    limits: ResultLimits,
+    simulation: Option<&Store>,
+    weighting: ScoreWeighting,
 ) -> Result<query_result::HpoSimTermGeneResult, anyhow::Error>
 where
-    S: std::hash::BuildHasher,
+    S: std::hash::BuildHasher + Sync,
 {
-    let query = query_result::HpoSimTermGeneQuery {
-        terms: patient
+    let simulation = simulation.filter(|simulation| {
+        let matches = simulation.scoring() == scoring;
+        if !matches {
+            tracing::warn!(
+                "simulation store has settings {:?}, cannot compute P-values for {:?}",
+                simulation.scoring(),
+                scoring
+            );
+        } else if !weighting.is_none() {
+            tracing::warn!("simulation store is not weighted, cannot compute P-values");
+        }
+        matches && weighting.is_none()
+    });
+    let patient_onset = weighting.patient_onset()?;
+
+    let ScoringArgs {
+        ic_base,
+        similarity,
+        combiner,
+    } = scoring;
+    let sim: Builtins = to_pairwise_sim(similarity, ic_base);
+
+    let to_result_gene = |gene: &hpo::annotations::Gene| query_result::Gene {
+        entrez_id: gene.id().as_u32(),
+        gene_symbol: gene.name().to_string(),
+        hgnc_id: ncbi_to_hgnc.get(&gene.id().as_u32()).cloned(),
+    };
+    let mut query = query_result::HpoSimTermGeneQuery {
+        terms: to_hpo_terms(patient, hpo),
+        excluded_terms: to_hpo_terms(excluded, hpo),
+        genes: genes
+            .unwrap_or_default()
             .iter()
-            .map(|t| {
-                let term = hpo.hpo(t).expect("could not resolve HPO term");
-                HpoTerm {
-                    term_id: term.id().to_string(),
-                    term_name: Some(term.name().to_string()),
-                }
-            })
+            .map(|gene| to_result_gene(gene))
             .collect(),
-        genes: Vec::new(),
+        method: RankingMethod::Similarity,
+        ic_base: Some(ic_base),
+        similarity: Some(similarity),
+        combiner: Some(combiner),
+        frequency_weighted: weighting.frequencies.is_some(),
+        onset: weighting.onset.map(|(onset, _)| onset.to_string()),
+        max_results: limits.max_results,
+        min_score: limits.min_score,
     };
-    let mut result = query_result::HpoSimTermGeneResult {
-        version: crate::common::Version::new(&hpo.hpo_version()),
-        query,
-        result: Vec::new(),
+    // Sort genes for reproducibility.
+    query.genes.sort();
+
+    // Score all genes in parallel, sorting all genes by ID for reproducibility.
+    let genes = if let Some(genes) = genes {
+        genes.to_vec()
+    } else {
+        let mut genes = hpo.genes().collect::<Vec<_>>();
+        genes.sort_by_key(|gene| gene.id().as_u32());
+        genes
     };
-    for gene in genes {
-        let ncbi_gene_id = gene.id().as_u32();
-        tracing::debug!("gene = {:?}", gene);
-        let raw_score = phenomizer::score(
-            patient,
-            &gene
+    let scored = genes
+        .into_par_iter()
+        .map(|gene| {
+            tracing::debug!("gene = {:?}", gene);
+            let gene_terms = gene
                 .to_hpo_set(hpo)
                 .child_nodes()
                 .without_modifier()
                 .into_iter()
-                .collect::<HpoGroup>(),
-            hpo,
-        );
+                .collect::<HpoGroup>();
+            let (penalty, excluded_details) = excluded_matches(excluded, &gene_terms, hpo, &sim);
+            let onset_compatible = patient_onset.and_then(|(patient, candidates)| {
+                onset::is_compatible(&patient, candidates.gene(gene.id().as_u32()))
+            });
+            let score = if let Some(frequencies) = weighting.frequencies {
+                phenomizer::score_weighted(patient, &gene_terms, hpo, &sim, combiner, |term_id| {
+                    frequencies.gene_weight(gene.id().as_u32(), term_id)
+                })
+            } else {
+                phenomizer::score(patient, &gene_terms, hpo, &sim, combiner)
+            };
+            let raw_score = score * onset::score_factor(onset_compatible) - penalty;
+            let p_value = if let Some(simulation) = simulation {
+                simulation.p_value(gene.id().as_u32(), patient.len(), raw_score)?
+            } else {
+                None
+            };
+            Ok((
+                gene,
+                gene_terms,
+                raw_score,
+                p_value,
+                onset_compatible,
+                excluded_details,
+            ))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+    // Adjust P-values for multiple testing over all genes with P-value.
+    let p_values = scored
+        .iter()
+        .filter_map(|(_, _, _, p_value, _, _)| *p_value)
+        .collect::<Vec<_>>();
+    let mut adjusted_p_values = stats::benjamini_hochberg(&p_values).into_iter();
+    let mut scored = scored
+        .into_iter()
+        .map(
+            |(gene, gene_terms, raw_score, p_value, onset_compatible, excluded_details)| {
+                let adjusted_p_value = p_value.and_then(|_| adjusted_p_values.next());
+                (
+                    gene,
+                    gene_terms,
+                    raw_score,
+                    p_value,
+                    adjusted_p_value,
+                    onset_compatible,
+                    excluded_details,
+                )
+            },
+        )
+        .collect::<Vec<_>>();
+
+    // Sort output records by raw score for reproducibility and apply limits.
+    scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+    if let Some(min_score) = limits.min_score {
+        scored.retain(|(_, _, raw_score, _, _, _, _)| *raw_score >= min_score);
+    }
+    if let Some(max_results) = limits.max_results {
+        scored.truncate(max_results);
+    }
+
+    // For each term in the reported genes, provide query term with the highest similarity.
+    let result =
+        scored
+            .into_par_iter()
+            .map(
+                |(
+                    gene,
+                    gene_terms,
+                    raw_score,
+                    p_value,
+                    adjusted_p_value,
+                    onset_compatible,
+                    excluded_details,
+                )| {
+                    let mut terms = best_matches(patient, &gene_terms, hpo, &sim)
+                        .into_iter()
+                        .map(|(term_query, term_gene, score)| HpoSimTermGeneTermDetails {
+                            term_query,
+                            term_gene,
+                            score,
+                            excluded: false,
+                        })
+                        .collect::<Vec<_>>();
+                    terms.extend(excluded_details.into_iter().map(
+                        |(term_query, term_gene, score)| HpoSimTermGeneTermDetails {
+                            term_query: Some(term_query),
+                            term_gene,
+                            score,
+                            excluded: true,
+                        },
+                    ));
+                    query_result::HpoSimTermGeneResultEntry {
+                        gene_symbol: gene.name().to_string(),
+                        raw_score,
+                        p_value,
+                        adjusted_p_value,
+                        onset_compatible,
+                        terms: Some(terms),
+                    }
+                },
+            )
+            .collect();
+
+    Ok(query_result::HpoSimTermGeneResult {
+        version: crate::common::Version::new(&hpo.hpo_version()),
+        query,
+        result,
+    })
+}
+
+/// Rank `genes` (or all genes) by enrichment of the `patient` terms in their annotations.
+///
+/// This is an alternative to the similarity-based `run_query`, see
+/// `crate::enrichment::gene_enrichment` for the test.  The raw score is `-log10` of
+/// the P-value, which is clamped to `f64::MIN_POSITIVE` to keep the score finite on
+/// underflow, and no term details are reported.  The similarity settings are not
+/// used and thus not reported in the query.
+///
+/// # Errors
+///
+/// In the case that there is a problem with query execution.
+pub fn run_enrichment_query<S>(
+    patient: &HpoGroup,
+    genes: Option<&[&hpo::annotations::Gene]>,
+    hpo: &Ontology,
+    ncbi_to_hgnc: &HashMap<u32, String, S>,
+    limits: ResultLimits,
+) -> Result<query_result::HpoSimTermGeneResult, anyhow::Error>
+where
+    S: std::hash::BuildHasher,
+{
+    let to_result_gene = |gene: &hpo::annotations::Gene| query_result::Gene {
+        entrez_id: gene.id().as_u32(),
+        gene_symbol: gene.name().to_string(),
+        hgnc_id: ncbi_to_hgnc.get(&gene.id().as_u32()).cloned(),
+    };
+    let mut query = query_result::HpoSimTermGeneQuery {
+        terms: to_hpo_terms(patient, hpo),
+        excluded_terms: Vec::new(),
+        genes: genes
+            .unwrap_or_default()
+            .iter()
+            .map(|gene| to_result_gene(gene))
+            .collect(),
+        method: RankingMethod::Enrichment,
+        ic_base: None,
+        similarity: None,
+        combiner: None,
+        frequency_weighted: false,
+        onset: None,
+        max_results: limits.max_results,
+        min_score: limits.min_score,
+    };
+    query.genes.sort();
 
-        // For each term in the gene, provide query term with the highest similarity.
-        let mut terms = gene
-            .to_hpo_set(hpo)
+    #[allow(clippy::cast_possible_truncation)]
+    let mut result = crate::enrichment::gene_enrichment(patient, genes, hpo)
+        .into_iter()
+        .map(|entry| query_result::HpoSimTermGeneResultEntry {
+            gene_symbol: entry.gene.name().to_string(),
+            raw_score: (-entry.p_value.max(f64::MIN_POSITIVE).log10()) as f32,
+            p_value: Some(entry.p_value),
+            adjusted_p_value: Some(entry.adjusted_p_value),
+            onset_compatible: None,
+            terms: None,
+        })
+        .collect::<Vec<_>>();
+    if let Some(min_score) = limits.min_score {
+        result.retain(|entry| entry.raw_score >= min_score);
+    }
+    if let Some(max_results) = limits.max_results {
+        result.truncate(max_results);
+    }
+
+    Ok(query_result::HpoSimTermGeneResult {
+        version: crate::common::Version::new(&hpo.hpo_version()),
+        query,
+        result,
+    })
+}
+
+/// Resolve the candidate diseases for `run_disease_query`.
+///
+/// Returns tuples of disease ID, name, and the disease's HPO terms.  Disease IDs
+/// that cannot be resolved are skipped; IDs without prefix are interpreted as OMIM.
+fn candidate_diseases(
+    disease_ids: Option<&[String]>,
+    hpo: &Ontology,
+) -> Vec<(String, String, HpoGroup)> {
+    let to_candidate = |id: String, name: &str, set: hpo::HpoSet| {
+        let terms = set
             .child_nodes()
             .without_modifier()
             .into_iter()
-            .collect::<HpoGroup>()
-            .iter()
-            .map(|gene_term_id| {
-                let gene_term = hpo.hpo(gene_term_id).expect("gene HPO term not found");
-                let (best_term, best_score) = patient
-                    .iter()
-                    .map(|query_term_id| {
-                        let query_term = hpo.hpo(query_term_id).expect("query HPO term not found");
-                        let score = gene_term.similarity_score(
-                            &query_term,
-                            &Builtins::Resnik(hpo::term::InformationContentKind::Gene),
-                        );
-                        (query_term, score)
-                    })
-                    .max_by(|(_, score1), (_, score2)| score1.partial_cmp(score2).unwrap())
-                    .expect("could not determine best query term");
+            .collect::<HpoGroup>();
+        (id, name.to_string(), terms)
+    };
 
-                let term_query = if best_score > 0.0 {
-                    Some(HpoTerm {
-                        term_id: best_term.id().to_string(),
-                        term_name: Some(best_term.name().to_string()),
+    if let Some(disease_ids) = disease_ids {
+        disease_ids
+            .iter()
+            .filter_map(|disease_id| {
+                let lower_disease_id = disease_id.to_lowercase();
+                if let Some(orpha_id) = lower_disease_id.strip_prefix("orpha:") {
+                    let orpha_id = OrphaDiseaseId::try_from(orpha_id).ok()?;
+                    hpo.orpha_disease(&orpha_id).map(|disease| {
+                        to_candidate(
+                            disease.id().to_string(),
+                            disease.name(),
+                            disease.to_hpo_set(hpo),
+                        )
                     })
                 } else {
-                    None
-                };
-
-                HpoSimTermGeneTermDetails {
-                    term_query,
-                    term_gene: HpoTerm {
-                        term_id: gene_term.id().to_string(),
-                        term_name: Some(gene_term.name().to_string()),
-                    },
-                    score: best_score,
+                    let omim_id = lower_disease_id
+                        .strip_prefix("omim:")
+                        .unwrap_or(&lower_disease_id);
+                    let omim_id = OmimDiseaseId::try_from(omim_id).ok()?;
+                    hpo.omim_disease(&omim_id).map(|disease| {
+                        to_candidate(
+                            disease.id().to_string(),
+                            disease.name(),
+                            disease.to_hpo_set(hpo),
+                        )
+                    })
                 }
             })
-            .collect::<Vec<_>>();
-        terms.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+            .collect()
+    } else {
+        hpo.omim_diseases()
+            .map(|disease| {
+                to_candidate(
+                    disease.id().to_string(),
+                    disease.name(),
+                    disease.to_hpo_set(hpo),
+                )
+            })
+            .chain(hpo.orpha_diseases().map(|disease| {
+                to_candidate(
+                    disease.id().to_string(),
+                    disease.name(),
+                    disease.to_hpo_set(hpo),
+                )
+            }))
+            .collect()
+    }
+}
 
-        result.query.genes.push(query_result::Gene {
-            entrez_id: ncbi_gene_id,
-            gene_symbol: gene.name().to_string(),
-            hgnc_id: ncbi_to_hgnc.get(&ncbi_gene_id).cloned(),
-        });
+/// Run the phenotypic similarity query for patient terms against diseases.
+///
+/// # Arguments
+///
+/// * `patient`: The query/patient HPO terms.
+/// * `excluded`: The HPO terms explicitly excluded in the patient, diseases annotated
+///   with these terms or their descendants are penalized.
+/// * `disease_ids`: Optional list of disease IDs to score, e.g., `OMIM:616145` or
+///   `ORPHA:1234`; all OMIM and ORPHA diseases are scored if not given.
+/// * `max_results`: The number of top-scoring diseases to report.
+/// * `hpo`: The HPO ontology.
+/// * `ncbi_to_hgnc`: Map from NCBI gene ID to HGNC gene ID.
+/// * `disease_to_genes`: Map from disease ID to NCBI gene IDs.
+/// * `scoring`: Configuration of the similarity computation.
+/// * `weighting`: Optional weighting of the scores by annotation frequency and onset.
+///
+/// # Returns
+///
+/// * `Ok(query_result::HpoSimTermDiseaseResult)` if successful.
+///
+/// # Errors
+///
+/// In the case that there is a problem with query execution.
+///
+/// # Panics
+///
+/// In the case that a term lookup fails.
+#[allow(clippy::too_many_arguments)]
+pub fn run_disease_query<S1, S2>(
+    patient: &HpoGroup,
+    excluded: &HpoGroup,
+    disease_ids: Option<&[String]>,
+    max_results: usize,
+    hpo: &Ontology,
+    ncbi_to_hgnc: &HashMap<u32, String, S1>,
+    disease_to_genes: &HashMap<String, Vec<u32>, S2>,
+    scoring: ScoringArgs,
+    weighting: ScoreWeighting,
+) -> Result<query_result::HpoSimTermDiseaseResult, anyhow::Error>
+where
+    S1: std::hash::BuildHasher,
+    S2: std::hash::BuildHasher,
+{
+    let ScoringArgs {
+        ic_base,
+        similarity,
+        combiner,
+    } = scoring;
+    let sim: Builtins = to_pairwise_sim(similarity, ic_base);
+    let patient_onset = weighting.patient_onset()?;
 
-        result.result.push(query_result::HpoSimTermGeneResultEntry {
-            gene_symbol: gene.name().to_string(),
-            raw_score,
-            terms: Some(terms),
-        });
-    }
+    // Score all candidate diseases in parallel and keep the top-scoring ones.
+    let mut scored = candidate_diseases(disease_ids, hpo)
+        .into_par_iter()
+        .filter(|(_, _, terms)| !terms.is_empty())
+        .map(|(disease_id, name, terms)| {
+            let (penalty, excluded_details) = excluded_matches(excluded, &terms, hpo, &sim);
+            let onset_compatible = patient_onset.and_then(|(patient, candidates)| {
+                onset::is_compatible(&patient, candidates.disease(&disease_id))
+            });
+            let score = if let Some(frequencies) = weighting.frequencies {
+                phenomizer::score_weighted(patient, &terms, hpo, &sim, combiner, |term_id| {
+                    frequencies.disease_weight(&disease_id, term_id)
+                })
+            } else {
+                phenomizer::score(patient, &terms, hpo, &sim, combiner)
+            };
+            let raw_score = score * onset::score_factor(onset_compatible) - penalty;
+            (
+                disease_id,
+                name,
+                terms,
+                raw_score,
+                onset_compatible,
+                excluded_details,
+            )
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.3.total_cmp(&a.3).then_with(|| a.0.cmp(&b.0)));
+    scored.truncate(max_results);
 
-    // Sort genes for reproducibility.
-    result.query.genes.sort();
+    // Only compute term details and genes for the reported diseases.
+    let result = scored
+        .into_iter()
+        .map(
+            |(disease_id, name, terms, raw_score, onset_compatible, excluded_details)| {
+                let mut terms = best_matches(patient, &terms, hpo, &sim)
+                    .into_iter()
+                    .map(
+                        |(term_query, term_disease, score)| HpoSimTermDiseaseTermDetails {
+                            term_query,
+                            term_disease,
+                            score,
+                            excluded: false,
+                        },
+                    )
+                    .collect::<Vec<_>>();
+                terms.extend(excluded_details.into_iter().map(
+                    |(term_query, term_disease, score)| HpoSimTermDiseaseTermDetails {
+                        term_query: Some(term_query),
+                        term_disease,
+                        score,
+                        excluded: true,
+                    },
+                ));
+                let genes = disease_to_genes
+                    .get(&disease_id)
+                    .map(|ncbi_gene_ids| {
+                        ncbi_gene_ids
+                            .iter()
+                            .filter_map(|ncbi_gene_id| hpo.gene(&GeneId::from(*ncbi_gene_id)))
+                            .map(|gene| query_result::Gene {
+                                entrez_id: gene.id().as_u32(),
+                                gene_symbol: gene.name().to_string(),
+                                hgnc_id: ncbi_to_hgnc.get(&gene.id().as_u32()).cloned(),
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
 
-    // Sort output records by raw score for reproducibility.
-    result
-        .result
-        .sort_by(|a, b| b.raw_score.partial_cmp(&a.raw_score).unwrap());
+                query_result::HpoSimTermDiseaseResultEntry {
+                    disease_id,
+                    name,
+                    raw_score,
+                    onset_compatible,
+                    terms: Some(terms),
+                    genes,
+                }
+            },
+        )
+        .collect();
 
-    Ok(result)
+    Ok(query_result::HpoSimTermDiseaseResult {
+        version: crate::common::Version::new(&hpo.hpo_version()),
+        query: query_result::HpoSimTermDiseaseQuery {
+            terms: to_hpo_terms(patient, hpo),
+            excluded_terms: to_hpo_terms(excluded, hpo),
+            disease_ids: disease_ids.map(<[String]>::to_vec),
+            max_results,
+            ic_base,
+            similarity,
+            combiner,
+            frequency_weighted: weighting.frequencies.is_some(),
+            onset: weighting.onset.map(|(onset, _)| onset.to_string()),
+        },
+        result,
+    })
 }
 
-/// Main entry point for `query` sub command.
-///
-/// # Errors
+/// Open `args.output` for writing, or stdout if not given.
+fn open_output(args: &Args) -> Result<Box<dyn std::io::Write>, anyhow::Error> {
+    Ok(if let Some(output) = &args.output {
+        Box::new(std::io::BufWriter::new(std::fs::File::create(output)?))
+    } else {
+        Box::new(std::io::stdout())
+    })
+}
+
+/// The patient as given on the command line.
+struct Patient<'a> {
+    /// The observed HPO terms.
+    observed: HpoGroup,
+    /// The explicitly excluded HPO terms.
+    excluded: HpoGroup,
+    /// The genes from the phenopacket, if requested with `--phenopacket-genes`.
+    genes: Option<Vec<&'a hpo::annotations::Gene>>,
+    /// The onset of the patient, i.e., the earliest onset given for the observed terms.
+    onset: Option<String>,
+}
+
+/// Load HPO term IDs from a JSON file with a list of `HpoTerm` records.
 ///
-/// In the case of query execution failure.
+/// Returns the terms and the earliest onset given for the terms, if any.
 ///
 /// # Panics
 ///
-/// In the case of term lookup failure.
-pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
-    tracing::info!("args_common = {:?}", &args_common);
-    tracing::info!("args = {:?}", &args);
+/// In the case that a term ID is invalid.
+fn load_terms_json(path: &str) -> Result<(HpoGroup, Option<String>), anyhow::Error> {
+    let terms_json = std::fs::read_to_string(path)?;
+    let terms: Vec<HpoTerm> = serde_json::from_str(&terms_json)?;
+    let mut group = HpoGroup::new();
+    for t in &terms {
+        group.insert(
+            HpoTermId::try_from(t.term_id.as_str())
+                .unwrap_or_else(|_| panic!("term {} no valid HPO term ID", &t.term_id)),
+        );
+    }
+    let onset = onset::earliest(terms.iter().filter_map(|t| t.onset.as_deref()))?;
+    Ok((group, onset.map(str::to_string)))
+}
 
-    if let Some(log::Level::Trace | log::Level::Debug) = args_common.verbose.log_level() {
-        std::env::set_var("RUST_LOG", "debug");
-        env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+/// The annotations of the candidates from `phenotype.hpoa` used for weighting.
+#[derive(Debug, Default)]
+struct CandidateAnnotations {
+    /// The annotation frequencies, if requested with `--frequency-weighted`.
+    frequencies: Option<AnnotationFrequencies>,
+    /// The annotated onsets, if the patient has an onset.
+    onsets: Option<CandidateOnsets>,
+}
+
+/// Load the annotation frequencies (if `frequency_weighted`) and the annotated onsets
+/// (if `with_onsets`) of genes and diseases from `phenotype.hpoa` in `path_hpo_dir`.
+fn load_candidate_annotations<S>(
+    path_hpo_dir: &str,
+    disease_to_genes: &HashMap<String, Vec<u32>, S>,
+    frequency_weighted: bool,
+    with_onsets: bool,
+) -> Result<CandidateAnnotations, anyhow::Error>
+where
+    S: std::hash::BuildHasher,
+{
+    if !frequency_weighted && !with_onsets {
+        return Ok(CandidateAnnotations::default());
     }
+    let diseases = crate::index::load_diseases(path_hpo_dir)?;
+    Ok(CandidateAnnotations {
+        frequencies: frequency_weighted
+            .then(|| AnnotationFrequencies::new(&diseases, disease_to_genes)),
+        onsets: with_onsets.then(|| CandidateOnsets::new(&diseases, disease_to_genes)),
+    })
+}
 
-    tracing::info!("Loading HPO...");
-    let before_loading = Instant::now();
-    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
-    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+/// Load the annotation frequencies (if `frequency_weighted`) and the annotated onsets
+/// (if `with_onsets`) of genes from `phenotype.hpoa` and `genes_to_phenotype.txt` in
+/// `path_hpo_dir`.
+fn load_gene_annotations(
+    path_hpo_dir: &str,
+    frequency_weighted: bool,
+    with_onsets: bool,
+) -> Result<CandidateAnnotations, anyhow::Error> {
+    if !frequency_weighted && !with_onsets {
+        return Ok(CandidateAnnotations::default());
+    }
+    let path_genes_to_phenotype = format!("{path_hpo_dir}/genes_to_phenotype.txt");
+    let disease_to_genes =
+        crate::common::genes_to_phenotype::load_disease_to_genes(path_genes_to_phenotype)?;
+    load_candidate_annotations(
+        path_hpo_dir,
+        &disease_to_genes,
+        frequency_weighted,
+        with_onsets,
+    )
+}
 
-    tracing::info!("Loading genes...");
-    let before_load_genes = Instant::now();
-    let genes_json = std::fs::read_to_string(&args.path_genes_json)?;
-    let genes: Vec<Gene> = serde_json::from_str(&genes_json)?;
-    let mut missing_genes = Vec::new();
-    let genes = genes
-        .iter()
-        .filter_map(|g| {
-            let mapped = hpo.gene_by_name(&g.gene_symbol);
-            if mapped.is_none() {
-                missing_genes.push(g.clone());
-            }
-            mapped
-        })
-        .collect::<Vec<_>>();
-    tracing::info!("... done loadin genes in {:?}", before_load_genes.elapsed());
+/// Load the patient's terms and, if requested, the genes from the phenopacket.
+fn load_patient<'a>(
+    args: &Args,
+    hpo: &'a Ontology,
+    ncbi_to_hgnc: &HashMap<u32, String>,
+) -> Result<Patient<'a>, anyhow::Error> {
+    if let Some(path_phenopacket) = &args.path_phenopacket {
+        tracing::info!("Loading phenopacket...");
+        let before_load_phenopacket = Instant::now();
+        let hgnc_to_ncbi = crate::common::hgnc_xlink::inverse_hashmap(ncbi_to_hgnc);
+        let extracted =
+            phenopacket::extract(&phenopacket::load(path_phenopacket)?, hpo, &hgnc_to_ncbi);
+        tracing::info!(
+            "... done loading phenopacket {} in {:?}",
+            &extracted.id,
+            before_load_phenopacket.elapsed()
+        );
+        return Ok(Patient {
+            observed: extracted.observed,
+            excluded: extracted.excluded,
+            genes: args.phenopacket_genes.then_some(extracted.genes),
+            onset: extracted.onset,
+        });
+    }
 
     tracing::info!("Loading (patient/query) HPO term ids...");
     let before_load_genes = Instant::now();
-    let query_json = std::fs::read_to_string(&args.path_terms_json)?;
-    let query: Vec<HpoTerm> = serde_json::from_str(&query_json)?;
-    let query = query
-        .iter()
-        .map(|t| {
-            HpoTermId::try_from(t.term_id.as_str())
-                .unwrap_or_else(|_| panic!("term {} no valid HPO term ID", &t.term_id))
-        })
-        .collect::<Vec<_>>();
-    let query = {
-        let mut group = HpoGroup::new();
-        for term in query {
-            group.insert(term);
-        }
-        group
+    let path_terms_json = args
+        .path_terms_json
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("--path-terms-json or --path-phenopacket required"))?;
+    let (observed, onset) = load_terms_json(path_terms_json)?;
+    let excluded = if let Some(path_excluded_terms_json) = &args.path_excluded_terms_json {
+        load_terms_json(path_excluded_terms_json)?.0
+    } else {
+        HpoGroup::new()
     };
     tracing::info!(
         "... done loading HPO IDs in {:?}",
         before_load_genes.elapsed()
     );
+    Ok(Patient {
+        observed,
+        excluded,
+        genes: None,
+        onset,
+    })
+}
 
-    tracing::info!("Loading HGNC xlink...");
-    let before_load_xlink = Instant::now();
-    let path_hgnc_xlink = format!("{}/hgnc_xlink.tsv", args.path_hpo_dir);
-    let ncbi_to_hgnc = crate::common::hgnc_xlink::load_ncbi_to_hgnc(path_hgnc_xlink)?;
-    tracing::info!(
-        "... done loading HGNC xlink in {:?}",
-        before_load_xlink.elapsed()
-    );
+/// Rank the genes from `args.path_genes_json` and write the result.
+fn run_genes(
+    args: &Args,
+    hpo: &Ontology,
+    patient: Patient,
+    ncbi_to_hgnc: &HashMap<u32, String>,
+) -> Result<(), anyhow::Error> {
+    let genes = if patient.genes.is_some() {
+        patient.genes
+    } else if let Some(path_genes_json) = &args.path_genes_json {
+        tracing::info!("Loading genes...");
+        let before_load_genes = Instant::now();
+        let genes_json = std::fs::read_to_string(path_genes_json)?;
+        let genes: Vec<Gene> = serde_json::from_str(&genes_json)?;
+        let genes = genes
+            .iter()
+            .filter_map(|g| {
+                let mapped = hpo.gene_by_name(&g.gene_symbol);
+                if mapped.is_none() {
+                    tracing::warn!("gene {} not found in HPO annotations", &g.gene_symbol);
+                }
+                mapped
+            })
+            .collect::<Vec<_>>();
+        tracing::info!("... done loadin genes in {:?}", before_load_genes.elapsed());
+        Some(genes)
+    } else {
+        None
+    };
 
     tracing::info!("Starting priorization...");
     let before_priorization = Instant::now();
-    let result = run_query(&query, &genes, &hpo, &ncbi_to_hgnc)?;
+    let result = match args.method {
+        RankingMethod::Similarity => {
+            let simulation = args
+                .path_simulation_dir
+                .as_ref()
+                .map(|path| load_store(path, hpo))
+                .transpose()?;
+            let annotations = load_gene_annotations(
+                &args.path_hpo_dir,
+                args.frequency_weighted,
+                patient.onset.is_some(),
+            )?;
+            run_query(
+                &patient.observed,
+                &patient.excluded,
+                genes.as_deref(),
+                hpo,
+                ncbi_to_hgnc,
+                args.scoring,
+                args.limits,
+                simulation.as_ref(),
+                ScoreWeighting {
+                    frequencies: annotations.frequencies.as_ref(),
+                    onset: patient.onset.as_deref().zip(annotations.onsets.as_ref()),
+                },
+            )?
+        }
+        RankingMethod::Enrichment => {
+            if !patient.excluded.is_empty() {
+                tracing::warn!("excluded terms are ignored when ranking by enrichment");
+            }
+            if args.frequency_weighted {
+                tracing::warn!("--frequency-weighted is ignored when ranking by enrichment");
+            }
+            run_enrichment_query(
+                &patient.observed,
+                genes.as_deref(),
+                hpo,
+                ncbi_to_hgnc,
+                args.limits,
+            )?
+        }
+    };
     tracing::info!(
         "... done with prioritization in {:?}",
         before_priorization.elapsed()
     );
 
-    println!("{result:#?}");
+    output::write_gene_result(
+        &result,
+        args.output_format,
+        args.with_term_details,
+        open_output(args)?,
+    )?;
 
     tracing::info!(
         "{: >4} | {: <10} | {: >10} | {: >10}",
@@ -340,14 +1359,153 @@ pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow:
     );
     tracing::info!("     |            |");
     for (i, gene) in result.result.iter().enumerate() {
+        let p_value = gene
+            .p_value
+            .map(|p_value| format!("{p_value:.5}"))
+            .unwrap_or_default();
         tracing::info!(
-            "{: >4} | {: <10} | {: >10.5}",
+            "{: >4} | {: <10} | {: >10} | {: >10.5}",
             i + 1,
             gene.gene_symbol,
+            p_value,
             gene.raw_score
         );
     }
 
+    Ok(())
+}
+
+/// Rank the diseases and write the result.
+fn run_diseases(
+    args: &Args,
+    hpo: &Ontology,
+    patient: &Patient,
+    ncbi_to_hgnc: &HashMap<u32, String>,
+) -> Result<(), anyhow::Error> {
+    let disease_ids = if let Some(path_diseases_json) = &args.path_diseases_json {
+        tracing::info!("Loading diseases...");
+        let before_load_diseases = Instant::now();
+        let diseases_json = std::fs::read_to_string(path_diseases_json)?;
+        let diseases: Vec<Disease> = serde_json::from_str(&diseases_json)?;
+        tracing::info!(
+            "... done loading diseases in {:?}",
+            before_load_diseases.elapsed()
+        );
+        Some(
+            diseases
+                .into_iter()
+                .map(|d| d.disease_id)
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        None
+    };
+
+    tracing::info!("Loading disease to gene mapping...");
+    let before_load_genes = Instant::now();
+    let path_genes_to_phenotype = format!("{}/genes_to_phenotype.txt", args.path_hpo_dir);
+    let disease_to_genes =
+        crate::common::genes_to_phenotype::load_disease_to_genes(path_genes_to_phenotype)?;
+    tracing::info!(
+        "... done loading disease to gene mapping in {:?}",
+        before_load_genes.elapsed()
+    );
+
+    let annotations = load_candidate_annotations(
+        &args.path_hpo_dir,
+        &disease_to_genes,
+        args.frequency_weighted,
+        patient.onset.is_some(),
+    )?;
+
+    tracing::info!("Starting priorization...");
+    let before_priorization = Instant::now();
+    let result = run_disease_query(
+        &patient.observed,
+        &patient.excluded,
+        disease_ids.as_deref(),
+        args.limits.max_results.unwrap_or(100),
+        hpo,
+        ncbi_to_hgnc,
+        &disease_to_genes,
+        args.scoring,
+        ScoreWeighting {
+            frequencies: annotations.frequencies.as_ref(),
+            onset: patient.onset.as_deref().zip(annotations.onsets.as_ref()),
+        },
+    )?;
+    tracing::info!(
+        "... done with prioritization in {:?}",
+        before_priorization.elapsed()
+    );
+
+    output::write_disease_result(
+        &result,
+        args.output_format,
+        args.with_term_details,
+        open_output(args)?,
+    )?;
+
+    tracing::info!(
+        "{: >4} | {: <14} | {: >10} | {}",
+        "rank",
+        "disease",
+        "score",
+        "name"
+    );
+    tracing::info!("     |                |            |");
+    for (i, disease) in result.result.iter().enumerate() {
+        tracing::info!(
+            "{: >4} | {: <14} | {: >10.5} | {}",
+            i + 1,
+            disease.disease_id,
+            disease.raw_score,
+            disease.name
+        );
+    }
+
+    Ok(())
+}
+
+/// Main entry point for `query` sub command.
+///
+/// # Errors
+///
+/// In the case of query execution failure.
+///
+/// # Panics
+///
+/// In the case of term lookup failure.
+pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
+    tracing::info!("args_common = {:?}", &args_common);
+    tracing::info!("args = {:?}", &args);
+
+    if let Some(log::Level::Trace | log::Level::Debug) = args_common.verbose.log_level() {
+        std::env::set_var("RUST_LOG", "debug");
+        env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    }
+
+    tracing::info!("Loading HPO...");
+    let before_loading = Instant::now();
+    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
+    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+
+    tracing::info!("Loading HGNC xlink...");
+    let before_load_xlink = Instant::now();
+    let path_hgnc_xlink = format!("{}/hgnc_xlink.tsv", args.path_hpo_dir);
+    let ncbi_to_hgnc = crate::common::hgnc_xlink::load_ncbi_to_hgnc(path_hgnc_xlink)?;
+    tracing::info!(
+        "... done loading HGNC xlink in {:?}",
+        before_load_xlink.elapsed()
+    );
+
+    let patient = load_patient(args, &hpo, &ncbi_to_hgnc)?;
+
+    match args.mode {
+        QueryMode::Genes => run_genes(args, &hpo, patient, &ncbi_to_hgnc)?,
+        QueryMode::Diseases => run_diseases(args, &hpo, &patient, &ncbi_to_hgnc)?,
+    }
+
     tracing::info!("All done. Have a nice day!");
     Ok(())
 }
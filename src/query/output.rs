@@ -0,0 +1,306 @@
+//! Writing of `query` results in machine-readable formats.
+
+use std::str::FromStr;
+
+use strum::{EnumIter, IntoEnumIterator};
+
+use super::query_result::{HpoSimTermDiseaseResult, HpoSimTermGeneResult};
+
+/// The output format of the `query` command.
+#[derive(Default, Debug, Clone, Copy, EnumIter, PartialEq, Eq, derive_more::Display)]
+pub enum OutputFormat {
+    /// The full result as pretty-printed JSON.
+    #[default]
+    #[display("json")]
+    Json,
+    /// One JSON record per result entry.
+    #[display("jsonl")]
+    Jsonl,
+    /// One TSV row per result entry, or per entry and term with term details.
+    #[display("tsv")]
+    Tsv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutputFormat::iter()
+            .find(|m| m.to_string().as_str().eq(s))
+            .ok_or(anyhow::anyhow!("unknown output format: {}", s))
+    }
+}
+
+/// TSV row for a gene, optionally with the details for one of its terms.
+#[derive(Debug, serde::Serialize)]
+struct GeneRow<'a> {
+    /// The 1-based rank.
+    rank: usize,
+    /// The gene symbol.
+    gene_symbol: &'a str,
+    /// The raw score.
+    raw_score: f32,
+    /// The empirical P-value.
+    p_value: Option<f64>,
+    /// The adjusted P-value.
+    adjusted_p_value: Option<f64>,
+    /// The gene's term, with term details.
+    term_gene_id: Option<&'a str>,
+    /// The name of the gene's term, with term details.
+    term_gene_name: Option<&'a str>,
+    /// The best matching query term, with term details.
+    term_query_id: Option<&'a str>,
+    /// The name of the best matching query term, with term details.
+    term_query_name: Option<&'a str>,
+    /// The similarity of the terms, with term details.
+    term_score: Option<f32>,
+}
+
+/// TSV row for a disease, optionally with the details for one of its terms.
+#[derive(Debug, serde::Serialize)]
+struct DiseaseRow<'a> {
+    /// The 1-based rank.
+    rank: usize,
+    /// The disease ID.
+    disease_id: &'a str,
+    /// The disease name.
+    name: &'a str,
+    /// The raw score.
+    raw_score: f32,
+    /// The comma-separated symbols of the associated genes.
+    gene_symbols: String,
+    /// The disease's term, with term details.
+    term_disease_id: Option<&'a str>,
+    /// The name of the disease's term, with term details.
+    term_disease_name: Option<&'a str>,
+    /// The best matching query term, with term details.
+    term_query_id: Option<&'a str>,
+    /// The name of the best matching query term, with term details.
+    term_query_name: Option<&'a str>,
+    /// The similarity of the terms, with term details.
+    term_score: Option<f32>,
+}
+
+/// Construct a TSV writer on top of `writer`.
+fn tsv_writer<W: std::io::Write>(writer: W) -> csv::Writer<W> {
+    csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(true)
+        .from_writer(writer)
+}
+
+/// Write the gene ranking `result` to `writer` in the given `format`.
+///
+/// With `term_details`, TSV output contains one row per gene and gene term.
+///
+/// # Errors
+///
+/// In the case that writing fails.
+pub fn write_gene_result<W: std::io::Write>(
+    result: &HpoSimTermGeneResult,
+    format: OutputFormat,
+    term_details: bool,
+    mut writer: W,
+) -> Result<(), anyhow::Error> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, result)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+        OutputFormat::Jsonl => {
+            for entry in &result.result {
+                serde_json::to_writer(&mut writer, entry)?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Tsv => {
+            let mut csv_writer = tsv_writer(writer);
+            for (i, entry) in result.result.iter().enumerate() {
+                let row = GeneRow {
+                    rank: i + 1,
+                    gene_symbol: &entry.gene_symbol,
+                    raw_score: entry.raw_score,
+                    p_value: entry.p_value,
+                    adjusted_p_value: entry.adjusted_p_value,
+                    term_gene_id: None,
+                    term_gene_name: None,
+                    term_query_id: None,
+                    term_query_name: None,
+                    term_score: None,
+                };
+                match (term_details, &entry.terms) {
+                    (true, Some(terms)) if !terms.is_empty() => {
+                        for term in terms {
+                            csv_writer.serialize(GeneRow {
+                                term_gene_id: Some(&term.term_gene.term_id),
+                                term_gene_name: term.term_gene.term_name.as_deref(),
+                                term_query_id: term.term_query.as_ref().map(|t| t.term_id.as_str()),
+                                term_query_name: term
+                                    .term_query
+                                    .as_ref()
+                                    .and_then(|t| t.term_name.as_deref()),
+                                term_score: Some(term.score),
+                                ..row
+                            })?;
+                        }
+                    }
+                    _ => csv_writer.serialize(row)?,
+                }
+            }
+            csv_writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the disease ranking `result` to `writer` in the given `format`.
+///
+/// With `term_details`, TSV output contains one row per disease and disease term.
+///
+/// # Errors
+///
+/// In the case that writing fails.
+pub fn write_disease_result<W: std::io::Write>(
+    result: &HpoSimTermDiseaseResult,
+    format: OutputFormat,
+    term_details: bool,
+    mut writer: W,
+) -> Result<(), anyhow::Error> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, result)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+        OutputFormat::Jsonl => {
+            for entry in &result.result {
+                serde_json::to_writer(&mut writer, entry)?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Tsv => {
+            let mut csv_writer = tsv_writer(writer);
+            for (i, entry) in result.result.iter().enumerate() {
+                let gene_symbols = entry
+                    .genes
+                    .iter()
+                    .map(|gene| gene.gene_symbol.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let row = DiseaseRow {
+                    rank: i + 1,
+                    disease_id: &entry.disease_id,
+                    name: &entry.name,
+                    raw_score: entry.raw_score,
+                    gene_symbols,
+                    term_disease_id: None,
+                    term_disease_name: None,
+                    term_query_id: None,
+                    term_query_name: None,
+                    term_score: None,
+                };
+                match (term_details, &entry.terms) {
+                    (true, Some(terms)) if !terms.is_empty() => {
+                        for term in terms {
+                            csv_writer.serialize(DiseaseRow {
+                                gene_symbols: row.gene_symbols.clone(),
+                                term_disease_id: Some(&term.term_disease.term_id),
+                                term_disease_name: term.term_disease.term_name.as_deref(),
+                                term_query_id: term.term_query.as_ref().map(|t| t.term_id.as_str()),
+                                term_query_name: term
+                                    .term_query
+                                    .as_ref()
+                                    .and_then(|t| t.term_name.as_deref()),
+                                term_score: Some(term.score),
+                                ..row
+                            })?;
+                        }
+                    }
+                    _ => csv_writer.serialize(row)?,
+                }
+            }
+            csv_writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::common::{IcBasedOn, ScoreCombiner, SimilarityMethod, Version};
+    use crate::query::query_result::{
+        HpoSimTermGeneQuery, HpoSimTermGeneResult, HpoSimTermGeneResultEntry,
+        HpoSimTermGeneTermDetails,
+    };
+    use crate::query::HpoTerm;
+
+    use super::OutputFormat;
+
+    fn gene_result() -> HpoSimTermGeneResult {
+        HpoSimTermGeneResult {
+            version: Version {
+                hpo: "2024-07-01".to_string(),
+                viguno: "0.0.0".to_string(),
+            },
+            query: HpoSimTermGeneQuery {
+                terms: vec![HpoTerm {
+                    term_id: "HP:0000347".to_string(),
+                    term_name: Some("Micrognathia".to_string()),
+                    onset: None,
+                }],
+                excluded_terms: Vec::new(),
+                genes: Vec::new(),
+                method: crate::query::RankingMethod::default(),
+                ic_base: Some(IcBasedOn::default()),
+                similarity: Some(SimilarityMethod::default()),
+                combiner: Some(ScoreCombiner::default()),
+                frequency_weighted: false,
+                onset: None,
+                max_results: None,
+                min_score: None,
+            },
+            result: vec![HpoSimTermGeneResultEntry {
+                gene_symbol: "TGDS".to_string(),
+                raw_score: 1.5,
+                p_value: Some(0.01),
+                adjusted_p_value: None,
+                onset_compatible: None,
+                terms: Some(vec![HpoSimTermGeneTermDetails {
+                    term_query: Some(HpoTerm {
+                        term_id: "HP:0000347".to_string(),
+                        term_name: Some("Micrognathia".to_string()),
+                        onset: None,
+                    }),
+                    term_gene: HpoTerm {
+                        term_id: "HP:0000347".to_string(),
+                        term_name: Some("Micrognathia".to_string()),
+                        onset: None,
+                    },
+                    score: 1.5,
+                    excluded: false,
+                }]),
+            }],
+        }
+    }
+
+    #[rstest::rstest]
+    #[case(OutputFormat::Jsonl, false)]
+    #[case(OutputFormat::Tsv, false)]
+    #[case(OutputFormat::Tsv, true)]
+    fn write_gene_result(
+        #[case] format: OutputFormat,
+        #[case] term_details: bool,
+    ) -> Result<(), anyhow::Error> {
+        let mut buf = Vec::new();
+        super::write_gene_result(&gene_result(), format, term_details, &mut buf)?;
+
+        let output = String::from_utf8(buf)?;
+        insta::with_settings!({snapshot_suffix => format!("{format}_{term_details}")}, {
+            insta::assert_snapshot!(output);
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! Extraction of query terms and candidate genes from GA4GH phenopackets.
+
+use std::collections::HashMap;
+
+use hpo::{
+    annotations::{Gene, GeneId},
+    term::HpoGroup,
+    HpoTermId, Ontology,
+};
+use prost::Message as _;
+
+use crate::{
+    algos::onset::{self, OnsetRange},
+    pbs::phenopacket::{GeneDescriptor, Phenopacket, TimeElement},
+};
+
+/// Data extracted from a phenopacket.
+#[derive(Debug)]
+pub struct Extracted<'a> {
+    /// The phenopacket ID.
+    pub id: String,
+    /// The observed phenotypic features.
+    pub observed: HpoGroup,
+    /// The explicitly excluded phenotypic features.
+    pub excluded: HpoGroup,
+    /// The onset of the patient, i.e., the earliest onset of the observed features,
+    /// as HPO onset term ID or ISO8601 age.
+    pub onset: Option<String>,
+    /// The genes from the genomic interpretations, in order of appearance.
+    pub genes: Vec<&'a Gene>,
+}
+
+/// Parse a phenopacket from `buf`, either in JSON or in protobuf format.
+///
+/// # Errors
+///
+/// In the case that `buf` could not be parsed.
+pub fn parse(buf: &[u8], json: bool) -> Result<Phenopacket, anyhow::Error> {
+    if json {
+        Ok(serde_json::from_slice(buf)?)
+    } else {
+        Ok(Phenopacket::decode(buf)?)
+    }
+}
+
+/// Load a phenopacket from `path`, files ending in `.json` are read as JSON and
+/// all other files as protobuf.
+///
+/// # Errors
+///
+/// In the case that the file could not be read or parsed.
+pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Phenopacket, anyhow::Error> {
+    let path = path.as_ref();
+    let buf = std::fs::read(path)?;
+    parse(&buf, path.extension().is_some_and(|ext| ext == "json"))
+}
+
+/// Resolve a gene descriptor via its ID (HGNC or NCBI gene) or symbol.
+fn resolve_gene<'a, S>(
+    gene: &GeneDescriptor,
+    hpo: &'a Ontology,
+    hgnc_to_ncbi: &HashMap<String, u32, S>,
+) -> Option<&'a Gene>
+where
+    S: std::hash::BuildHasher,
+{
+    let by_id = if let Some(ncbi_gene_id) = gene
+        .value_id
+        .strip_prefix("NCBIGene:")
+        .or_else(|| gene.value_id.strip_prefix("ncbigene:"))
+    {
+        ncbi_gene_id
+            .parse::<u32>()
+            .ok()
+            .and_then(|ncbi_gene_id| hpo.gene(&GeneId::from(ncbi_gene_id)))
+    } else {
+        hgnc_to_ncbi
+            .get(&gene.value_id)
+            .and_then(|ncbi_gene_id| hpo.gene(&GeneId::from(*ncbi_gene_id)))
+    };
+    by_id.or_else(|| hpo.gene_by_name(&gene.symbol))
+}
+
+/// Convert the `time` element to an HPO onset term ID or ISO8601 age.
+///
+/// Age ranges are converted to their start and gestational ages to the antenatal
+/// onset term covering them.  Returns `None` for time elements without supported
+/// value.
+pub fn to_onset(time: &TimeElement) -> Option<String> {
+    if let Some(age) = &time.age {
+        Some(age.iso8601duration.clone())
+    } else if let Some(start) = time
+        .age_range
+        .as_ref()
+        .and_then(|range| range.start.as_ref())
+    {
+        Some(start.iso8601duration.clone())
+    } else if let Some(term) = &time.ontology_class {
+        Some(term.id.clone())
+    } else {
+        time.gestational_age.as_ref().map(|age| {
+            let term_id = match age.weeks {
+                ..=10 => "HP:0011460",
+                11..=13 => "HP:0034199",
+                14..=27 => "HP:0034198",
+                _ => "HP:0034197",
+            };
+            term_id.to_string()
+        })
+    }
+}
+
+/// Extract the observed and excluded terms, the onset, and the genes from
+/// `phenopacket`.
+///
+/// Terms that are not in `hpo`, invalid onsets, and genes that cannot be resolved
+/// are skipped.
+pub fn extract<'a, S>(
+    phenopacket: &Phenopacket,
+    hpo: &'a Ontology,
+    hgnc_to_ncbi: &HashMap<String, u32, S>,
+) -> Extracted<'a>
+where
+    S: std::hash::BuildHasher,
+{
+    let mut observed = HpoGroup::new();
+    let mut excluded = HpoGroup::new();
+    let mut onsets = Vec::new();
+    for feature in &phenopacket.phenotypic_features {
+        let Some(term) = feature
+            .r#type
+            .as_ref()
+            .and_then(|term| hpo.hpo(HpoTermId::from(term.id.clone())))
+        else {
+            tracing::warn!("skipping unknown phenotypic feature {:?}", &feature.r#type);
+            continue;
+        };
+        if feature.excluded {
+            excluded.insert(term.id());
+        } else {
+            observed.insert(term.id());
+            match feature.onset.as_ref().map(to_onset) {
+                Some(Some(onset)) if OnsetRange::parse(&onset).is_ok() => onsets.push(onset),
+                Some(_) => tracing::warn!("skipping invalid onset {:?}", &feature.onset),
+                None => (),
+            }
+        }
+    }
+    // All onsets are valid, thus `earliest` cannot fail.
+    let onset = onset::earliest(onsets.iter().map(String::as_str))
+        .ok()
+        .flatten()
+        .map(str::to_string);
+
+    let mut genes: Vec<&Gene> = Vec::new();
+    let descriptors = phenopacket
+        .interpretations
+        .iter()
+        .filter_map(|interpretation| interpretation.diagnosis.as_ref())
+        .flat_map(|diagnosis| diagnosis.genomic_interpretations.iter())
+        .filter_map(|genomic_interpretation| {
+            genomic_interpretation.gene.as_ref().or_else(|| {
+                genomic_interpretation
+                    .variant_interpretation
+                    .as_ref()
+                    .and_then(|variant| variant.variation_descriptor.as_ref())
+                    .and_then(|variation| variation.gene_context.as_ref())
+            })
+        });
+    for descriptor in descriptors {
+        if let Some(gene) = resolve_gene(descriptor, hpo, hgnc_to_ncbi) {
+            if !genes.iter().any(|other| other.id() == gene.id()) {
+                genes.push(gene);
+            }
+        } else {
+            tracing::warn!("skipping unknown gene {:?}", descriptor);
+        }
+    }
+
+    Extracted {
+        id: phenopacket.id.clone(),
+        observed,
+        excluded,
+        onset,
+        genes,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn parse_json() -> Result<(), anyhow::Error> {
+        let json = r#"{
+            "id": "case-1",
+            "subject": {"id": "proband"},
+            "phenotypicFeatures": [
+                {"type": {"id": "HP:0000347", "label": "Micrognathia"}, "onset": {"age": {"iso8601duration": "P2Y"}}},
+                {"type": {"id": "HP:0010442", "label": "Polydactyly"}, "excluded": true}
+            ],
+            "interpretations": [{
+                "id": "interpretation-1",
+                "progressStatus": "SOLVED",
+                "diagnosis": {
+                    "disease": {"id": "OMIM:616145"},
+                    "genomicInterpretations": [
+                        {"subjectOrBiosampleId": "proband", "gene": {"valueId": "HGNC:20324", "symbol": "TGDS"}},
+                        {
+                            "subjectOrBiosampleId": "proband",
+                            "variantInterpretation": {
+                                "variationDescriptor": {
+                                    "id": "var-1",
+                                    "geneContext": {"valueId": "NCBIGene:7273", "symbol": "TTN"}
+                                }
+                            }
+                        }
+                    ]
+                }
+            }]
+        }"#;
+
+        let phenopacket = super::parse(json.as_bytes(), true)?;
+        insta::assert_yaml_snapshot!(phenopacket);
+
+        // Round-trip through protobuf.
+        let buf = prost::Message::encode_to_vec(&phenopacket);
+        assert_eq!(super::parse(&buf, false)?, phenopacket);
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[case::age(r#"{"age": {"iso8601duration": "P2Y"}}"#, Some("P2Y"))]
+    #[case::age_range(
+        r#"{"ageRange": {"start": {"iso8601duration": "P1Y"}, "end": {"iso8601duration": "P3Y"}}}"#,
+        Some("P1Y")
+    )]
+    #[case::ontology_class(r#"{"ontologyClass": {"id": "HP:0003623"}}"#, Some("HP:0003623"))]
+    #[case::gestational_age(r#"{"gestationalAge": {"weeks": 20, "days": 3}}"#, Some("HP:0034198"))]
+    #[case::empty("{}", None)]
+    fn to_onset(#[case] json: &str, #[case] expected: Option<&str>) -> Result<(), anyhow::Error> {
+        let time: crate::pbs::phenopacket::TimeElement = serde_json::from_str(json)?;
+        assert_eq!(super::to_onset(&time).as_deref(), expected);
+        Ok(())
+    }
+}
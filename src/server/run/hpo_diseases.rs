@@ -0,0 +1,424 @@
+//! Implementation of `/hpo/diseases`.
+
+use std::sync::Arc;
+
+use actix_web::{
+    get,
+    web::{self, Data, Json, Path},
+};
+use hpo::{
+    annotations::{AnnotationId, GeneId},
+    HpoTermId,
+};
+
+use crate::{common::phenotype_hpoa::Disease, common::Version, server::run::WebServerData};
+
+use super::{CustomError, Match, ResultGene, ResultHpoTerm};
+
+/// The namespace of a disease ID.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DiseaseSource {
+    /// Online Mendelian Inheritance in Man.
+    Omim,
+    /// Orphanet.
+    Orpha,
+    /// DECIPHER.
+    Decipher,
+}
+
+impl DiseaseSource {
+    /// The prefix of the disease IDs, e.g., `OMIM`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            DiseaseSource::Omim => "OMIM",
+            DiseaseSource::Orpha => "ORPHA",
+            DiseaseSource::Decipher => "DECIPHER",
+        }
+    }
+}
+
+/// Parameters for `handle`.
+///
+/// This allows to query for OMIM, ORPHA, and DECIPHER diseases.  The first given of
+/// the following is interpreted.
+///
+/// - `disease_id` -- specify disease ID, e.g., `ORPHA:1388`, or a `MONDO:` ID to
+///   query for the OMIM and ORPHA diseases mapped to the MONDO disease and its
+///   subtypes
+/// - `name` -- specify the name to query for, with `match=fuzzy` this is a
+///   relevance-ranked full text search over the names and alternative titles
+///
+/// The following properties restrict and extend the result:
+///
+/// - `source` -- only return diseases from this namespace
+/// - `match` -- how to match the name
+/// - `ignore_case` -- whether to ignore case when matching the name
+/// - `max_results` -- the maximum number of records to return
+/// - `hpo_terms` -- whether to include `"hpo_terms"` in result
+/// - `genes` -- whether to include `"genes"` in result
+#[derive(
+    Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema, utoipa::IntoParams,
+)]
+pub struct HpoDiseasesQuery {
+    /// The disease ID or MONDO ID to search for.
+    pub disease_id: Option<String>,
+    /// The disease name to search for.
+    pub name: Option<String>,
+    /// The namespace to restrict the results to.
+    pub source: Option<DiseaseSource>,
+    /// The match mode, default is `Match::Exact`.
+    #[serde(alias = "match")]
+    pub match_: Option<Match>,
+    /// Whether case is insensitive, default is `false`.
+    pub ignore_case: Option<bool>,
+    /// Maximal number of results to return.
+    #[serde(default = "_default_max_results")]
+    pub max_results: usize,
+    /// Whether to include HPO terms.
+    #[serde(default = "_default_hpo_terms")]
+    pub hpo_terms: bool,
+    /// Whether to include genes.
+    #[serde(default = "_default_genes")]
+    pub genes: bool,
+}
+
+impl HpoDiseasesQuery {
+    /// Normalize the prefix of `disease_id` to upper case, e.g., `orpha:1388` to
+    /// `ORPHA:1388`.
+    fn with_normalized_prefix(self) -> Self {
+        Self {
+            disease_id: self.disease_id.map(|disease_id| {
+                if let Some((prefix, local)) = disease_id.split_once(':') {
+                    format!("{}:{}", prefix.trim().to_uppercase(), local.trim())
+                } else {
+                    disease_id
+                }
+            }),
+            ..self
+        }
+    }
+}
+
+/// Return default of `HpoDiseasesQuery::max_results`.
+fn _default_max_results() -> usize {
+    100
+}
+
+/// Return default of `HpoDiseasesQuery::hpo_terms`.
+fn _default_hpo_terms() -> bool {
+    false
+}
+
+/// Return default of `HpoDiseasesQuery::genes`.
+fn _default_genes() -> bool {
+    false
+}
+
+/// Result entry for `handle`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoDiseasesResultEntry {
+    /// The disease ID, e.g., `ORPHA:1388`.
+    pub disease_id: String,
+    /// The preferred disease name.
+    pub name: String,
+    /// Alternative titles of the disease.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alt_titles: Vec<String>,
+    /// The disease's associated HPO terms.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub hpo_terms: Option<Vec<ResultHpoTerm>>,
+    /// The disease's associated genes.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub genes: Option<Vec<ResultGene>>,
+    /// The IDs of the equivalent MONDO diseases, if MONDO is available.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mondo_ids: Vec<String>,
+    /// The full text search score, only for `match=fuzzy`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+}
+
+impl HpoDiseasesResultEntry {
+    /// Create a `ResultEntry` from a `Disease`.
+    pub fn from_disease_with_data(
+        disease: &Disease,
+        data: &WebServerData,
+        hpo_terms: bool,
+        genes: bool,
+    ) -> Self {
+        let hpo_terms = hpo_terms.then(|| {
+            disease
+                .hpo_terms
+                .iter()
+                .filter_map(|term_id| data.ontology.hpo(HpoTermId::from(term_id.clone())))
+                .map(|term| ResultHpoTerm {
+                    term_id: term.id().to_string(),
+                    name: term.name().to_string(),
+                })
+                .collect::<Vec<_>>()
+        });
+        let genes = genes.then(|| {
+            let mut result = data
+                .disease_to_genes
+                .get(&disease.disease_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|ncbi_gene_id| data.ontology.gene(&GeneId::from(*ncbi_gene_id)))
+                .map(|gene| ResultGene {
+                    ncbi_gene_id: gene.id().as_u32(),
+                    gene_symbol: gene.symbol().to_string(),
+                    hgnc_id: data.ncbi_to_hgnc.get(&gene.id().as_u32()).cloned(),
+                })
+                .collect::<Vec<_>>();
+            result.sort();
+            result
+        });
+
+        HpoDiseasesResultEntry {
+            disease_id: disease.disease_id.clone(),
+            name: disease.name.clone(),
+            alt_titles: disease.alt_titles.clone(),
+            hpo_terms,
+            genes,
+            mondo_ids: data
+                .mondo
+                .as_ref()
+                .map(|mondo| mondo.mondo_ids(&disease.disease_id).to_vec())
+                .unwrap_or_default(),
+            score: None,
+        }
+    }
+}
+
+/// Container for the result.
+#[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoDiseasesResult {
+    /// Version information.
+    pub version: Version,
+    /// The original query records.
+    pub query: HpoDiseasesQuery,
+    /// The resulting records, sorted by disease ID except for `match=fuzzy` where
+    /// the best match comes first.  For a MONDO ID, all mapped diseases are returned.
+    pub result: Vec<HpoDiseasesResultEntry>,
+}
+
+/// Lookup the disease with `disease_id` in `diseases`, sorted by ID.
+pub(crate) fn lookup<'a>(diseases: &'a [Disease], disease_id: &str) -> Option<&'a Disease> {
+    diseases
+        .binary_search_by(|disease| disease.disease_id.as_str().cmp(disease_id))
+        .ok()
+        .map(|idx| &diseases[idx])
+}
+
+/// Query for OMIM, ORPHA, and DECIPHER diseases from `phenotype.hpoa`.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoDiseases",
+    params(HpoDiseasesQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoDiseasesResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/diseases")]
+async fn handle(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoDiseasesQuery>,
+) -> actix_web::Result<Json<HpoDiseasesResult>, CustomError> {
+    let query = query.into_inner().with_normalized_prefix();
+    let match_ = query.match_.unwrap_or_default();
+    let ignore_case = query.ignore_case.unwrap_or_default();
+    let in_source = |disease: &Disease| {
+        query
+            .source
+            .is_none_or(|source| disease.source() == source.prefix())
+    };
+    let to_entry = |disease: &Disease| {
+        HpoDiseasesResultEntry::from_disease_with_data(disease, &data, query.hpo_terms, query.genes)
+    };
+    let mut result: Vec<HpoDiseasesResultEntry> = Vec::new();
+
+    if let Some(mondo_id) = query
+        .disease_id
+        .as_ref()
+        .filter(|disease_id| disease_id.starts_with("MONDO:"))
+    {
+        let mondo = data.mondo.as_ref().ok_or_else(|| {
+            CustomError::new(anyhow::anyhow!(
+                "MONDO is not available, cannot query {mondo_id}"
+            ))
+        })?;
+        result.extend(
+            mondo
+                .grouped_diseases(mondo_id)
+                .iter()
+                .filter_map(|disease_id| lookup(&data.diseases, disease_id))
+                .filter(|disease| in_source(disease))
+                .take(query.max_results)
+                .map(to_entry),
+        );
+    } else if let Some(disease_id) = &query.disease_id {
+        if let Some(disease) = lookup(&data.diseases, disease_id).filter(|d| in_source(d)) {
+            result.push(to_entry(disease));
+        }
+    } else if let Some(name) = &query.name {
+        if match_ == Match::Fuzzy {
+            let hits = data
+                .full_text_index
+                .search_diseases(
+                    name,
+                    query.source.map(DiseaseSource::prefix),
+                    query.max_results,
+                )
+                .map_err(CustomError::new)?;
+            for (disease_id, score) in hits {
+                if let Some(disease) = lookup(&data.diseases, &disease_id) {
+                    result.push(HpoDiseasesResultEntry {
+                        score: Some(score),
+                        ..to_entry(disease)
+                    });
+                }
+            }
+        } else {
+            let name = if ignore_case {
+                name.to_lowercase()
+            } else {
+                name.clone()
+            };
+            // Match against the primary name and the alternative titles.
+            let matches = |title: &str| {
+                let title = if ignore_case {
+                    title.to_lowercase()
+                } else {
+                    title.to_string()
+                };
+                match match_ {
+                    Match::Exact => title == name,
+                    Match::Prefix => title.starts_with(&name),
+                    Match::Suffix => title.ends_with(&name),
+                    Match::Contains => title.contains(&name),
+                    Match::Fuzzy => panic!("cannot happen here"),
+                }
+            };
+            for disease in data.diseases.iter().filter(|d| in_source(d)) {
+                if result.len() >= query.max_results {
+                    break;
+                }
+                if matches(&disease.name) || disease.alt_titles.iter().any(|title| matches(title)) {
+                    result.push(to_entry(disease));
+                }
+            }
+        }
+    }
+
+    Ok(Json(HpoDiseasesResult {
+        version: Version::new(&data.ontology.hpo_version()),
+        query,
+        result,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::{web_server_data, web_server_data_mondo};
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        uri: &str,
+    ) -> Result<super::HpoDiseasesResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+        let resp: super::HpoDiseasesResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[case::omim_id(
+        "omim_id",
+        "/api/v1/hpo/diseases?disease_id=OMIM:616145&hpo_terms=true&genes=true"
+    )]
+    #[case::orpha_id(
+        "orpha_id",
+        "/api/v1/hpo/diseases?disease_id=orpha:1388&hpo_terms=true&genes=true"
+    )]
+    #[case::decipher_id(
+        "decipher_id",
+        "/api/v1/hpo/diseases?disease_id=DECIPHER:1&hpo_terms=true"
+    )]
+    #[case::name_prefix(
+        "name_prefix",
+        "/api/v1/hpo/diseases?name=catel&match=prefix&ignore_case=true"
+    )]
+    #[case::name_fuzzy_orpha(
+        "name_fuzzy_orpha",
+        "/api/v1/hpo/diseases?name=Catel-Manzk+syndrom&match=fuzzy&source=orpha"
+    )]
+    #[actix_web::test]
+    async fn hpo_diseases(
+        #[case] suffix: &str,
+        #[case] uri: &str,
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let result = run_query(web_server_data.clone(), uri).await?;
+        insta::with_settings!({snapshot_suffix => suffix}, {
+            insta::assert_yaml_snapshot!(&result);
+        });
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_diseases_mondo_id(
+        web_server_data_mondo: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let disease_ids = |result: super::HpoDiseasesResult| {
+            result
+                .result
+                .into_iter()
+                .map(|entry| entry.disease_id)
+                .collect::<Vec<_>>()
+        };
+
+        // The grouping term resolves to the diseases of its descendants.
+        let result = run_query(
+            web_server_data_mondo.clone(),
+            "/api/v1/hpo/diseases?disease_id=MONDO:0015159",
+        )
+        .await?;
+        assert_eq!(disease_ids(result), vec!["OMIM:616145", "ORPHA:1388"]);
+
+        let result = run_query(
+            web_server_data_mondo.clone(),
+            "/api/v1/hpo/diseases?disease_id=MONDO:0014553&source=orpha",
+        )
+        .await?;
+        assert_eq!(result.result.len(), 1);
+        assert_eq!(result.result[0].disease_id, "ORPHA:1388");
+        assert_eq!(result.result[0].mondo_ids, vec!["MONDO:0014553"]);
+
+        let result = run_query(
+            web_server_data_mondo.clone(),
+            "/api/v1/hpo/diseases?disease_id=MONDO:9999999",
+        )
+        .await?;
+        assert!(disease_ids(result).is_empty());
+        Ok(())
+    }
+}
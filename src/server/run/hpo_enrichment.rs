@@ -0,0 +1,197 @@
+//! Implementation of `/hpo/enrichment/terms`.
+
+use std::sync::Arc;
+
+use actix_web::{
+    get,
+    web::{self, Data, Json, Path},
+};
+
+use hpo::annotations::AnnotationId;
+
+use crate::{
+    common::{PValueCorrection, Version},
+    enrichment::{resolve_gene, term_enrichment, TermEnrichment},
+    server::run::WebServerData,
+};
+
+use super::{CustomError, ResultGene};
+
+/// Parameters for `handle_terms`.
+///
+/// - `genes` -- the gene set, given as NCBI gene IDs, HGNC IDs, or symbols
+/// - `correction` -- the correction for multiple testing
+/// - `max_results` -- the maximal number of terms to return
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+pub struct RequestQuery {
+    /// The gene set, given as NCBI gene IDs, HGNC IDs, or symbols.
+    #[serde(deserialize_with = "super::vec_str_deserialize")]
+    pub genes: Vec<String>,
+    /// The correction for multiple testing.
+    #[serde(default = "PValueCorrection::default")]
+    pub correction: PValueCorrection,
+    /// Maximal number of results to return.
+    #[serde(default = "_default_max_results")]
+    pub max_results: usize,
+}
+
+/// Return default of `RequestQuery::max_results`.
+fn _default_max_results() -> usize {
+    100
+}
+
+/// Query as sent together with the response.
+///
+/// The difference is that the `genes` field is replaced by a vec.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoEnrichmentTermsQuery {
+    /// The gene set, given as NCBI gene IDs, HGNC IDs, or symbols.
+    pub genes: Vec<String>,
+    /// The correction for multiple testing.
+    pub correction: PValueCorrection,
+    /// Maximal number of results to return.
+    pub max_results: usize,
+}
+
+impl From<RequestQuery> for HpoEnrichmentTermsQuery {
+    fn from(query: RequestQuery) -> Self {
+        let RequestQuery {
+            genes,
+            correction,
+            max_results,
+        } = query;
+        Self {
+            genes,
+            correction,
+            max_results,
+        }
+    }
+}
+
+/// Container for the result.
+#[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoEnrichmentTermsResult {
+    /// Version information.
+    pub version: Version,
+    /// The original query records.
+    pub query: HpoEnrichmentTermsQuery,
+    /// The resolved genes of the gene set.
+    pub genes: Vec<ResultGene>,
+    /// The enriched terms, sorted by P-value.
+    pub result: Vec<TermEnrichment>,
+}
+
+/// Compute the term enrichment for `query`.
+fn compute(data: &WebServerData, query: HpoEnrichmentTermsQuery) -> HpoEnrichmentTermsResult {
+    let ontology = &data.ontology;
+    let genes = query
+        .genes
+        .iter()
+        .filter_map(|identifier| resolve_gene(identifier, ontology, &data.hgnc_to_ncbi))
+        .collect::<Vec<_>>();
+
+    let mut result = term_enrichment(&genes, ontology, query.correction);
+    result.truncate(query.max_results);
+
+    let mut genes = genes
+        .into_iter()
+        .map(|gene| ResultGene {
+            ncbi_gene_id: gene.id().as_u32(),
+            gene_symbol: gene.symbol().to_string(),
+            hgnc_id: data.ncbi_to_hgnc.get(&gene.id().as_u32()).cloned(),
+        })
+        .collect::<Vec<_>>();
+    genes.sort();
+    genes.dedup();
+
+    HpoEnrichmentTermsResult {
+        version: Version::new(&ontology.hpo_version()),
+        query,
+        genes,
+        result,
+    }
+}
+
+/// Query for the enrichment of HPO terms in a gene set.
+///
+/// The annotations are propagated to the ancestor terms and compared to all genes
+/// with HPO annotations using the hypergeometric test.  Genes that cannot be
+/// resolved are ignored.  The test is run on the blocking thread pool.
+#[utoipa::path(
+    get,
+    operation_id = "hpoEnrichmentTerms",
+    params(RequestQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoEnrichmentTermsResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/enrichment/terms")]
+async fn handle_terms(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<RequestQuery>,
+) -> actix_web::Result<Json<HpoEnrichmentTermsResult>, CustomError> {
+    let query = query.into_inner().into();
+    let result = web::block(move || compute(&data, query))
+        .await
+        .map_err(|e| CustomError::new(anyhow::anyhow!("problem computing enrichment: {}", e)))?;
+
+    Ok(Json(result))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        uri: &str,
+    ) -> Result<super::HpoEnrichmentTermsResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle_terms),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+        let resp: super::HpoEnrichmentTermsResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_enrichment_terms(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/enrichment/terms?genes=TGDS,HGNC:12403,2200&max_results=5"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_enrichment_terms_bonferroni(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/enrichment/terms?genes=TGDS,HGNC:12403,2200&max_results=5&correction=bonferroni"
+            )
+            .await?
+        );
+        Ok(())
+    }
+}
@@ -21,7 +21,9 @@ use super::{CustomError, Match, ResultHpoTerm};
 /// interpreted.
 ///
 /// - `gene_id` -- specify gene ID (either NCBI or HGNC gene ID)
-/// - `gene_symbol` -- specify the gene symbol
+/// - `gene_symbol` -- specify the gene symbol, outdated symbols are resolved to the
+///   current gene if unambiguous, with `match=fuzzy` this is a relevance-ranked full
+///   text search over the current, alias, and previous symbols and the gene names
 /// - `max_results` -- the maximnum number of records to return
 /// - `hpo_terms` -- whether to include `"hpo_terms"` in result
 ///
@@ -58,17 +60,7 @@ fn _default_hpo_terms() -> bool {
 }
 
 /// Result entry for `handle`.
-#[derive(
-    Debug,
-    Clone,
-    Eq,
-    PartialEq,
-    Ord,
-    PartialOrd,
-    serde::Serialize,
-    serde::Deserialize,
-    utoipa::ToSchema,
-)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct HpoGenesResultEntry {
     /// The gene's NCBI ID.
     pub gene_ncbi_id: u32,
@@ -79,6 +71,40 @@ pub struct HpoGenesResultEntry {
     /// The gene's associated HPO terms.
     #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
     pub hpo_terms: Option<Vec<ResultHpoTerm>>,
+    /// The full text search score, only for `match=fuzzy`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+}
+
+impl PartialEq for HpoGenesResultEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for HpoGenesResultEntry {}
+
+impl PartialOrd for HpoGenesResultEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HpoGenesResultEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (
+            self.gene_ncbi_id,
+            &self.gene_symbol,
+            &self.hgnc_id,
+            &self.hpo_terms,
+        )
+            .cmp(&(
+                other.gene_ncbi_id,
+                &other.gene_symbol,
+                &other.hgnc_id,
+                &other.hpo_terms,
+            ))
+    }
 }
 
 impl HpoGenesResultEntry {
@@ -109,6 +135,7 @@ impl HpoGenesResultEntry {
             gene_symbol: gene.name().to_string(),
             hgnc_id: ncbi_to_hgnc.get(&gene.id().as_u32()).cloned(),
             hpo_terms,
+            score: None,
         }
     }
 }
@@ -159,7 +186,14 @@ async fn handle(
             }?;
             ontology.gene(&gene_id)
         } else if let Some(gene_symbol) = &query.gene_symbol {
-            ontology.gene_by_name(gene_symbol)
+            if let Some(gene) = ontology.gene_by_name(gene_symbol) {
+                Some(gene)
+            } else {
+                data.full_text_index
+                    .resolve_gene_symbol(gene_symbol)
+                    .map_err(CustomError::new)?
+                    .and_then(|ncbi_gene_id| ontology.gene(&GeneId::from(ncbi_gene_id)))
+            }
         } else {
             None
         };
@@ -171,6 +205,26 @@ async fn handle(
                 &data.ncbi_to_hgnc,
             ));
         }
+    } else if match_ == Match::Fuzzy {
+        if let Some(gene_symbol) = &query.gene_symbol {
+            let hits = data
+                .full_text_index
+                .search_genes(gene_symbol, query.max_results)
+                .map_err(CustomError::new)?;
+            for (ncbi_gene_id, score) in hits {
+                if let Some(gene) = ontology.gene(&GeneId::from(ncbi_gene_id)) {
+                    result.push(HpoGenesResultEntry {
+                        score: Some(score),
+                        ..HpoGenesResultEntry::from_gene_with_ontology(
+                            gene,
+                            ontology,
+                            query.hpo_terms,
+                            &data.ncbi_to_hgnc,
+                        )
+                    });
+                }
+            }
+        }
     } else if let Some(gene_symbol) = &query.gene_symbol {
         let mut it = ontology.genes();
         let mut gene = it.next();
@@ -180,7 +234,7 @@ async fn handle(
                 Match::Prefix => symbol.starts_with(gene_symbol),
                 Match::Suffix => symbol.ends_with(gene_symbol),
                 Match::Contains => symbol.contains(gene_symbol),
-                Match::Exact => panic!("cannot happen here"),
+                Match::Exact | Match::Fuzzy => panic!("cannot happen here"),
             };
             if is_match {
                 result.push(HpoGenesResultEntry::from_gene_with_ontology(
@@ -195,7 +249,10 @@ async fn handle(
         }
     }
 
-    result.sort();
+    // Keep the relevance order of the full text search.
+    if match_ != Match::Fuzzy {
+        result.sort();
+    }
 
     let result = HpoGenesResult {
         version: Version::new(&data.ontology.hpo_version()),
@@ -210,26 +267,64 @@ async fn handle(
 pub(crate) mod test {
     use std::sync::Arc;
 
-    /// Return the default ``crate::server::run::WebServerData`` for testing.
-    #[rstest::fixture]
-    #[once]
-    pub fn web_server_data() -> Arc<crate::server::run::WebServerData> {
+    /// Return the ``crate::server::run::WebServerData`` for testing with `mondo`.
+    fn build_web_server_data(
+        mondo: Option<crate::mondo::Mondo>,
+    ) -> Arc<crate::server::run::WebServerData> {
         let ontology = crate::common::load_hpo("tests/data/hpo").expect("could not load HPO");
         let ncbi_to_hgnc =
             crate::common::hgnc_xlink::load_ncbi_to_hgnc("tests/data/hpo/hgnc_xlink.tsv")
                 .expect("could not HGNC xlink");
         let hgnc_to_ncbi = crate::common::hgnc_xlink::inverse_hashmap(&ncbi_to_hgnc);
+        let disease_to_genes = crate::common::genes_to_phenotype::load_disease_to_genes(
+            "tests/data/hpo/genes_to_phenotype.txt",
+        )
+        .expect("could not load genes to phenotype");
         let hpo_doc = fastobo::from_file("tests/data/hpo/hp.obo").expect("could not load HPO OBO");
+        let diseases =
+            crate::common::phenotype_hpoa::load_diseases("tests/data/hpo/phenotype.hpoa")
+                .expect("could not load diseases");
+        let genes = crate::common::hgnc_xlink::load_gene_names("tests/data/hpo/hgnc_xlink.tsv")
+            .expect("could not load gene names");
 
         Arc::new(crate::server::run::WebServerData {
             ontology,
             ncbi_to_hgnc,
             hgnc_to_ncbi,
-            full_text_index: crate::index::Index::new(hpo_doc)
+            full_text_index: crate::index::Index::new(&hpo_doc, &diseases, &genes)
                 .expect("could not create full text index"),
+            annotation_frequencies: crate::query::AnnotationFrequencies::new(
+                &diseases,
+                &disease_to_genes,
+            ),
+            candidate_onsets: crate::algos::onset::CandidateOnsets::new(
+                &diseases,
+                &disease_to_genes,
+            ),
+            disease_to_genes,
+            diseases,
+            mondo,
+            simulation: None,
         })
     }
 
+    /// Return the default ``crate::server::run::WebServerData`` for testing.
+    #[rstest::fixture]
+    #[once]
+    pub fn web_server_data() -> Arc<crate::server::run::WebServerData> {
+        build_web_server_data(None)
+    }
+
+    /// Return the ``crate::server::run::WebServerData`` for testing with a small MONDO
+    /// excerpt, see ``crate::mondo::test::mondo``.
+    #[rstest::fixture]
+    #[once]
+    pub fn web_server_data_mondo() -> Arc<crate::server::run::WebServerData> {
+        build_web_server_data(Some(
+            crate::mondo::test::mondo().expect("could not load MONDO"),
+        ))
+    }
+
     /// Helper function for running a query.
     #[allow(dead_code)]
     pub async fn run_query(
@@ -411,4 +506,34 @@ pub(crate) mod test {
             .await?
         ))
     }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_genes_gene_symbol_exact_prev_symbol(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/genes?gene_symbol=C11orf80"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_genes_gene_symbol_fuzzy_no_hpo_terms(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/genes?gene_symbol=c11orf&match=fuzzy"
+            )
+            .await?
+        );
+        Ok(())
+    }
 }
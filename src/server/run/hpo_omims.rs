@@ -14,17 +14,22 @@ use hpo::{
 
 use crate::{common::Version, server::run::WebServerData};
 
-use super::{CustomError, Match, ResultHpoTerm};
+use super::{hpo_diseases::lookup, CustomError, Match, ResultAnnotation, ResultHpoTerm};
 
 /// Parameters for `handle`.
 ///
 /// This allows to query for diseases.  The first given of the following
 /// is interpreted.
 ///
-/// - `omim_id` -- specify disease ID
-/// - `name` -- specify the name to query for
+/// - `omim_id` -- specify disease ID, or a `MONDO:` ID to query for the OMIM
+///   diseases mapped to the MONDO disease and its subtypes
+/// - `name` -- specify the name to query for, with `match=fuzzy` this is a
+///   relevance-ranked full text search over the names and alternative titles
 /// - `max_results` -- the maximum number of records to return
 /// - `hpo_terms` -- whether to include `"hpo_terms"` in result
+/// - `details` -- whether to include `"annotations"` in result, the disease-term
+///   associations with evidence, onset, frequency, sex, modifiers, aspect, and
+///   references from `phenotype.hpoa`
 ///
 /// The following propery defines how matches are performed:
 ///
@@ -33,7 +38,7 @@ use super::{CustomError, Match, ResultHpoTerm};
     Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema, utoipa::IntoParams,
 )]
 pub struct HpoOmimsQuery {
-    /// The OMIM ID to search for.
+    /// The OMIM ID or MONDO ID to search for.
     pub omim_id: Option<String>,
     /// The disease name to search for.
     pub name: Option<String>,
@@ -47,15 +52,20 @@ pub struct HpoOmimsQuery {
     /// Whether to include HPO terms.
     #[serde(default = "_default_hpo_terms")]
     pub hpo_terms: bool,
+    /// Whether to include the annotation details.
+    #[serde(default)]
+    pub details: bool,
 }
 
 impl HpoOmimsQuery {
-    /// Strip "OMIM:" prefix from `omim_id`, if any.
+    /// Strip "OMIM:" prefix from `omim_id`, if any, and normalize the "MONDO:" prefix.
     fn with_stripped_prefix(self) -> Self {
         Self {
             omim_id: self.omim_id.map(|omim_id| {
                 let lower_omim_id = omim_id.to_lowercase();
-                if lower_omim_id.starts_with("omim:") {
+                if lower_omim_id.starts_with("mondo:") {
+                    format!("MONDO:{}", &omim_id[6..])
+                } else if lower_omim_id.starts_with("omim:") {
                     omim_id[5..].to_string()
                 } else if lower_omim_id.starts_with("mim:") {
                     omim_id[4..].to_string()
@@ -88,6 +98,15 @@ pub struct HpoOmimsResultEntry {
     /// The gene's associated HPO terms.
     #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
     pub hpo_terms: Option<Vec<ResultHpoTerm>>,
+    /// The disease's annotations with details, including terms qualified with `NOT`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Vec<ResultAnnotation>>,
+    /// The IDs of the equivalent MONDO diseases, if MONDO is available.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mondo_ids: Vec<String>,
+    /// The full text search score, only for `match=fuzzy`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
 }
 
 impl PartialEq for HpoOmimsResultEntry {
@@ -143,6 +162,9 @@ impl HpoOmimsResultEntry {
             omim_id: omim_disease.id().to_string(),
             name: omim_disease.name().to_string(),
             hpo_terms,
+            annotations: None,
+            mondo_ids: Vec::new(),
+            score: None,
         }
     }
 }
@@ -182,7 +204,33 @@ async fn handle(
     // Strip "OMIM:" and "MIM:" prefix from `query.omim_id` if given.
     let query = query.into_inner().with_stripped_prefix();
 
-    if match_ == Match::Exact {
+    if let Some(mondo_id) = query
+        .omim_id
+        .as_ref()
+        .filter(|omim_id| omim_id.starts_with("MONDO:"))
+    {
+        let mondo = data.mondo.as_ref().ok_or_else(|| {
+            CustomError::new(anyhow::anyhow!(
+                "MONDO is not available, cannot query {mondo_id}"
+            ))
+        })?;
+        for disease_id in mondo.grouped_diseases(mondo_id) {
+            let Some(omim_id) = disease_id.strip_prefix("OMIM:") else {
+                continue;
+            };
+            let omim_id = OmimDiseaseId::try_from(omim_id)
+                .map_err(|e| CustomError::new(anyhow::anyhow!(e)))?;
+            if let Some(omim_disease) = ontology.omim_disease(&omim_id) {
+                result.push(HpoOmimsResultEntry::from_omim_disease_with_ontology(
+                    omim_disease,
+                    ontology,
+                    query.hpo_terms,
+                ));
+            }
+        }
+        result.sort();
+        result.truncate(query.max_results);
+    } else if match_ == Match::Exact {
         let omim_disease = if let Some(omim_id) = &query.omim_id {
             let omim_id = OmimDiseaseId::try_from(omim_id.as_ref())
                 .map_err(|e| CustomError::new(anyhow::anyhow!(e)))?;
@@ -219,6 +267,27 @@ async fn handle(
                 query.hpo_terms,
             ));
         }
+    } else if match_ == Match::Fuzzy {
+        if let Some(name) = &query.name {
+            let hits = data
+                .full_text_index
+                .search_diseases(name, Some("OMIM"), query.max_results)
+                .map_err(CustomError::new)?;
+            for (disease_id, score) in hits {
+                let omim_id = OmimDiseaseId::try_from(disease_id.trim_start_matches("OMIM:"))
+                    .map_err(|e| CustomError::new(anyhow::anyhow!(e)))?;
+                if let Some(omim_disease) = ontology.omim_disease(&omim_id) {
+                    result.push(HpoOmimsResultEntry {
+                        score: Some(score),
+                        ..HpoOmimsResultEntry::from_omim_disease_with_ontology(
+                            omim_disease,
+                            ontology,
+                            query.hpo_terms,
+                        )
+                    });
+                }
+            }
+        }
     } else if let Some(name) = &query.name {
         let mut it = ontology.omim_diseases();
         let mut omim_disease = it.next();
@@ -238,7 +307,7 @@ async fn handle(
                 Match::Prefix => omim_name.starts_with(&name),
                 Match::Suffix => omim_name.ends_with(&name),
                 Match::Contains => omim_name.contains(&name),
-                Match::Exact => panic!("cannot happen here"),
+                Match::Exact | Match::Fuzzy => panic!("cannot happen here"),
             };
             if is_match {
                 result.push(HpoOmimsResultEntry::from_omim_disease_with_ontology(
@@ -252,7 +321,29 @@ async fn handle(
         }
     }
 
-    result.sort();
+    // Keep the relevance order of the full text search.
+    if match_ != Match::Fuzzy {
+        result.sort();
+    }
+    if let Some(mondo) = &data.mondo {
+        for entry in &mut result {
+            entry.mondo_ids = mondo.mondo_ids(&entry.omim_id).to_vec();
+        }
+    }
+    if query.details {
+        for entry in &mut result {
+            entry.annotations = Some(
+                lookup(&data.diseases, &entry.omim_id)
+                    .map(|disease| disease.annotations.as_slice())
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|annotation| {
+                        ResultAnnotation::from_annotation_with_ontology(annotation, ontology)
+                    })
+                    .collect(),
+            );
+        }
+    }
 
     let result = HpoOmimsResult {
         version: Version::new(&data.ontology.hpo_version()),
@@ -422,4 +513,34 @@ mod test {
             .await?
         ))
     }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_omims_omim_id_exact_with_details(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/omims?omim_id=616145&details=true"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_omims_name_fuzzy_no_hpo_terms(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/omims?name=Catel-Manzk+syndrom&match=fuzzy"
+            )
+            .await?
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,153 @@
+//! Entry point `/hpo/sim/cohort` that allows the pairwise similarity computation between
+//! the cases of a cohort.
+
+use std::sync::Arc;
+
+use actix_web::{
+    post,
+    web::{self, Data, Json, Path},
+};
+
+use super::super::CustomError;
+use crate::{
+    batch::Case,
+    cohort::{similarity_matrix, SimilarityMatrix},
+    common::{IcBasedOn, ScoreCombiner, SimilarityMethod, Version},
+    query::ScoringArgs,
+    server::run::WebServerData,
+};
+
+/// JSON body for `handle`.
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, utoipa::ToSchema)]
+pub struct HpoSimCohortRequest {
+    /// The cases of the cohort, excluded terms are ignored.
+    pub cases: Vec<Case>,
+    /// What should information content be based on.
+    #[serde(default = "IcBasedOn::default")]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[serde(default = "SimilarityMethod::default")]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[serde(default = "ScoreCombiner::default")]
+    pub combiner: ScoreCombiner,
+}
+
+/// The performed cohort query, without the cases.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct HpoSimCohortQuery {
+    /// What should information content be based on.
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    pub combiner: ScoreCombiner,
+}
+
+/// Result container.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct HpoSimCohortResult {
+    /// Version information.
+    pub version: Version,
+    /// The original query records.
+    pub query: HpoSimCohortQuery,
+    /// The pairwise similarities of the cases.
+    pub result: SimilarityMatrix,
+}
+
+/// Query for the pairwise similarity of the cases of a cohort.
+///
+/// The matrix is computed in parallel on the blocking thread pool.
+#[utoipa::path(
+    post,
+    operation_id = "hpoSimCohort",
+    request_body = HpoSimCohortRequest,
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoSimCohortResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[post("/api/v1/hpo/sim/cohort")]
+async fn handle(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: Json<HpoSimCohortRequest>,
+) -> actix_web::Result<Json<HpoSimCohortResult>, CustomError> {
+    let HpoSimCohortRequest {
+        cases,
+        ic_base,
+        similarity,
+        combiner,
+    } = query.into_inner();
+    let scoring = ScoringArgs {
+        ic_base,
+        similarity,
+        combiner,
+    };
+
+    let ontology_data = data.clone();
+    let matrix = web::block(move || similarity_matrix(&cases, &ontology_data.ontology, scoring))
+        .await
+        .map_err(|e| CustomError::new(anyhow::anyhow!("problem computing matrix: {}", e)))?;
+
+    Ok(Json(HpoSimCohortResult {
+        version: Version::new(&data.ontology.hpo_version()),
+        query: HpoSimCohortQuery {
+            ic_base,
+            similarity,
+            combiner,
+        },
+        result: matrix,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        query: &super::HpoSimCohortRequest,
+    ) -> Result<super::HpoSimCohortResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/hpo/sim/cohort")
+            .set_json(query)
+            .to_request();
+        let resp: super::HpoSimCohortResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_cohort_three_cases(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let case = |case_id: &str, terms: &[&str]| crate::batch::Case {
+            case_id: case_id.to_string(),
+            terms: terms.iter().map(|term| (*term).to_string()).collect(),
+            excluded_terms: Vec::new(),
+        };
+        let query = super::HpoSimCohortRequest {
+            cases: vec![
+                case("case-1", &["HP:0010442", "HP:0000347"]),
+                case("case-2", &["HP:0001780"]),
+                case("case-3", &["HP:0000347", "HP:0000252"]),
+            ],
+            ..Default::default()
+        };
+        insta::assert_yaml_snapshot!(&run_query(web_server_data.clone(), &query).await?);
+        Ok(())
+    }
+}
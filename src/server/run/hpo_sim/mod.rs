@@ -1,4 +1,53 @@
-//! Implementation of `/hpo/sim/{term-term,term-gene}` endpoints.
+//! Implementation of `/hpo/sim/{term-term,term-gene,term-gene/batch,term-disease,phenopacket-gene,cohort}` endpoints.
 
+pub mod cohort;
+pub mod phenopacket_gene;
+pub mod term_disease;
 pub mod term_gene;
+pub mod term_gene_batch;
 pub mod term_term;
+
+use hpo::{term::HpoGroup, HpoTermId, Ontology};
+
+/// Translate term IDs from a query into an `HpoGroup`, skipping invalid and unknown
+/// terms.
+fn to_hpo_group(terms: &[String], hpo: &Ontology) -> HpoGroup {
+    let mut group = HpoGroup::new();
+    for term in terms {
+        if let Some(term) = HpoTermId::try_from(term.as_str())
+            .ok()
+            .and_then(|term_id| hpo.hpo(term_id))
+        {
+            group.insert(term.id());
+        }
+    }
+    group
+}
+
+/// Translate the patient's term IDs from a query into an `HpoGroup`.
+///
+/// # Errors
+///
+/// With status code 400 in the case that none of the terms is known.
+fn to_patient(terms: &[String], hpo: &Ontology) -> Result<HpoGroup, super::CustomError> {
+    let group = to_hpo_group(terms, hpo);
+    if group.is_empty() {
+        return Err(super::CustomError::bad_request(anyhow::anyhow!(
+            "query contains no known HPO terms"
+        )));
+    }
+    Ok(group)
+}
+
+/// Check that the patient's onset from a query, if any, is valid.
+///
+/// # Errors
+///
+/// With status code 400 in the case that the onset is neither an HPO onset term ID
+/// nor an ISO8601 age.
+fn validate_onset(onset: Option<&str>) -> Result<(), super::CustomError> {
+    if let Some(onset) = onset {
+        crate::algos::onset::OnsetRange::parse(onset).map_err(super::CustomError::bad_request)?;
+    }
+    Ok(())
+}
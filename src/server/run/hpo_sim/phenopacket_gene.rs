@@ -0,0 +1,237 @@
+//! Entry point `/hpo/sim/phenopacket-gene` that allows the similarity computation between
+//! the phenotypic features of a GA4GH phenopacket and a set of genes.
+
+use std::sync::Arc;
+
+use actix_web::{
+    post,
+    web::{self, Bytes, Data, Json, Path},
+    HttpRequest,
+};
+
+use super::super::CustomError;
+use crate::{
+    common::{IcBasedOn, ScoreCombiner, SimilarityMethod},
+    query::{self, phenopacket, query_result::HpoSimTermGeneResult, ResultLimits, ScoringArgs},
+    server::run::WebServerData,
+};
+
+/// Parameters for `handle`.
+///
+/// The phenopacket itself is given in the request body, either as JSON or, with
+/// the content type `application/x-protobuf`, in protobuf wire format.
+///
+/// - `use_genes` -- rank only the genes from the phenopacket's interpretations
+///   rather than all genes
+/// - `max_results` -- the maximal number of genes to return
+/// - `min_score` -- the minimal raw score of the returned genes
+///
+/// The similarity computation is configured with `ic_base`, `similarity`,
+/// and `combiner`.
+#[derive(serde::Deserialize, Debug, Clone, utoipa::ToSchema, utoipa::IntoParams)]
+pub struct HpoSimPhenopacketGeneQuery {
+    /// Whether to rank only the genes from the phenopacket's interpretations.
+    #[serde(default)]
+    pub use_genes: bool,
+    /// The maximal number of genes to return.
+    #[serde(default = "Option::default")]
+    pub max_results: Option<usize>,
+    /// The minimal raw score of the returned genes.
+    #[serde(default = "Option::default")]
+    pub min_score: Option<f32>,
+    /// What should information content be based on.
+    #[serde(default = "IcBasedOn::default")]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[serde(default = "SimilarityMethod::default")]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[serde(default = "ScoreCombiner::default")]
+    pub combiner: ScoreCombiner,
+}
+
+/// Whether the request body is in protobuf wire format according to its content type.
+fn is_protobuf(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            let mime = value.split(';').next().unwrap_or_default().trim();
+            mime == "application/x-protobuf" || mime == "application/protobuf"
+        })
+}
+
+/// Query for the similarity of the observed phenotypic features of a phenopacket
+/// to a set of genes.
+///
+/// Genes annotated with excluded phenotypic features are penalized and the scores
+/// of genes whose diseases' annotated onsets are incompatible with the earliest
+/// onset of the observed features are down-weighted.  By default, all genes are
+/// ranked, with `use_genes` only the genes from the phenopacket's interpretations.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    post,
+    operation_id = "hpoSimPhenopacketGene",
+    params(HpoSimPhenopacketGeneQuery),
+    request_body(
+        content = crate::pbs::phenopacket::Phenopacket,
+        content_type = "application/json",
+        description = "The phenopacket, in JSON or (with `application/x-protobuf`) protobuf format."
+    ),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoSimTermGeneResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[post("/api/v1/hpo/sim/phenopacket-gene")]
+async fn handle(
+    req: HttpRequest,
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoSimPhenopacketGeneQuery>,
+    body: Bytes,
+) -> actix_web::Result<Json<HpoSimTermGeneResult>, CustomError> {
+    let packet = phenopacket::parse(&body, !is_protobuf(&req)).map_err(CustomError::new)?;
+    let extracted = phenopacket::extract(&packet, &data.ontology, &data.hgnc_to_ncbi);
+
+    let scoring = ScoringArgs {
+        ic_base: query.ic_base,
+        similarity: query.similarity,
+        combiner: query.combiner,
+    };
+    let limits = ResultLimits {
+        max_results: query.max_results,
+        min_score: query.min_score,
+    };
+    let result = query::run_query(
+        &extracted.observed,
+        &extracted.excluded,
+        query.use_genes.then_some(extracted.genes.as_slice()),
+        &data.ontology,
+        &data.ncbi_to_hgnc,
+        scoring,
+        limits,
+        data.simulation.as_ref(),
+        query::ScoreWeighting {
+            onset: extracted
+                .onset
+                .as_deref()
+                .map(|onset| (onset, &data.candidate_onsets)),
+            ..Default::default()
+        },
+    )
+    .map_err(CustomError::new)?;
+
+    Ok(Json(result))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    /// Phenopacket used in the tests.
+    const PHENOPACKET_JSON: &str = r#"{
+        "id": "case-1",
+        "phenotypicFeatures": [
+            {"type": {"id": "HP:0010442", "label": "Polydactyly"}},
+            {"type": {"id": "HP:0000347", "label": "Micrognathia"}},
+            {"type": {"id": "HP:0001780", "label": "Abnormal toe morphology"}, "excluded": true}
+        ],
+        "interpretations": [{
+            "id": "interpretation-1",
+            "diagnosis": {
+                "genomicInterpretations": [
+                    {"subjectOrBiosampleId": "proband", "gene": {"valueId": "HGNC:20324", "symbol": "TGDS"}},
+                    {"subjectOrBiosampleId": "proband", "gene": {"valueId": "NCBIGene:7273", "symbol": "TTN"}}
+                ]
+            }
+        }]
+    }"#;
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        uri: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<crate::query::query_result::HpoSimTermGeneResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::post()
+            .uri(uri)
+            .insert_header((actix_web::http::header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        let resp: crate::query::query_result::HpoSimTermGeneResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_phenopacket_gene_use_genes(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/phenopacket-gene?use_genes=true",
+                "application/json",
+                PHENOPACKET_JSON.as_bytes().to_vec(),
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_phenopacket_gene_use_genes_protobuf(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let phenopacket = crate::query::phenopacket::parse(PHENOPACKET_JSON.as_bytes(), true)?;
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/phenopacket-gene?use_genes=true",
+                "application/x-protobuf",
+                prost::Message::encode_to_vec(&phenopacket),
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_phenopacket_gene_onset(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let mut phenopacket = crate::query::phenopacket::parse(PHENOPACKET_JSON.as_bytes(), true)?;
+        for (feature, onset) in phenopacket.phenotypic_features.iter_mut().zip([
+            r#"{"age": {"iso8601duration": "P30Y"}}"#,
+            r#"{"ontologyClass": {"id": "HP:0003623"}}"#,
+        ]) {
+            feature.onset = Some(serde_json::from_str(onset)?);
+        }
+        let result = run_query(
+            web_server_data.clone(),
+            "/api/v1/hpo/sim/phenopacket-gene?use_genes=true",
+            "application/x-protobuf",
+            prost::Message::encode_to_vec(&phenopacket),
+        )
+        .await?;
+
+        // The earliest onset of the observed features is used.
+        assert_eq!(result.query.onset.as_deref(), Some("HP:0003623"));
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+//! Entry point `/hpo/sim/term-disease` that allows to rank diseases by their similarity
+//! to a set of terms.
+
+use std::sync::Arc;
+
+use actix_web::{
+    get, post,
+    web::{self, Data, Json, Path},
+};
+
+use hpo::Ontology;
+
+use super::super::CustomError;
+use crate::{
+    common::{IcBasedOn, ScoreCombiner, SimilarityMethod},
+    query::{self, query_result::HpoSimTermDiseaseResult, ScoreWeighting, ScoringArgs},
+    server::run::WebServerData,
+};
+
+/// Default number of diseases to return.
+fn default_max_results() -> usize {
+    100
+}
+
+/// Parameters for `handle`.
+///
+/// This allows to compute differences between
+///
+/// - `terms` -- set of terms to use as query
+/// - `excluded_terms` -- optional set of terms explicitly excluded in the patient,
+///   candidates annotated with these terms or their descendants are penalized
+/// - `disease_ids` -- optional set of OMIM or ORPHA disease IDs to use as
+///   "database", e.g., `OMIM:616145` or `ORPHA:1234`; defaults to all diseases
+/// - `max_results` -- number of top-scoring diseases to return
+///
+/// The similarity computation is configured with `ic_base`, `similarity`,
+/// and `combiner`.  With `frequency_weighted`, the diseases' terms are weighted
+/// by their frequency from `phenotype.hpoa`, such that rare features absent from
+/// the query lower the score less.  With `onset`, the scores of diseases whose
+/// annotated onset is incompatible with the patient's onset are down-weighted.
+#[derive(serde::Deserialize, Debug, Clone, utoipa::ToSchema, utoipa::IntoParams)]
+pub struct HpoSimTermDiseaseQuery {
+    /// Set of terms to use as query.
+    #[serde(deserialize_with = "super::super::vec_str_deserialize")]
+    pub terms: Vec<String>,
+    /// Set of terms explicitly excluded in the patient.
+    #[serde(
+        default = "Option::default",
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "super::super::option_vec_str_deserialize"
+    )]
+    pub excluded_terms: Option<Vec<String>>,
+    /// The set of disease IDs to use as "database", all diseases if not given.
+    #[serde(
+        default = "Option::default",
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "super::super::option_vec_str_deserialize"
+    )]
+    pub disease_ids: Option<Vec<String>>,
+    /// The maximal number of diseases to return.
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+    /// What should information content be based on.
+    #[serde(default = "IcBasedOn::default")]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[serde(default = "SimilarityMethod::default")]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[serde(default = "ScoreCombiner::default")]
+    pub combiner: ScoreCombiner,
+    /// Whether to weight the annotated terms absent from the query by their frequency.
+    #[serde(default)]
+    pub frequency_weighted: bool,
+    /// The patient's onset as HPO onset term ID or ISO8601 age, e.g., `HP:0003623`
+    /// or `P2W`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub onset: Option<String>,
+}
+
+/// JSON body for `handle_post`.
+///
+/// This is the same as `HpoSimTermDiseaseQuery` but with the lists given as JSON
+/// arrays rather than comma-separated strings.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, utoipa::ToSchema)]
+pub struct HpoSimTermDiseaseRequest {
+    /// Set of terms to use as query.
+    pub terms: Vec<String>,
+    /// Set of terms explicitly excluded in the patient.
+    #[serde(default = "Vec::default", skip_serializing_if = "Vec::is_empty")]
+    pub excluded_terms: Vec<String>,
+    /// The set of disease IDs to use as "database", all diseases if not given.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub disease_ids: Option<Vec<String>>,
+    /// The maximal number of diseases to return.
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+    /// What should information content be based on.
+    #[serde(default = "IcBasedOn::default")]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[serde(default = "SimilarityMethod::default")]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[serde(default = "ScoreCombiner::default")]
+    pub combiner: ScoreCombiner,
+    /// Whether to weight the annotated terms absent from the query by their frequency.
+    #[serde(default)]
+    pub frequency_weighted: bool,
+    /// The patient's onset as HPO onset term ID or ISO8601 age, e.g., `HP:0003623`
+    /// or `P2W`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub onset: Option<String>,
+}
+
+impl From<HpoSimTermDiseaseQuery> for HpoSimTermDiseaseRequest {
+    fn from(query: HpoSimTermDiseaseQuery) -> Self {
+        let HpoSimTermDiseaseQuery {
+            terms,
+            excluded_terms,
+            disease_ids,
+            max_results,
+            ic_base,
+            similarity,
+            combiner,
+            frequency_weighted,
+            onset,
+        } = query;
+        Self {
+            terms,
+            excluded_terms: excluded_terms.unwrap_or_default(),
+            disease_ids,
+            max_results,
+            ic_base,
+            similarity,
+            combiner,
+            frequency_weighted,
+            onset,
+        }
+    }
+}
+
+/// Perform the similarity computation for `query`.
+fn compute(
+    data: &WebServerData,
+    query: &HpoSimTermDiseaseRequest,
+) -> Result<HpoSimTermDiseaseResult, CustomError> {
+    let hpo: &Ontology = &data.ontology;
+
+    // Translate strings from the query into `HpoGroup`s.
+    let query_terms = super::to_patient(&query.terms, hpo)?;
+    let excluded_terms = super::to_hpo_group(&query.excluded_terms, hpo);
+    super::validate_onset(query.onset.as_deref())?;
+
+    // Perform similarity computation.
+    let scoring = ScoringArgs {
+        ic_base: query.ic_base,
+        similarity: query.similarity,
+        combiner: query.combiner,
+    };
+    query::run_disease_query(
+        &query_terms,
+        &excluded_terms,
+        query.disease_ids.as_deref(),
+        query.max_results,
+        hpo,
+        &data.ncbi_to_hgnc,
+        &data.disease_to_genes,
+        scoring,
+        ScoreWeighting {
+            frequencies: query
+                .frequency_weighted
+                .then_some(&data.annotation_frequencies),
+            onset: query
+                .onset
+                .as_deref()
+                .map(|onset| (onset, &data.candidate_onsets)),
+        },
+    )
+    .map_err(CustomError::new)
+}
+
+/// Query for the diseases with the highest similarity to a set of terms.
+///
+/// The result contains the top-scoring OMIM and ORPHA diseases together with
+/// their associated genes.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoSimTermDisease",
+    params(HpoSimTermDiseaseQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoSimTermDiseaseResult),
+        (status = 400, description = "The query contains no known HPO terms or an invalid onset.", body = CustomError),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/sim/term-disease")]
+async fn handle(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoSimTermDiseaseQuery>,
+) -> actix_web::Result<Json<HpoSimTermDiseaseResult>, CustomError> {
+    Ok(Json(compute(&data, &query.into_inner().into())?))
+}
+
+/// Query for the diseases with the highest similarity to a set of terms, with
+/// the query given in a JSON body.
+///
+/// This is the same as the `GET` variant but does not suffer from URL length
+/// limits for large term sets.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    post,
+    operation_id = "hpoSimTermDiseasePost",
+    request_body = HpoSimTermDiseaseRequest,
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoSimTermDiseaseResult),
+        (status = 400, description = "The query contains no known HPO terms or an invalid onset.", body = CustomError),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[post("/api/v1/hpo/sim/term-disease")]
+async fn handle_post(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: Json<HpoSimTermDiseaseRequest>,
+) -> actix_web::Result<Json<HpoSimTermDiseaseResult>, CustomError> {
+    Ok(Json(compute(&data, &query)?))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        uri: &str,
+    ) -> Result<crate::query::query_result::HpoSimTermDiseaseResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+        let resp: crate::query::query_result::HpoSimTermDiseaseResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    /// Helper function for running a `POST` query.
+    #[allow(dead_code)]
+    pub async fn run_query_post(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        query: &super::HpoSimTermDiseaseRequest,
+    ) -> Result<crate::query::query_result::HpoSimTermDiseaseResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle_post),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/hpo/sim/term-disease")
+            .set_json(query)
+            .to_request();
+        let resp: crate::query::query_result::HpoSimTermDiseaseResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_disease_terms_all(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-disease?terms=HP:0010442,HP:0000347&max_results=5"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[case("terms=HP:9999999")]
+    #[case("terms=not-a-term")]
+    #[case("terms=")]
+    #[case("terms=HP:0000347&onset=soon")]
+    #[case("terms=HP:0000347&onset=HP:0000118")]
+    #[actix_web::test]
+    async fn hpo_sim_term_disease_bad_request(
+        #[case] params: &str,
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data.clone()))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get()
+            .uri(&format!("/api/v1/hpo/sim/term-disease?{params}"))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_disease_terms_disease_ids(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-disease?terms=HP:0010442,HP:0000347&disease_ids=OMIM:616145,ORPHA:2461"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_disease_terms_disease_ids_post(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let query = super::HpoSimTermDiseaseRequest {
+            terms: vec!["HP:0010442".to_string(), "HP:0000347".to_string()],
+            excluded_terms: Vec::new(),
+            disease_ids: Some(vec!["OMIM:616145".to_string(), "ORPHA:2461".to_string()]),
+            max_results: super::default_max_results(),
+            ic_base: crate::common::IcBasedOn::default(),
+            similarity: crate::common::SimilarityMethod::default(),
+            combiner: crate::common::ScoreCombiner::default(),
+            frequency_weighted: false,
+            onset: None,
+        };
+        insta::assert_yaml_snapshot!(&run_query_post(web_server_data.clone(), &query).await?);
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_disease_terms_frequency_weighted(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-disease?terms=HP:0010442,HP:0000347&disease_ids=OMIM:616145,ORPHA:1388&frequency_weighted=true"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_disease_terms_onset(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-disease?terms=HP:0010442,HP:0000347&disease_ids=OMIM:616145,ORPHA:1388&onset=HP:0003581"
+            )
+            .await?
+        );
+        Ok(())
+    }
+}
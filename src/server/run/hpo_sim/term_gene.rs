@@ -4,15 +4,22 @@
 use std::sync::Arc;
 
 use actix_web::{
-    get,
+    get, post,
     web::{self, Data, Json, Path},
 };
 
-use hpo::{annotations::GeneId, term::HpoGroup, HpoTermId, Ontology};
+use hpo::{
+    annotations::{Gene, GeneId},
+    Ontology,
+};
 
 use super::super::CustomError;
 use crate::{
-    query::{self, query_result::HpoSimTermGeneResult},
+    common::{IcBasedOn, ScoreCombiner, SimilarityMethod},
+    query::{
+        self, query_result::HpoSimTermGeneResult, RankingMethod, ResultLimits, ScoreWeighting,
+        ScoringArgs,
+    },
     server::run::WebServerData,
 };
 
@@ -21,15 +28,39 @@ use crate::{
 /// This allows to compute differences between
 ///
 /// - `terms` -- set of terms to use as query
-/// - `gene_ids` -- set of ids for genes to use as "database", can be NCBI\
-///                 gene ID or HGNC gene ID.
+/// - `excluded_terms` -- optional set of terms explicitly excluded in the patient,
+///   candidates annotated with these terms or their descendants are penalized
+/// - `gene_ids` -- set of ids for genes to use as "database", can be NCBI
+///   gene ID or HGNC gene ID.
 /// - `gene_symbols` -- set of symbols for genes to use as
 ///   "database"
+///
+/// If neither `gene_ids` nor `gene_symbols` is given, all genes with HPO
+/// annotations are scored.  The result can be limited with `max_results`
+/// and `min_score`.
+///
+/// With `method=enrichment`, the genes are ranked by the P-value of the
+/// over-representation of the query terms in their annotations instead and
+/// excluded terms are ignored.
+///
+/// The similarity computation is configured with `ic_base`, `similarity`,
+/// and `combiner`.  With `frequency_weighted`, the genes' terms are weighted by
+/// their frequency in the gene's diseases from `phenotype.hpoa`, such that rare
+/// features absent from the query lower the score less.  With `onset`, the
+/// scores of genes whose diseases' annotated onsets are incompatible with the
+/// patient's onset are down-weighted.  P-values are not available with either.
 #[derive(serde::Deserialize, Debug, Clone, utoipa::ToSchema, utoipa::IntoParams)]
 pub struct HpoSimTermGeneQuery {
     /// Set of terms to use as query.
     #[serde(deserialize_with = "super::super::vec_str_deserialize")]
     pub terms: Vec<String>,
+    /// Set of terms explicitly excluded in the patient.
+    #[serde(
+        default = "Option::default",
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "super::super::option_vec_str_deserialize"
+    )]
+    pub excluded_terms: Option<Vec<String>>,
     /// The set of ids for genes to use as "database".
     #[serde(
         default = "Option::default",
@@ -44,17 +75,220 @@ pub struct HpoSimTermGeneQuery {
         deserialize_with = "super::super::option_vec_str_deserialize"
     )]
     pub gene_symbols: Option<Vec<String>>,
+    /// How to rank the genes.
+    #[serde(default = "RankingMethod::default")]
+    pub method: RankingMethod,
+    /// What should information content be based on.
+    #[serde(default = "IcBasedOn::default")]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[serde(default = "SimilarityMethod::default")]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[serde(default = "ScoreCombiner::default")]
+    pub combiner: ScoreCombiner,
+    /// Whether to weight the annotated terms absent from the query by their frequency.
+    #[serde(default)]
+    pub frequency_weighted: bool,
+    /// The patient's onset as HPO onset term ID or ISO8601 age, e.g., `HP:0003623`
+    /// or `P2W`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub onset: Option<String>,
+    /// The maximal number of genes to return.
+    #[serde(default = "Option::default")]
+    pub max_results: Option<usize>,
+    /// The minimal raw score of the returned genes.
+    #[serde(default = "Option::default")]
+    pub min_score: Option<f32>,
+}
+
+/// JSON body for `handle_post`.
+///
+/// This is the same as `HpoSimTermGeneQuery` but with the lists given as JSON
+/// arrays rather than comma-separated strings.
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, utoipa::ToSchema)]
+pub struct HpoSimTermGeneRequest {
+    /// Set of terms to use as query.
+    pub terms: Vec<String>,
+    /// Set of terms explicitly excluded in the patient.
+    #[serde(default = "Vec::default", skip_serializing_if = "Vec::is_empty")]
+    pub excluded_terms: Vec<String>,
+    /// The set of ids for genes to use as "database".
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub gene_ids: Option<Vec<String>>,
+    /// The set of symbols for genes to use as "database".
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub gene_symbols: Option<Vec<String>>,
+    /// How to rank the genes.
+    #[serde(default = "RankingMethod::default")]
+    pub method: RankingMethod,
+    /// What should information content be based on.
+    #[serde(default = "IcBasedOn::default")]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[serde(default = "SimilarityMethod::default")]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[serde(default = "ScoreCombiner::default")]
+    pub combiner: ScoreCombiner,
+    /// Whether to weight the annotated terms absent from the query by their frequency.
+    #[serde(default)]
+    pub frequency_weighted: bool,
+    /// The patient's onset as HPO onset term ID or ISO8601 age, e.g., `HP:0003623`
+    /// or `P2W`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub onset: Option<String>,
+    /// The maximal number of genes to return.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+    /// The minimal raw score of the returned genes.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f32>,
+}
+
+impl From<HpoSimTermGeneQuery> for HpoSimTermGeneRequest {
+    fn from(query: HpoSimTermGeneQuery) -> Self {
+        let HpoSimTermGeneQuery {
+            terms,
+            excluded_terms,
+            gene_ids,
+            gene_symbols,
+            method,
+            ic_base,
+            similarity,
+            combiner,
+            frequency_weighted,
+            onset,
+            max_results,
+            min_score,
+        } = query;
+        Self {
+            terms,
+            excluded_terms: excluded_terms.unwrap_or_default(),
+            gene_ids,
+            gene_symbols,
+            method,
+            ic_base,
+            similarity,
+            combiner,
+            frequency_weighted,
+            onset,
+            max_results,
+            min_score,
+        }
+    }
+}
+
+/// Translate gene IDs (NCBI or HGNC) or, if not given, gene symbols into genes.
+///
+/// Returns `None` if neither is given, i.e., all genes are to be used.
+pub fn resolve_genes<'a>(
+    data: &'a WebServerData,
+    gene_ids: Option<&[String]>,
+    gene_symbols: Option<&[String]>,
+) -> Option<Vec<&'a Gene>> {
+    let hpo: &Ontology = &data.ontology;
+    if let Some(gene_ids) = gene_ids {
+        Some(
+            gene_ids
+                .iter()
+                .filter_map(|gene_id| {
+                    if let Ok(gene_id) = gene_id.parse::<u32>() {
+                        hpo.gene(&GeneId::from(gene_id))
+                    } else if let Some(gene_id) = data.hgnc_to_ncbi.get(gene_id) {
+                        hpo.gene(&GeneId::from(*gene_id))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        gene_symbols.map(|gene_symbols| {
+            gene_symbols
+                .iter()
+                .filter_map(|gene_symbol| hpo.gene_by_name(gene_symbol))
+                .collect::<Vec<_>>()
+        })
+    }
+}
+
+/// Perform the similarity computation for `query`.
+fn compute(
+    data: &WebServerData,
+    query: &HpoSimTermGeneRequest,
+) -> Result<HpoSimTermGeneResult, CustomError> {
+    let hpo: &Ontology = &data.ontology;
+
+    // Translate strings from the query into `HpoGroup`s.
+    let query_terms = super::to_patient(&query.terms, hpo)?;
+    let excluded_terms = super::to_hpo_group(&query.excluded_terms, hpo);
+    super::validate_onset(query.onset.as_deref())?;
+
+    // Translate strings from the query into genes via symbol or gene ID, use all
+    // genes if neither is given.
+    let genes = resolve_genes(
+        data,
+        query.gene_ids.as_deref(),
+        query.gene_symbols.as_deref(),
+    );
+
+    let limits = ResultLimits {
+        max_results: query.max_results,
+        min_score: query.min_score,
+    };
+    if query.method == RankingMethod::Enrichment {
+        return query::run_enrichment_query(
+            &query_terms,
+            genes.as_deref(),
+            hpo,
+            &data.ncbi_to_hgnc,
+            limits,
+        )
+        .map_err(CustomError::new);
+    }
+
+    // Perform similarity computation.
+    let scoring = ScoringArgs {
+        ic_base: query.ic_base,
+        similarity: query.similarity,
+        combiner: query.combiner,
+    };
+    query::run_query(
+        &query_terms,
+        &excluded_terms,
+        genes.as_deref(),
+        hpo,
+        &data.ncbi_to_hgnc,
+        scoring,
+        limits,
+        data.simulation.as_ref(),
+        ScoreWeighting {
+            frequencies: query
+                .frequency_weighted
+                .then_some(&data.annotation_frequencies),
+            onset: query
+                .onset
+                .as_deref()
+                .map(|onset| (onset, &data.candidate_onsets)),
+        },
+    )
+    .map_err(CustomError::new)
 }
 
 /// Query for similarity between a set of terms to each entry in a
-/// list of genes.
-#[allow(clippy::unused_async)]
+/// list of genes or to all genes.
+///
+/// P-values are reported if the server was started with a simulation store
+/// that was created with the same similarity settings.  The scores are computed
+/// on the blocking thread pool.
 #[utoipa::path(
     get,
     operation_id = "hpoSimTermGene",
     params(HpoSimTermGeneQuery),
     responses(
         (status = 200, description = "The query was successful.", body = HpoSimTermGeneResult),
+        (status = 400, description = "The query contains no known HPO terms or an invalid onset.", body = CustomError),
         (status = 500, description = "The server encountered an error.", body = CustomError)
     )
 )]
@@ -64,47 +298,39 @@ async fn handle(
     _path: Path<()>,
     query: web::Query<HpoSimTermGeneQuery>,
 ) -> actix_web::Result<Json<HpoSimTermGeneResult>, CustomError> {
-    let hpo: &Ontology = &data.ontology;
+    let query = query.into_inner().into();
+    let result = web::block(move || compute(&data, &query))
+        .await
+        .map_err(|e| CustomError::new(anyhow::anyhow!("problem computing scores: {}", e)))??;
 
-    // Translate strings from the query into an `HpoGroup`.
-    let query_terms = {
-        let mut query_terms = HpoGroup::new();
-        for term in &query.terms {
-            if let Some(term) = hpo.hpo(HpoTermId::from(term.clone())) {
-                query_terms.insert(term.id());
-            }
-        }
-        query_terms
-    };
-
-    // Translate strings from the query into genes via symbol or gene ID.
-    let genes = if let Some(gene_ids) = &query.gene_ids {
-        Ok(gene_ids
-            .iter()
-            .filter_map(|gene_id| {
-                if let Ok(gene_id) = gene_id.parse::<u32>() {
-                    hpo.gene(&GeneId::from(gene_id))
-                } else if let Some(gene_id) = data.hgnc_to_ncbi.get(gene_id) {
-                    hpo.gene(&GeneId::from(*gene_id))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>())
-    } else if let Some(gene_symbols) = &query.gene_symbols {
-        Ok(gene_symbols
-            .iter()
-            .filter_map(|gene_symbol| hpo.gene_by_name(gene_symbol))
-            .collect::<Vec<_>>())
-    } else {
-        Err(CustomError::new(anyhow::anyhow!(
-            "either `gene_ids` or `gene_symbols` must be given"
-        )))
-    }?;
+    Ok(Json(result))
+}
 
-    // Perform similarity computation.
-    let result = query::run_query(&query_terms, &genes, hpo, &data.ncbi_to_hgnc)
-        .map_err(CustomError::new)?;
+/// Query for similarity between a set of terms to each entry in a
+/// list of genes or to all genes, with the query given in a JSON body.
+///
+/// This is the same as the `GET` variant but does not suffer from URL length
+/// limits for large term sets or gene panels.
+#[utoipa::path(
+    post,
+    operation_id = "hpoSimTermGenePost",
+    request_body = HpoSimTermGeneRequest,
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoSimTermGeneResult),
+        (status = 400, description = "The query contains no known HPO terms or an invalid onset.", body = CustomError),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[post("/api/v1/hpo/sim/term-gene")]
+async fn handle_post(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: Json<HpoSimTermGeneRequest>,
+) -> actix_web::Result<Json<HpoSimTermGeneResult>, CustomError> {
+    let query = query.into_inner();
+    let result = web::block(move || compute(&data, &query))
+        .await
+        .map_err(|e| CustomError::new(anyhow::anyhow!("problem computing scores: {}", e)))??;
 
     Ok(Json(result))
 }
@@ -134,6 +360,28 @@ mod test {
         Ok(resp)
     }
 
+    /// Helper function for running a `POST` query.
+    #[allow(dead_code)]
+    pub async fn run_query_post(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        query: &super::HpoSimTermGeneRequest,
+    ) -> Result<crate::query::query_result::HpoSimTermGeneResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle_post),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/hpo/sim/term-gene")
+            .set_json(query)
+            .to_request();
+        let resp: crate::query::query_result::HpoSimTermGeneResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
     #[rstest::rstest]
     #[actix_web::test]
     async fn hpo_sim_term_gene_terms_ncbi_gene_ids(
@@ -175,4 +423,156 @@ mod test {
             .await?
         ))
     }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_terms_symbols_lin_bma(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-gene?terms=HP:0010442,HP:0000347&gene_symbols=TGDS,TTN&similarity=lin&combiner=bma"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_terms_all_genes_max_results(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-gene?terms=HP:0010442,HP:0000347&max_results=5"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_terms_all_genes_enrichment(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let result = run_query(
+            web_server_data.clone(),
+            "/api/v1/hpo/sim/term-gene?terms=HP:0010442,HP:0000347&method=enrichment&max_results=5",
+        )
+        .await?;
+
+        // The similarity settings are not used and the scores stay finite.
+        assert!(result.query.ic_base.is_none());
+        assert!(result.query.similarity.is_none());
+        assert!(result.query.combiner.is_none());
+        assert!(result
+            .result
+            .iter()
+            .all(|entry| entry.raw_score.is_finite()));
+
+        insta::assert_yaml_snapshot!(&result);
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_terms_symbols_onset(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-gene?terms=HP:0010442,HP:0000347&gene_symbols=TGDS,TTN&onset=HP:0003581"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_terms_symbols_excluded(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let uri = "/api/v1/hpo/sim/term-gene?terms=HP:0010442&gene_symbols=TGDS,TTN";
+        let plain = run_query(web_server_data.clone(), uri).await?;
+        let excluded = run_query(
+            web_server_data.clone(),
+            &format!("{uri}&excluded_terms=HP:0000347"),
+        )
+        .await?;
+
+        // Genes annotated with the excluded term report it and are penalized.
+        let mut penalized = 0;
+        for entry in &excluded.result {
+            let details = entry
+                .terms
+                .iter()
+                .flatten()
+                .filter(|term| term.excluded)
+                .collect::<Vec<_>>();
+            let plain_entry = plain
+                .result
+                .iter()
+                .find(|plain_entry| plain_entry.gene_symbol == entry.gene_symbol)
+                .expect("same genes with and without excluded terms");
+            for term in &details {
+                assert_eq!(
+                    term.term_query.as_ref().map(|term| term.term_id.as_str()),
+                    Some("HP:0000347")
+                );
+                assert!(term.score <= 0.0);
+            }
+            if details.is_empty() {
+                assert!((entry.raw_score - plain_entry.raw_score).abs() < f32::EPSILON);
+            } else {
+                assert!(entry.raw_score < plain_entry.raw_score);
+                penalized += 1;
+            }
+        }
+        assert!(penalized > 0);
+
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_terms_symbols_post(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let query = super::HpoSimTermGeneRequest {
+            terms: vec!["HP:0010442".to_string(), "HP:0000347".to_string()],
+            gene_symbols: Some(vec!["TGDS".to_string(), "TTN".to_string()]),
+            ..Default::default()
+        };
+        insta::assert_yaml_snapshot!(&run_query_post(web_server_data.clone(), &query).await?);
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[case("terms=HP:0000347&onset=soon")]
+    #[case("terms=HP:0000347&onset=P1X")]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_invalid_onset(
+        #[case] params: &str,
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data.clone()))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get()
+            .uri(&format!("/api/v1/hpo/sim/term-gene?{params}"))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
 }
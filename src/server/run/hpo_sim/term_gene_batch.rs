@@ -0,0 +1,181 @@
+//! Entry point `/hpo/sim/term-gene/batch` that allows the similarity computation between
+//! the terms of many cases and a set of genes.
+
+use std::sync::Arc;
+
+use actix_web::{
+    post,
+    web::{Bytes, Data, Json, Path},
+    HttpResponse,
+};
+use futures::SinkExt as _;
+use rayon::prelude::*;
+
+use super::super::CustomError;
+use crate::{
+    batch::{score_case, Case, CaseResult},
+    common::{IcBasedOn, ScoreCombiner, SimilarityMethod},
+    query::{ResultLimits, ScoringArgs},
+    server::run::WebServerData,
+};
+
+/// The number of case results buffered ahead of the client, scoring waits for the
+/// client when the buffer is full.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// JSON body for `handle`.
+///
+/// The cases in `cases` are all scored against the same genes, given by
+/// `gene_ids` or `gene_symbols` or all genes if neither is given.
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, utoipa::ToSchema)]
+pub struct HpoSimTermGeneBatchRequest {
+    /// The cases to score.
+    pub cases: Vec<Case>,
+    /// The set of ids for genes to use as "database".
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub gene_ids: Option<Vec<String>>,
+    /// The set of symbols for genes to use as "database".
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub gene_symbols: Option<Vec<String>>,
+    /// What should information content be based on.
+    #[serde(default = "IcBasedOn::default")]
+    pub ic_base: IcBasedOn,
+    /// The similarity method to use.
+    #[serde(default = "SimilarityMethod::default")]
+    pub similarity: SimilarityMethod,
+    /// The score combiner.
+    #[serde(default = "ScoreCombiner::default")]
+    pub combiner: ScoreCombiner,
+    /// The maximal number of genes to return per case.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+    /// The minimal raw score of the returned genes.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f32>,
+}
+
+/// Score many cases against the same genes.
+///
+/// The cases are scored in parallel and the result is streamed as JSONL with one
+/// `CaseResult` per line, in the order in which the cases are finished.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    post,
+    operation_id = "hpoSimTermGeneBatch",
+    request_body = HpoSimTermGeneBatchRequest,
+    responses(
+        (status = 200, description = "The query was successful.", body = CaseResult, content_type = "application/x-ndjson"),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[post("/api/v1/hpo/sim/term-gene/batch")]
+async fn handle(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: Json<HpoSimTermGeneBatchRequest>,
+) -> actix_web::Result<HttpResponse, CustomError> {
+    let query = query.into_inner();
+    let (tx, rx) =
+        futures::channel::mpsc::channel::<Result<Bytes, anyhow::Error>>(CHANNEL_CAPACITY);
+
+    // Score the cases on the blocking thread pool and send each result down the channel.
+    actix_web::rt::task::spawn_blocking(move || {
+        let genes = super::term_gene::resolve_genes(
+            &data,
+            query.gene_ids.as_deref(),
+            query.gene_symbols.as_deref(),
+        );
+        let scoring = ScoringArgs {
+            ic_base: query.ic_base,
+            similarity: query.similarity,
+            combiner: query.combiner,
+        };
+        let limits = ResultLimits {
+            max_results: query.max_results,
+            min_score: query.min_score,
+        };
+        let _ = query.cases.par_iter().try_for_each_with(tx, |tx, case| {
+            let line = score_case(
+                case,
+                genes.as_deref(),
+                &data.ontology,
+                &data.ncbi_to_hgnc,
+                scoring,
+                limits,
+                data.simulation.as_ref(),
+            )
+            .and_then(|result| Ok(serde_json::to_string(&result)?))
+            .map(|line| Bytes::from(format!("{line}\n")));
+            // Stop on errors or when the client has gone away.
+            let is_err = line.is_err();
+            if futures::executor::block_on(tx.send(line)).is_err() || is_err {
+                Err(())
+            } else {
+                Ok(())
+            }
+        });
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(rx))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        query: &super::HpoSimTermGeneBatchRequest,
+    ) -> Result<Vec<crate::batch::CaseResult>, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/hpo/sim/term-gene/batch")
+            .set_json(query)
+            .to_request();
+        let body = actix_web::test::call_and_read_body(&app, req).await;
+        let mut resp = std::str::from_utf8(&body)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<crate::batch::CaseResult>, _>>()?;
+        // The order of the cases is not deterministic.
+        resp.sort_by(|a, b| a.case_id.cmp(&b.case_id));
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_gene_batch_symbols(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let query = super::HpoSimTermGeneBatchRequest {
+            cases: vec![
+                crate::batch::Case {
+                    case_id: "case-1".to_string(),
+                    terms: vec!["HP:0010442".to_string(), "HP:0000347".to_string()],
+                    excluded_terms: Vec::new(),
+                },
+                crate::batch::Case {
+                    case_id: "case-2".to_string(),
+                    terms: vec!["HP:0001780".to_string()],
+                    excluded_terms: Vec::new(),
+                },
+            ],
+            gene_symbols: Some(vec!["TGDS".to_string(), "TTN".to_string()]),
+            ..Default::default()
+        };
+        insta::assert_yaml_snapshot!(&run_query(web_server_data.clone(), &query).await?);
+        Ok(())
+    }
+}
@@ -4,16 +4,20 @@
 use std::sync::Arc;
 
 use actix_web::{
-    get,
+    get, post,
     web::{self, Data, Json, Path},
 };
 use hpo::{
     similarity::{Builtins, Similarity},
-    HpoTermId, Ontology,
+    term::{HpoGroup, InformationContentKind},
+    HpoTerm, HpoTermId, Ontology,
 };
 use itertools::Itertools;
 
-use crate::common::{to_pairwise_sim, IcBasedOn, ScoreCombiner, SimilarityMethod, Version};
+use crate::algos::phenomizer;
+use crate::common::{
+    to_ic_kind, to_pairwise_sim, IcBasedOn, ScoreCombiner, SimilarityMethod, Version,
+};
 use crate::server::{run::CustomError, run::WebServerData};
 
 /// Parameters for `handle`.
@@ -22,6 +26,8 @@ use crate::server::{run::CustomError, run::WebServerData};
 ///
 /// - `lhs` -- first set of terms to compute similarity for
 /// - `rhs` -- econd set of terms to compute similarity for
+/// - `mica` -- whether to explain each score with the most informative common
+///   ancestor of the two terms
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
 pub struct RequestQuery {
     /// The one set of HPO terms to compute similarity for.
@@ -39,9 +45,12 @@ pub struct RequestQuery {
     /// The score combiner.
     #[serde(default = "ScoreCombiner::default")]
     pub combiner: ScoreCombiner,
+    /// Whether to include the most informative common ancestor.
+    #[serde(default)]
+    pub mica: bool,
 }
 
-/// Request as sent together with the response.
+/// Request as sent together with the response and as accepted by `handle_post`.
 ///
 /// The difference is that the `lhs` and `rhs` fields are replaced by vecs.
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
@@ -59,6 +68,9 @@ pub struct HpoSimTermTermQuery {
     /// The score combiner.
     #[serde(default = "ScoreCombiner::default")]
     pub combiner: ScoreCombiner,
+    /// Whether to include the most informative common ancestor.
+    #[serde(default, skip_serializing_if = "crate::common::is_false")]
+    pub mica: bool,
 }
 
 /// Result container.
@@ -68,10 +80,28 @@ pub struct HpoSimTermTermResult {
     pub version: Version,
     /// The original query records.
     pub query: HpoSimTermTermQuery,
+    /// The similarity of the two term sets, computed with the query's combiner.
+    pub score: f32,
+    /// The pairwise similarities as a matrix.
+    pub matrix: HpoSimTermTermMatrix,
     /// The resulting records for the scored genes.
     pub result: Vec<HpoSimTermTermResultEntry>,
 }
 
+/// The pairwise similarities in a compact layout.
+///
+/// The entry `scores[i][j]` is the similarity of `lhs[i]` and `rhs[j]`.  Terms that
+/// are not in the ontology are omitted.
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoSimTermTermMatrix {
+    /// The lhs terms, one per row.
+    pub lhs: Vec<String>,
+    /// The rhs terms, one per column.
+    pub rhs: Vec<String>,
+    /// The similarity scores, one row per lhs term.
+    pub scores: Vec<Vec<f32>>,
+}
+
 /// Result entry for `handle`.
 #[derive(
     Default,
@@ -90,31 +120,61 @@ pub struct HpoSimTermTermResultEntry {
     pub rhs: String,
     /// The similarity score.
     pub score: f32,
+    /// The most informative common ancestor, if requested.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub mica: Option<HpoSimTermTermMica>,
 }
 
-/// Query for pairwise term similarity.
-///
-/// In the case of Resnik, this corresponds to `IC(MICA(t_1, t_2))`.
-///
-/// # Errors
-///
-/// In the case that there is an error running the server.
-#[utoipa::path(
-    get,
-    operation_id = "hpoSimTermTerm",
-    params(RequestQuery),
-    responses(
-        (status = 200, description = "The query was successful.", body = HpoSimTermTermResult),
-        (status = 500, description = "The server encountered an error.", body = CustomError)
-    )
+/// The most informative common ancestor (MICA) of two terms.
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    PartialEq,
+    PartialOrd,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
 )]
-#[get("/api/v1/hpo/sim/term-term")]
-async fn handle(
-    data: Data<Arc<WebServerData>>,
-    _path: Path<()>,
-    query: web::Query<RequestQuery>,
-) -> actix_web::Result<Json<HpoSimTermTermResult>, CustomError> {
-    let ontology: &Ontology = &data.ontology;
+pub struct HpoSimTermTermMica {
+    /// The ID of the MICA.
+    pub term_id: String,
+    /// The name of the MICA.
+    pub name: String,
+    /// The information content of the MICA.
+    pub ic: f32,
+    /// The length of the shortest path from the lhs term to the MICA.
+    pub lhs_distance: usize,
+    /// The length of the shortest path from the rhs term to the MICA.
+    pub rhs_distance: usize,
+}
+
+/// Determine the most informative common ancestor of `lhs` and `rhs`.
+///
+/// The terms themselves count as their ancestors.  Ties in the information content
+/// are broken by the smaller term ID.
+fn mica(
+    ontology: &Ontology,
+    lhs: &HpoTerm,
+    rhs: &HpoTerm,
+    kind: InformationContentKind,
+) -> Option<HpoSimTermTermMica> {
+    lhs.all_common_ancestor_ids(rhs)
+        .iter()
+        .filter_map(|term_id| ontology.hpo(term_id))
+        .map(|term| (term.information_content().get_kind(&kind), term))
+        .max_by(|(ic_a, a), (ic_b, b)| ic_a.total_cmp(ic_b).then_with(|| b.id().cmp(&a.id())))
+        .map(|(ic, term)| HpoSimTermTermMica {
+            term_id: term.id().to_string(),
+            name: term.name().to_string(),
+            ic,
+            lhs_distance: lhs.distance_to_ancestor(&term).unwrap_or_default(),
+            rhs_distance: rhs.distance_to_ancestor(&term).unwrap_or_default(),
+        })
+}
+
+/// Compute the pairwise similarities for `query`.
+fn compute(ontology: &Ontology, query: HpoSimTermTermQuery) -> HpoSimTermTermResult {
     let mut result = Vec::new();
 
     let ic: Builtins = to_pairwise_sim(query.similarity, query.ic_base);
@@ -131,13 +191,24 @@ async fn handle(
         .filter_map(|rhs| ontology.hpo(HpoTermId::from(rhs.clone())))
         .collect::<Vec<_>>();
 
-    // Compute the similarity for each pair.
-    for (lhs, rhs) in lhs.iter().cartesian_product(rhs.iter()) {
+    // Compute the similarity for each pair, filling the matrix row by row.
+    let mut matrix = HpoSimTermTermMatrix {
+        lhs: lhs.iter().map(|term| term.id().to_string()).collect(),
+        rhs: rhs.iter().map(|term| term.id().to_string()).collect(),
+        scores: vec![Vec::with_capacity(rhs.len()); lhs.len()],
+    };
+    for ((i, lhs), rhs) in lhs.iter().enumerate().cartesian_product(rhs.iter()) {
         let similarity = ic.calculate(lhs, rhs);
+        matrix.scores[i].push(similarity);
         let elem = HpoSimTermTermResultEntry {
             lhs: lhs.id().to_string(),
             rhs: rhs.id().to_string(),
             score: similarity,
+            mica: if query.mica {
+                mica(ontology, lhs, rhs, to_ic_kind(query.ic_base))
+            } else {
+                None
+            },
         };
         result.push(elem);
     }
@@ -148,6 +219,48 @@ async fn handle(
             .expect("could not sort by score")
     });
 
+    // Combine the pairwise scores into the set-level score.
+    let score = phenomizer::score(
+        &lhs.iter().map(HpoTerm::id).collect::<HpoGroup>(),
+        &rhs.iter().map(HpoTerm::id).collect::<HpoGroup>(),
+        ontology,
+        &ic,
+        query.combiner,
+    );
+
+    HpoSimTermTermResult {
+        version: Version::new(&ontology.hpo_version()),
+        query,
+        score,
+        matrix,
+        result,
+    }
+}
+
+/// Query for pairwise term similarity.
+///
+/// In the case of Resnik, this corresponds to `IC(MICA(t_1, t_2))`.  The result also
+/// contains the similarity of the two sets as computed with `combiner` and the
+/// pairwise similarities as a matrix.
+///
+/// # Errors
+///
+/// In the case that there is an error running the server.
+#[utoipa::path(
+    get,
+    operation_id = "hpoSimTermTerm",
+    params(RequestQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoSimTermTermResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/sim/term-term")]
+async fn handle(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<RequestQuery>,
+) -> actix_web::Result<Json<HpoSimTermTermResult>, CustomError> {
     // We need to convert between Request and RequestResponse here so we can serialize the
     // lhs and rhs as Vec (they must be strings to parse the GET).
     let RequestQuery {
@@ -156,25 +269,50 @@ async fn handle(
         ic_base,
         similarity,
         combiner,
+        mica,
     } = query.into_inner();
 
-    let result = HpoSimTermTermResult {
-        version: Version::new(&data.ontology.hpo_version()),
-        query: HpoSimTermTermQuery {
+    let result = compute(
+        &data.ontology,
+        HpoSimTermTermQuery {
             lhs,
             rhs,
             ic_base,
             similarity,
             combiner,
+            mica,
         },
-        result,
-    };
-
-    dbg!(&result);
+    );
 
     Ok(Json(result))
 }
 
+/// Query for pairwise term similarity with the terms given in a JSON body.
+///
+/// This is the same as the `GET` variant but does not suffer from URL length
+/// limits for large term sets.
+///
+/// # Errors
+///
+/// In the case that there is an error running the server.
+#[utoipa::path(
+    post,
+    operation_id = "hpoSimTermTermPost",
+    request_body = HpoSimTermTermQuery,
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoSimTermTermResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[post("/api/v1/hpo/sim/term-term")]
+async fn handle_post(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: Json<HpoSimTermTermQuery>,
+) -> actix_web::Result<Json<HpoSimTermTermResult>, CustomError> {
+    Ok(Json(compute(&data.ontology, query.into_inner())))
+}
+
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
@@ -200,6 +338,28 @@ mod test {
         Ok(resp)
     }
 
+    /// Helper function for running a `POST` query.
+    #[allow(dead_code)]
+    pub async fn run_query_post(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        query: &super::HpoSimTermTermQuery,
+    ) -> Result<super::HpoSimTermTermResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle_post),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/v1/hpo/sim/term-term")
+            .set_json(query)
+            .to_request();
+        let resp: super::HpoSimTermTermResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
     #[rstest::rstest]
     #[actix_web::test]
     async fn hpo_sim_term_term_one_one(
@@ -227,4 +387,51 @@ mod test {
             .await?
         ))
     }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_term_two_two_post(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let query = super::HpoSimTermTermQuery {
+            lhs: vec!["HP:0010442".to_string(), "HP:0000347".to_string()],
+            rhs: vec!["HP:0001780".to_string(), "HP:0000252".to_string()],
+            ..Default::default()
+        };
+        insta::assert_yaml_snapshot!(&run_query_post(web_server_data.clone(), &query).await?);
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_term_two_two_mica(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/sim/term-term?lhs=HP:0010442,HP:0000347&rhs=HP:0001780,HP:0000252&mica=true"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_sim_term_term_ic_base_orpha(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let result = run_query(
+            web_server_data.clone(),
+            "/api/v1/hpo/sim/term-term?lhs=HP:0010442,HP:0000347&rhs=HP:0001780,HP:0000252&ic_base=orpha",
+        )
+        .await?;
+
+        assert_eq!(result.query.ic_base, crate::common::IcBasedOn::Orpha);
+        assert_eq!(result.result.len(), 4);
+        assert!(result.score.is_finite() && result.score > 0.0);
+        assert!(result.result.iter().all(|entry| entry.score.is_finite()));
+        Ok(())
+    }
 }
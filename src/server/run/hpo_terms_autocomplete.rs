@@ -0,0 +1,315 @@
+//! Implementation of `/hpo/terms/autocomplete`.
+
+use std::sync::Arc;
+
+use actix_web::{
+    get,
+    web::{self, Data, Json, Path},
+};
+use hpo::{HpoTermId, Ontology};
+
+use crate::{
+    common::Version,
+    index::{split_words, TermCandidate},
+    server::run::WebServerData,
+};
+
+use super::CustomError;
+
+/// Minimal number of candidates to fetch from the index for ranking.
+///
+/// The index only orders the candidates by BM25 score and not by the number of
+/// annotated genes, so the pool must hold all matches for the ranking to be
+/// correct.  This is the case for all but the shortest input, e.g., a single
+/// letter, which is ranked among the best BM25 scores only.  Ranking 5000
+/// candidates takes about 15ms.
+const MIN_CANDIDATES: usize = 5_000;
+
+/// Parameters for `handle`.
+///
+/// - `name` -- the partial term name or synonym as typed so far, the last word is
+///   completed as a prefix unless followed by a space
+/// - `max_results` -- the maximal number of terms to return
+#[derive(
+    Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema, utoipa::IntoParams,
+)]
+pub struct HpoTermsAutocompleteQuery {
+    /// The partial term name or synonym.
+    pub name: String,
+    /// Maximal number of results to return.
+    #[serde(default = "_default_max_results")]
+    pub max_results: usize,
+}
+
+/// Return default of `HpoTermsAutocompleteQuery::max_results`.
+fn _default_max_results() -> usize {
+    10
+}
+
+/// A highlighted span of matched characters.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HighlightSpan {
+    /// The 0-based character offset of the start.
+    pub start: usize,
+    /// The 0-based character offset of the end, exclusive.
+    pub end: usize,
+}
+
+/// Result entry for `handle`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoTermsAutocompleteResultEntry {
+    /// The HPO term's ID.
+    pub term_id: String,
+    /// The HPO term's name.
+    pub name: String,
+    /// The matched synonym, only if the name does not match.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub synonym: Option<String>,
+    /// The matched spans in the synonym if given, in the name otherwise.
+    pub highlights: Vec<HighlightSpan>,
+}
+
+/// Container for the result.
+#[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoTermsAutocompleteResult {
+    /// Version information.
+    pub version: Version,
+    /// The original query records.
+    pub query: HpoTermsAutocompleteQuery,
+    /// The resulting terms, best completion first.
+    pub result: Vec<HpoTermsAutocompleteResultEntry>,
+}
+
+/// The match of the query words in a name or synonym.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TextMatch {
+    /// Whether the words match the start of the text in order.
+    at_start: bool,
+    /// The matched spans, by start.
+    highlights: Vec<HighlightSpan>,
+}
+
+/// Match the query `words` against `text`.
+///
+/// Each word must match a distinct word of `text`, the last word only as a prefix if
+/// `last_is_prefix`.  Returns `None` if a word does not match.
+fn match_text(text: &str, words: &[String], last_is_prefix: bool) -> Option<TextMatch> {
+    let text_words = split_words(text);
+    let mut used = vec![false; text_words.len()];
+    let mut positions = Vec::with_capacity(words.len());
+    let mut highlights = Vec::with_capacity(words.len());
+    for (i, word) in words.iter().enumerate() {
+        let is_prefix = last_is_prefix && i + 1 == words.len();
+        let pos = text_words
+            .iter()
+            .enumerate()
+            .position(|(j, (_, _, text_word))| {
+                !used[j]
+                    && if is_prefix {
+                        text_word.starts_with(word.as_str())
+                    } else {
+                        text_word == word
+                    }
+            })?;
+        used[pos] = true;
+        positions.push(pos);
+        let start = text_words[pos].0;
+        highlights.push(HighlightSpan {
+            start,
+            end: start + word.chars().count(),
+        });
+    }
+    highlights.sort_by_key(|span| span.start);
+
+    Some(TextMatch {
+        at_start: positions.iter().enumerate().all(|(i, pos)| i == *pos),
+        highlights,
+    })
+}
+
+/// Rank key of a completion, smaller is better.
+type RankKey = (u8, std::cmp::Reverse<usize>, usize, String);
+
+/// Build the result entry for `candidate` with its rank key, if its name or one of
+/// its synonyms matches.
+///
+/// Completions are ranked by the match quality, i.e., names matching at the start
+/// before names matching elsewhere before synonyms matching at the start before
+/// synonyms matching elsewhere, then by the number of annotated genes, descending,
+/// then by name length.
+fn to_entry(
+    candidate: TermCandidate,
+    words: &[String],
+    last_is_prefix: bool,
+    ontology: &Ontology,
+) -> Option<(RankKey, HpoTermsAutocompleteResultEntry)> {
+    let term = ontology.hpo(HpoTermId::from(candidate.term_id.clone()))?;
+    let (quality, synonym, text_match) = if let Some(text_match) =
+        match_text(&candidate.name, words, last_is_prefix)
+    {
+        (u8::from(!text_match.at_start), None, text_match)
+    } else {
+        let (synonym, text_match) = candidate
+            .synonyms
+            .into_iter()
+            .filter_map(|synonym| match_text(&synonym, words, last_is_prefix).map(|m| (synonym, m)))
+            .min_by_key(|(synonym, m)| (!m.at_start, synonym.chars().count()))?;
+        (
+            2 + u8::from(!text_match.at_start),
+            Some(synonym),
+            text_match,
+        )
+    };
+
+    let key = (
+        quality,
+        std::cmp::Reverse(term.gene_ids().len()),
+        candidate.name.chars().count(),
+        candidate.term_id.clone(),
+    );
+    Some((
+        key,
+        HpoTermsAutocompleteResultEntry {
+            term_id: candidate.term_id,
+            name: candidate.name,
+            synonym,
+            highlights: text_match.highlights,
+        },
+    ))
+}
+
+/// Complete partial input to HPO terms, for typeahead.
+///
+/// The words of the input are matched against the names and synonyms of the terms,
+/// the last word as a prefix unless the input ends with a space or punctuation.
+/// Completions are ranked by match quality, i.e., names matching at the start
+/// first and synonyms last, and then by the number of annotated genes.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoTermsAutocomplete",
+    params(HpoTermsAutocompleteQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoTermsAutocompleteResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/terms/autocomplete")]
+async fn handle(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoTermsAutocompleteQuery>,
+) -> actix_web::Result<Json<HpoTermsAutocompleteResult>, CustomError> {
+    let ontology = &data.ontology;
+    let words = split_words(&query.name)
+        .into_iter()
+        .map(|(_, _, word)| word)
+        .collect::<Vec<_>>();
+    let last_is_prefix = query.name.chars().last().is_some_and(char::is_alphanumeric);
+
+    let candidates = data
+        .full_text_index
+        .complete_terms(
+            &words,
+            last_is_prefix,
+            MIN_CANDIDATES.max(query.max_results),
+        )
+        .map_err(CustomError::new)?;
+    let mut result = candidates
+        .into_iter()
+        .filter_map(|candidate| to_entry(candidate, &words, last_is_prefix, ontology))
+        .collect::<Vec<_>>();
+    result.sort_by(|a, b| a.0.cmp(&b.0));
+    let result = result
+        .into_iter()
+        .take(query.max_results)
+        .map(|(_, entry)| entry)
+        .collect();
+
+    Ok(Json(HpoTermsAutocompleteResult {
+        version: Version::new(&ontology.hpo_version()),
+        query: query.into_inner(),
+        result,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        uri: &str,
+    ) -> Result<super::HpoTermsAutocompleteResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+        let resp: super::HpoTermsAutocompleteResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[case("Abnormality of the hand", &["abnormality", "of", "the", "ha"], true, Some((true, vec![(0, 11), (12, 14), (15, 18), (19, 21)])))]
+    #[case("Abnormality of the hand", &["hand", "abnormality"], false, Some((false, vec![(0, 11), (19, 23)])))]
+    #[case("Abnormality of the hand", &["hand", "foot"], true, None)]
+    #[case("Abnormality of the hand", &["ha"], false, None)]
+    fn match_text(
+        #[case] text: &str,
+        #[case] words: &[&str],
+        #[case] last_is_prefix: bool,
+        #[case] expected: Option<(bool, Vec<(usize, usize)>)>,
+    ) {
+        let words = words
+            .iter()
+            .map(|word| (*word).to_string())
+            .collect::<Vec<_>>();
+        let expected = expected.map(|(at_start, spans)| super::TextMatch {
+            at_start,
+            highlights: spans
+                .into_iter()
+                .map(|(start, end)| super::HighlightSpan { start, end })
+                .collect(),
+        });
+        assert_eq!(super::match_text(text, &words, last_is_prefix), expected);
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_terms_autocomplete_prefix(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/terms/autocomplete?name=abnormality+of+the+ha&max_results=5"
+            )
+            .await?
+        );
+        Ok(())
+    }
+
+    #[rstest::rstest]
+    #[actix_web::test]
+    async fn hpo_terms_autocomplete_synonym(
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        insta::assert_yaml_snapshot!(
+            &run_query(
+                web_server_data.clone(),
+                "/api/v1/hpo/terms/autocomplete?name=small+ja&max_results=5"
+            )
+            .await?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+//! Implementation of `/hpo/terms/{parents,children,ancestors,descendants,path-to-root}`.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, LazyLock},
+};
+
+use actix_web::{
+    get,
+    web::{self, Data, Json, Path},
+};
+use hpo::{term::HpoGroup, HpoTerm, HpoTermId, Ontology};
+
+use crate::{common::Version, server::run::WebServerData};
+
+use super::CustomError;
+
+/// The ID of the root term of the HPO, "All".
+const ROOT_TERM_ID: &str = "HP:0000001";
+
+/// Regular expression for valid (lower-cased) term IDs.
+static TERM_ID_RE: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^hp:\d+$").expect("invalid regex"));
+
+/// Parameters for the ancestors and descendants handlers, also used as the query
+/// echoed in the result of all handlers in this module.
+///
+/// - `term_id` -- the term to start from
+/// - `max_depth` -- the maximal distance of the returned terms to `term_id`
+#[derive(
+    Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema, utoipa::IntoParams,
+)]
+pub struct HpoTermsTreeQuery {
+    /// The term ID to start from.
+    pub term_id: String,
+    /// The maximal distance of the returned terms to `term_id`.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+}
+
+/// Parameters for the parents, children, and path to root handlers.
+///
+/// - `term_id` -- the term to start from
+#[derive(
+    Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema, utoipa::IntoParams,
+)]
+pub struct HpoTermsTreeTermQuery {
+    /// The term ID to start from.
+    pub term_id: String,
+}
+
+impl From<HpoTermsTreeTermQuery> for HpoTermsTreeQuery {
+    fn from(query: HpoTermsTreeTermQuery) -> Self {
+        Self {
+            term_id: query.term_id,
+            max_depth: None,
+        }
+    }
+}
+
+/// Result entry for the handlers in this module.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoTermsTreeResultEntry {
+    /// The HPO term's ID.
+    pub term_id: String,
+    /// The HPO term's name.
+    pub name: String,
+    /// The (shortest) number of steps from the query term to this term.
+    pub depth: usize,
+}
+
+/// Container for the result.
+#[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HpoTermsTreeResult {
+    /// Version information.
+    pub version: Version,
+    /// The original query records.
+    pub query: HpoTermsTreeQuery,
+    /// The resulting terms, sorted by depth and term ID except for the path to the
+    /// root which is sorted from the query term to the root.
+    pub result: Vec<HpoTermsTreeResultEntry>,
+}
+
+/// Direction for walking the ontology.
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    /// Walk towards the root via the parents.
+    Up,
+    /// Walk towards the leaves via the children.
+    Down,
+}
+
+/// Resolve `term_id` in `ontology`.
+fn resolve_term<'a>(ontology: &'a Ontology, term_id: &str) -> Result<HpoTerm<'a>, CustomError> {
+    if !TERM_ID_RE.is_match(&term_id.to_lowercase()) {
+        return Err(CustomError::new(anyhow::anyhow!(
+            "Invalid term ID: {}",
+            term_id
+        )));
+    }
+
+    let term_id = HpoTermId::from(term_id.to_string());
+    ontology
+        .hpo(term_id)
+        .ok_or_else(|| CustomError::new(anyhow::anyhow!("Term ID {} not found in HPO", term_id)))
+}
+
+/// Construct result entry for `term_id` at `depth`.
+fn to_entry(ontology: &Ontology, term_id: HpoTermId, depth: usize) -> HpoTermsTreeResultEntry {
+    HpoTermsTreeResultEntry {
+        term_id: term_id.to_string(),
+        name: ontology
+            .hpo(term_id)
+            .map(|term| term.name().to_string())
+            .unwrap_or_default(),
+        depth,
+    }
+}
+
+/// Collect the terms reachable from `term` in `direction` with their shortest
+/// distance, up to `max_depth` steps.
+fn walk(
+    ontology: &Ontology,
+    term: &HpoTerm,
+    direction: Direction,
+    max_depth: Option<usize>,
+) -> Vec<HpoTermsTreeResultEntry> {
+    let mut seen = HpoGroup::new();
+    seen.insert(term.id());
+    let mut queue = VecDeque::from([(term.id(), 0usize)]);
+    let mut result = Vec::new();
+
+    // Breadth-first search yields the shortest distance for each term.
+    while let Some((term_id, depth)) = queue.pop_front() {
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            continue;
+        }
+        let Some(current) = ontology.hpo(term_id) else {
+            continue;
+        };
+        let next = match direction {
+            Direction::Up => current.parent_ids(),
+            Direction::Down => current.children_ids(),
+        };
+        for next_id in next {
+            if !seen.contains(&next_id) {
+                seen.insert(next_id);
+                result.push(to_entry(ontology, next_id, depth + 1));
+                queue.push_back((next_id, depth + 1));
+            }
+        }
+    }
+
+    result.sort_by(|a, b| {
+        a.depth
+            .cmp(&b.depth)
+            .then_with(|| a.term_id.cmp(&b.term_id))
+    });
+    result
+}
+
+/// Run `walk` for `query` and wrap the result.
+fn compute(
+    data: &WebServerData,
+    query: HpoTermsTreeQuery,
+    direction: Direction,
+    max_depth: Option<usize>,
+) -> Result<HpoTermsTreeResult, CustomError> {
+    let ontology = &data.ontology;
+    let term = resolve_term(ontology, &query.term_id)?;
+    Ok(HpoTermsTreeResult {
+        version: Version::new(&ontology.hpo_version()),
+        result: walk(ontology, &term, direction, max_depth),
+        query,
+    })
+}
+
+/// Query for the direct parents of a term.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoTermsParents",
+    params(HpoTermsTreeTermQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoTermsTreeResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/terms/parents")]
+async fn handle_parents(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoTermsTreeTermQuery>,
+) -> actix_web::Result<Json<HpoTermsTreeResult>, CustomError> {
+    Ok(Json(compute(
+        &data,
+        query.into_inner().into(),
+        Direction::Up,
+        Some(1),
+    )?))
+}
+
+/// Query for the direct children of a term.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoTermsChildren",
+    params(HpoTermsTreeTermQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoTermsTreeResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/terms/children")]
+async fn handle_children(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoTermsTreeTermQuery>,
+) -> actix_web::Result<Json<HpoTermsTreeResult>, CustomError> {
+    Ok(Json(compute(
+        &data,
+        query.into_inner().into(),
+        Direction::Down,
+        Some(1),
+    )?))
+}
+
+/// Query for all ancestors of a term, optionally up to `max_depth` steps.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoTermsAncestors",
+    params(HpoTermsTreeQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoTermsTreeResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/terms/ancestors")]
+async fn handle_ancestors(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoTermsTreeQuery>,
+) -> actix_web::Result<Json<HpoTermsTreeResult>, CustomError> {
+    let max_depth = query.max_depth;
+    Ok(Json(compute(
+        &data,
+        query.into_inner(),
+        Direction::Up,
+        max_depth,
+    )?))
+}
+
+/// Query for all descendants of a term, optionally up to `max_depth` steps.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoTermsDescendants",
+    params(HpoTermsTreeQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoTermsTreeResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/terms/descendants")]
+async fn handle_descendants(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoTermsTreeQuery>,
+) -> actix_web::Result<Json<HpoTermsTreeResult>, CustomError> {
+    let max_depth = query.max_depth;
+    Ok(Json(compute(
+        &data,
+        query.into_inner(),
+        Direction::Down,
+        max_depth,
+    )?))
+}
+
+/// Query for the shortest path from a term to the root of the ontology.
+///
+/// The result starts with the term itself and ends with the root term.
+#[allow(clippy::unused_async)]
+#[utoipa::path(
+    get,
+    operation_id = "hpoTermsPathToRoot",
+    params(HpoTermsTreeTermQuery),
+    responses(
+        (status = 200, description = "The query was successful.", body = HpoTermsTreeResult),
+        (status = 500, description = "The server encountered an error.", body = CustomError)
+    )
+)]
+#[get("/api/v1/hpo/terms/path-to-root")]
+async fn handle_path_to_root(
+    data: Data<Arc<WebServerData>>,
+    _path: Path<()>,
+    query: web::Query<HpoTermsTreeTermQuery>,
+) -> actix_web::Result<Json<HpoTermsTreeResult>, CustomError> {
+    let ontology = &data.ontology;
+    let term = resolve_term(ontology, &query.term_id)?;
+    let root = resolve_term(ontology, ROOT_TERM_ID)?;
+    let path = term
+        .path_to_ancestor(&root)
+        .ok_or_else(|| CustomError::new(anyhow::anyhow!("No path from {} to root", term.id())))?;
+
+    let result = std::iter::once(term.id())
+        .chain(path)
+        .enumerate()
+        .map(|(depth, term_id)| to_entry(ontology, term_id, depth))
+        .collect();
+
+    Ok(Json(HpoTermsTreeResult {
+        version: Version::new(&ontology.hpo_version()),
+        query: query.into_inner().into(),
+        result,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::server::run::hpo_genes::test::web_server_data;
+
+    /// Helper function for running a query.
+    #[allow(dead_code)]
+    pub async fn run_query(
+        web_server_data: Arc<crate::server::run::WebServerData>,
+        uri: &str,
+    ) -> Result<super::HpoTermsTreeResult, anyhow::Error> {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(web_server_data))
+                .service(super::handle_parents)
+                .service(super::handle_children)
+                .service(super::handle_ancestors)
+                .service(super::handle_descendants)
+                .service(super::handle_path_to_root),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+        let resp: super::HpoTermsTreeResult =
+            actix_web::test::call_and_read_body_json(&app, req).await;
+
+        Ok(resp)
+    }
+
+    #[rstest::rstest]
+    #[case::parents("/api/v1/hpo/terms/parents?term_id=HP:0000347")]
+    #[case::children("/api/v1/hpo/terms/children?term_id=HP:0000347")]
+    #[case::ancestors("/api/v1/hpo/terms/ancestors?term_id=HP:0000347")]
+    #[case::ancestors_max_depth("/api/v1/hpo/terms/ancestors?term_id=HP:0000347&max_depth=2")]
+    #[case::descendants("/api/v1/hpo/terms/descendants?term_id=HP:0000347")]
+    #[case::descendants_max_depth("/api/v1/hpo/terms/descendants?term_id=HP:0000347&max_depth=1")]
+    #[case::path_to_root("/api/v1/hpo/terms/path-to-root?term_id=HP:0000347")]
+    #[actix_web::test]
+    async fn hpo_terms_tree(
+        #[case] uri: &str,
+        web_server_data: &Arc<crate::server::run::WebServerData>,
+    ) -> Result<(), anyhow::Error> {
+        let uri_path = uri.split('?').next().unwrap_or_default();
+        let suffix = uri_path.rsplit('/').next().unwrap_or_default().to_string();
+        let suffix = if uri.contains("max_depth") {
+            format!("{suffix}_max_depth")
+        } else {
+            suffix
+        };
+        let result = run_query(web_server_data.clone(), uri).await?;
+        insta::with_settings!({snapshot_suffix => suffix}, {
+            insta::assert_yaml_snapshot!(&result);
+        });
+        Ok(())
+    }
+}
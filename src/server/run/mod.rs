@@ -1,9 +1,15 @@
 //! Implementation of the Actix server.
+// The code generated by `utoipa::OpenApi` triggers this lint.
+#![allow(clippy::needless_for_each)]
 
+pub mod hpo_diseases;
+pub mod hpo_enrichment;
 pub mod hpo_genes;
 pub mod hpo_omims;
 pub mod hpo_sim;
 pub mod hpo_terms;
+pub mod hpo_terms_autocomplete;
+pub mod hpo_terms_tree;
 
 use std::{collections::HashMap, sync::Arc};
 
@@ -21,8 +27,20 @@ pub struct WebServerData {
     pub ncbi_to_hgnc: HashMap<u32, String>,
     /// Xlink map from HGNC gene ID to NCBI gene ID.
     pub hgnc_to_ncbi: HashMap<String, u32>,
+    /// Map from disease ID (e.g., `OMIM:616145`) to NCBI gene IDs.
+    pub disease_to_genes: HashMap<String, Vec<u32>>,
+    /// The OMIM, ORPHA, and DECIPHER diseases from `phenotype.hpoa`, sorted by ID.
+    pub diseases: Vec<crate::common::phenotype_hpoa::Disease>,
     /// The full text index over the HPO OBO document.
     pub full_text_index: crate::index::Index,
+    /// The frequencies of the annotated terms of genes and diseases.
+    pub annotation_frequencies: crate::query::AnnotationFrequencies,
+    /// The annotated onsets of genes and diseases.
+    pub candidate_onsets: crate::algos::onset::CandidateOnsets,
+    /// The optional MONDO mapping to OMIM and ORPHA diseases.
+    pub mondo: Option<crate::mondo::Mondo>,
+    /// The optional simulation store for P-values.
+    pub simulation: Option<crate::simulate::store::Store>,
 }
 
 /// Command line arguments for `server run` sub command.
@@ -32,6 +50,9 @@ pub struct Args {
     /// Path to the directory with the HPO files.
     #[arg(long, required = true)]
     pub path_hpo_dir: String,
+    /// Optional path to the directory with the simulation store for P-values.
+    #[arg(long)]
+    pub path_simulation_dir: Option<String>,
 
     /// Whether to suppress printing hints.
     #[arg(long, default_value_t = false)]
@@ -48,6 +69,9 @@ pub struct Args {
 #[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 struct CustomError {
     err: String,
+    /// The HTTP status code, internal server error if not given.
+    #[serde(skip)]
+    status: Option<actix_web::http::StatusCode>,
 }
 
 impl std::fmt::Display for CustomError {
@@ -61,11 +85,26 @@ impl CustomError {
     fn new(err: anyhow::Error) -> Self {
         CustomError {
             err: err.to_string(),
+            status: None,
+        }
+    }
+
+    /// Construct an error for an invalid request, answered with status code 400.
+    #[allow(clippy::needless_pass_by_value)]
+    fn bad_request(err: anyhow::Error) -> Self {
+        CustomError {
+            err: err.to_string(),
+            status: Some(actix_web::http::StatusCode::BAD_REQUEST),
         }
     }
 }
 
-impl ResponseError for CustomError {}
+impl ResponseError for CustomError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        self.status
+            .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
 
 /// Specify how to perform query matches in the API calls.
 #[derive(Serialize, Deserialize, utoipa::ToSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -80,6 +119,8 @@ pub enum Match {
     Suffix,
     /// String containment.
     Contains,
+    /// Relevance-ranked full text search with fuzzy matching.
+    Fuzzy,
 }
 
 /// Representation of a gene.
@@ -125,6 +166,79 @@ pub struct ResultHpoTerm {
     pub name: String,
 }
 
+impl ResultHpoTerm {
+    /// Create from `term_id` with the name from `ontology`, empty if not found.
+    pub fn from_term_id(term_id: &str, ontology: &hpo::Ontology) -> Self {
+        let name = hpo::HpoTermId::try_from(term_id)
+            .ok()
+            .and_then(|term_id| ontology.hpo(term_id))
+            .map(|term| term.name().to_string())
+            .unwrap_or_default();
+        Self {
+            term_id: term_id.to_string(),
+            name,
+        }
+    }
+}
+
+/// Representation of the association of a disease with an HPO term with the details
+/// from `phenotype.hpoa`.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema, Debug, Clone, PartialEq, Eq)]
+pub struct ResultAnnotation {
+    /// The annotated HPO term.
+    pub term: ResultHpoTerm,
+    /// Whether the term is explicitly excluded in the disease.
+    #[serde(default, skip_serializing_if = "crate::common::is_false")]
+    pub negated: bool,
+    /// The evidence code, one of `IEA` (inferred from electronic annotation), `PCS`
+    /// (published clinical study), and `TAS` (traceable author statement).
+    pub evidence: String,
+    /// The onset of the term in the disease.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub onset: Option<ResultHpoTerm>,
+    /// The frequency of the term in the disease, an HPO term ID, a fraction `n/m`,
+    /// or a percentage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<String>,
+    /// The sex specificity, `MALE` or `FEMALE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sex: Option<String>,
+    /// The modifier terms.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<ResultHpoTerm>,
+    /// The sub ontology of the term, e.g., `P` for phenotypic abnormality.
+    pub aspect: String,
+    /// The sources, e.g., `PMID:1234`.
+    pub references: Vec<String>,
+}
+
+impl ResultAnnotation {
+    /// Create from an `Annotation` with the term names from `ontology`.
+    pub fn from_annotation_with_ontology(
+        annotation: &crate::common::phenotype_hpoa::Annotation,
+        ontology: &hpo::Ontology,
+    ) -> Self {
+        Self {
+            term: ResultHpoTerm::from_term_id(&annotation.hpo_id, ontology),
+            negated: annotation.negated,
+            evidence: annotation.evidence.to_string(),
+            onset: annotation
+                .onset
+                .as_ref()
+                .map(|onset| ResultHpoTerm::from_term_id(onset, ontology)),
+            frequency: annotation.frequency.clone(),
+            sex: annotation.sex.map(|sex| sex.to_string()),
+            modifiers: annotation
+                .modifiers
+                .iter()
+                .map(|modifier| ResultHpoTerm::from_term_id(modifier, ontology))
+                .collect(),
+            aspect: annotation.aspect.to_string(),
+            references: annotation.references.clone(),
+        }
+    }
+}
+
 /// Helper to deserialize a comma-separated list of strings.
 fn vec_str_deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
@@ -160,36 +274,96 @@ where
 #[openapi(
     paths(
         hpo_genes::handle,
+        hpo_enrichment::handle_terms,
         hpo_terms::handle,
+        hpo_terms_autocomplete::handle,
+        hpo_terms_tree::handle_parents,
+        hpo_terms_tree::handle_children,
+        hpo_terms_tree::handle_ancestors,
+        hpo_terms_tree::handle_descendants,
+        hpo_terms_tree::handle_path_to_root,
         hpo_omims::handle,
+        hpo_diseases::handle,
         hpo_sim::term_term::handle,
+        hpo_sim::term_term::handle_post,
         hpo_sim::term_gene::handle,
+        hpo_sim::term_gene::handle_post,
+        hpo_sim::term_gene_batch::handle,
+        hpo_sim::term_disease::handle,
+        hpo_sim::term_disease::handle_post,
+        hpo_sim::phenopacket_gene::handle,
+        hpo_sim::cohort::handle,
     ),
     components(schemas(
         hpo_genes::HpoGenesQuery,
         hpo_genes::HpoGenesResult,
         hpo_genes::HpoGenesResultEntry,
+        hpo_enrichment::HpoEnrichmentTermsQuery,
+        hpo_enrichment::HpoEnrichmentTermsResult,
+        crate::enrichment::TermEnrichment,
         hpo_omims::HpoOmimsQuery,
         hpo_omims::HpoOmimsResult,
         hpo_omims::HpoOmimsResultEntry,
+        hpo_diseases::HpoDiseasesQuery,
+        hpo_diseases::HpoDiseasesResult,
+        hpo_diseases::HpoDiseasesResultEntry,
+        hpo_diseases::DiseaseSource,
         hpo_terms::HpoTermsQuery,
         hpo_terms::HpoTermsResult,
         hpo_terms::HpoTermsResultEntry,
+        hpo_terms_autocomplete::HpoTermsAutocompleteQuery,
+        hpo_terms_autocomplete::HpoTermsAutocompleteResult,
+        hpo_terms_autocomplete::HpoTermsAutocompleteResultEntry,
+        hpo_terms_autocomplete::HighlightSpan,
+        hpo_terms_tree::HpoTermsTreeQuery,
+        hpo_terms_tree::HpoTermsTreeResult,
+        hpo_terms_tree::HpoTermsTreeResultEntry,
+        hpo_terms_tree::HpoTermsTreeTermQuery,
         hpo_sim::term_gene::HpoSimTermGeneQuery,
+        hpo_sim::term_gene::HpoSimTermGeneRequest,
+        hpo_sim::term_gene_batch::HpoSimTermGeneBatchRequest,
+        crate::batch::Case,
+        crate::batch::CaseResult,
         crate::query::query_result::HpoSimTermGeneResult,
         crate::query::query_result::HpoSimTermGeneResultEntry,
         crate::query::query_result::HpoSimTermGeneTermDetails,
+        hpo_sim::term_disease::HpoSimTermDiseaseQuery,
+        hpo_sim::term_disease::HpoSimTermDiseaseRequest,
+        crate::query::query_result::HpoSimTermDiseaseResult,
+        crate::query::query_result::HpoSimTermDiseaseResultEntry,
+        crate::query::query_result::HpoSimTermDiseaseTermDetails,
+        hpo_sim::phenopacket_gene::HpoSimPhenopacketGeneQuery,
+        hpo_sim::cohort::HpoSimCohortRequest,
+        hpo_sim::cohort::HpoSimCohortQuery,
+        hpo_sim::cohort::HpoSimCohortResult,
+        crate::cohort::SimilarityMatrix,
+        crate::pbs::phenopacket::Phenopacket,
+        crate::pbs::phenopacket::PhenotypicFeature,
+        crate::pbs::phenopacket::TimeElement,
+        crate::pbs::phenopacket::Age,
+        crate::pbs::phenopacket::AgeRange,
+        crate::pbs::phenopacket::GestationalAge,
+        crate::pbs::phenopacket::OntologyClass,
+        crate::pbs::phenopacket::Interpretation,
+        crate::pbs::phenopacket::Diagnosis,
+        crate::pbs::phenopacket::GenomicInterpretation,
+        crate::pbs::phenopacket::GeneDescriptor,
+        crate::pbs::phenopacket::VariantInterpretation,
+        crate::pbs::phenopacket::VariationDescriptor,
         crate::query::HpoTerm,
+        crate::query::RankingMethod,
         hpo_sim::term_term::HpoSimTermTermQuery,
         hpo_sim::term_term::HpoSimTermTermResult,
         hpo_sim::term_term::HpoSimTermTermResultEntry,
         ResultGene,
+        ResultAnnotation,
         ResultHpoTerm,
         Match,
         crate::common::Version,
         crate::common::IcBasedOn,
         crate::common::SimilarityMethod,
         crate::common::ScoreCombiner,
+        crate::common::PValueCorrection,
     ))
 )]
 pub struct ApiDoc;
@@ -204,10 +378,25 @@ pub async fn main(args: &Args, dbs: Data<Arc<WebServerData>>) -> std::io::Result
         App::new()
             .app_data(dbs.clone())
             .service(hpo_genes::handle)
+            .service(hpo_enrichment::handle_terms)
             .service(hpo_terms::handle)
+            .service(hpo_terms_autocomplete::handle)
+            .service(hpo_terms_tree::handle_parents)
+            .service(hpo_terms_tree::handle_children)
+            .service(hpo_terms_tree::handle_ancestors)
+            .service(hpo_terms_tree::handle_descendants)
+            .service(hpo_terms_tree::handle_path_to_root)
             .service(hpo_omims::handle)
+            .service(hpo_diseases::handle)
             .service(hpo_sim::term_term::handle)
+            .service(hpo_sim::term_term::handle_post)
             .service(hpo_sim::term_gene::handle)
+            .service(hpo_sim::term_gene::handle_post)
+            .service(hpo_sim::term_gene_batch::handle)
+            .service(hpo_sim::term_disease::handle)
+            .service(hpo_sim::term_disease::handle_post)
+            .service(hpo_sim::phenopacket_gene::handle)
+            .service(hpo_sim::cohort::handle)
             .service(
                 utoipa_swagger_ui::SwaggerUi::new("/swagger-ui/{_:.*}")
                     .url("/api-docs/openapi.json", openapi.clone()),
@@ -269,26 +458,77 @@ pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow:
         before_load_xlink.elapsed()
     );
 
-    tracing::info!("Loading HPO OBO...");
-    let before_load_obo = std::time::Instant::now();
-    let hpo_doc = fastobo::from_file(format!("{}/{}", &args.path_hpo_dir, "hp.obo"))
-        .map_err(|e| anyhow::anyhow!("Error loading HPO OBO: {}", e))?;
+    tracing::info!("Loading disease to gene mapping...");
+    let before_load_genes_to_phenotype = std::time::Instant::now();
+    let path_genes_to_phenotype = format!("{}/genes_to_phenotype.txt", args.path_hpo_dir);
+    let disease_to_genes =
+        crate::common::genes_to_phenotype::load_disease_to_genes(path_genes_to_phenotype)?;
     tracing::info!(
-        "... done loading HPO OBO in {:?}",
-        before_load_obo.elapsed()
+        "... done loading disease to gene mapping in {:?}",
+        before_load_genes_to_phenotype.elapsed()
     );
 
-    tracing::info!("Indexing OBO...");
-    let before_index_obo = std::time::Instant::now();
-    let full_text_index = crate::index::Index::new(hpo_doc)
-        .map_err(|e| anyhow::anyhow!("Error indexing HPO OBO: {}", e))?;
-    tracing::info!("... done indexing OBO in {:?}", before_index_obo.elapsed());
+    let path_index_dir = format!("{}/{}", args.path_hpo_dir, crate::index::INDEX_DIR_NAME);
+    let opened_index = if std::path::Path::new(&path_index_dir).exists() {
+        tracing::info!("Opening full text index...");
+        let before_open_index = std::time::Instant::now();
+        match crate::index::Index::open(&path_index_dir, &ontology.hpo_version())
+            .and_then(|index| Ok((index, crate::index::Index::read_diseases(&path_index_dir)?)))
+        {
+            Ok(index_and_diseases) => {
+                tracing::info!(
+                    "... done opening full text index in {:?}",
+                    before_open_index.elapsed()
+                );
+                Some(index_and_diseases)
+            }
+            Err(e) => {
+                tracing::warn!("could not open full text index, rebuilding: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let (full_text_index, diseases) = if let Some(index_and_diseases) = opened_index {
+        index_and_diseases
+    } else {
+        let diseases = crate::index::load_diseases(&args.path_hpo_dir)?;
+        let hpo_doc = crate::index::load_hpo_doc(&args.path_hpo_dir)?;
+        let genes = crate::index::load_genes(&args.path_hpo_dir)?;
+        tracing::info!("Indexing OBO, disease names, and gene names...");
+        let before_index_obo = std::time::Instant::now();
+        let full_text_index = crate::index::Index::new(&hpo_doc, &diseases, &genes)
+            .map_err(|e| anyhow::anyhow!("Error indexing HPO OBO: {}", e))?;
+        tracing::info!(
+            "... done indexing OBO, disease names, and gene names in {:?}",
+            before_index_obo.elapsed()
+        );
+        (full_text_index, diseases)
+    };
+
+    let annotation_frequencies =
+        crate::query::AnnotationFrequencies::new(&diseases, &disease_to_genes);
+    let candidate_onsets = crate::algos::onset::CandidateOnsets::new(&diseases, &disease_to_genes);
+    let mondo = crate::mondo::load_optional(&args.path_hpo_dir)?;
+
+    let simulation = args
+        .path_simulation_dir
+        .as_ref()
+        .map(|path| crate::simulate::store::load_store(path, &ontology))
+        .transpose()?;
 
     let data = actix_web::web::Data::new(Arc::new(WebServerData {
         ontology,
         ncbi_to_hgnc,
         hgnc_to_ncbi,
+        disease_to_genes,
+        diseases,
         full_text_index,
+        annotation_frequencies,
+        candidate_onsets,
+        mondo,
+        simulation,
     }));
 
     // Print the server URL and some hints (the latter: unless suppressed).
@@ -0,0 +1,471 @@
+//! Precomputation of empirical score distributions for P-value computation.
+
+use std::io::Write as _;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use clap::Parser;
+use hpo::{
+    annotations::{AnnotationId, Gene},
+    similarity::CachedSimilarity,
+    term::HpoGroup,
+    HpoTermId, Ontology,
+};
+use indicatif::ParallelProgressIterator as _;
+use prost::Message as _;
+use rayon::prelude::*;
+
+use crate::algos::phenomizer;
+use crate::common::to_pairwise_sim;
+use crate::pbs::simulation::{SimulationIndex, SimulationIndexEntry, SimulationResults};
+use crate::query::ScoringArgs;
+
+/// Command line arguments for `simulate` sub command.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Simulate score distributions for P-values", long_about = None)]
+pub struct Args {
+    /// Path to the directory with the HPO files.
+    #[arg(long, required = true)]
+    pub path_hpo_dir: String,
+    /// Path to the output directory with the simulation store.
+    #[arg(long, required = true)]
+    pub path_out_dir: String,
+
+    /// Number of simulations to perform for each gene and term count.
+    #[arg(
+        long,
+        default_value_t = 100_000,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub num_simulations: usize,
+    /// Maximal number of terms to simulate for.
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub max_terms: usize,
+    /// Optional gene symbol to limit the simulation to, for debugging.
+    #[arg(long)]
+    pub gene_symbol: Option<String>,
+    /// Number of threads to use for simulation, defaults to number of CPUs.
+    #[arg(long)]
+    pub num_threads: Option<usize>,
+    /// Seed for the random number generator.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Configuration of the similarity computation.
+    #[command(flatten)]
+    pub scoring: ScoringArgs,
+}
+
+/// The term that all simulated terms must descend from ("Phenotypic abnormality").
+const PHENOTYPIC_ABNORMALITY: &str = "HP:0000118";
+
+/// Simulate the sorted score distributions for one gene.
+///
+/// # Arguments
+///
+/// * `hpo` - The HPO ontology.
+/// * `gene` - The gene to simulate for.
+/// * `term_ids` - The terms to draw random term sets from, must contain at least
+///   `args.max_terms` elements.
+/// * `args` - The command line arguments.
+///
+/// # Returns
+///
+/// One `SimulationResults` record for each term count `1..=args.max_terms`.
+///
+/// # Panics
+///
+/// If the term count does not fit into `u32`.
+pub fn simulate_gene(
+    hpo: &Ontology,
+    gene: &Gene,
+    term_ids: &[HpoTermId],
+    args: &Args,
+) -> Vec<SimulationResults> {
+    let ncbi_gene_id = gene.id().as_u32();
+    let mut rng = if let Some(seed) = args.seed {
+        fastrand::Rng::with_seed(seed.wrapping_add(u64::from(ncbi_gene_id)))
+    } else {
+        fastrand::Rng::new()
+    };
+    let sim = CachedSimilarity::new(to_pairwise_sim(
+        args.scoring.similarity,
+        args.scoring.ic_base,
+    ));
+    let gene_terms = gene
+        .to_hpo_set(hpo)
+        .child_nodes()
+        .without_modifier()
+        .into_iter()
+        .collect::<HpoGroup>();
+
+    (1..=args.max_terms)
+        .map(|term_count| {
+            let mut scores = (0..args.num_simulations)
+                .map(|_| {
+                    let mut query = HpoGroup::new();
+                    while query.len() < term_count {
+                        query.insert(term_ids[rng.usize(..term_ids.len())]);
+                    }
+                    phenomizer::score(&query, &gene_terms, hpo, &sim, args.scoring.combiner)
+                })
+                .collect::<Vec<_>>();
+            scores.sort_by(f32::total_cmp);
+
+            SimulationResults {
+                ncbi_gene_id,
+                gene_symbol: gene.name().to_string(),
+                term_count: u32::try_from(term_count).expect("too many terms"),
+                scores,
+            }
+        })
+        .collect()
+}
+
+/// Data file writer together with the index written so far.
+struct StoreWriter {
+    /// The data file.
+    data: std::io::BufWriter<std::fs::File>,
+    /// Current offset in the data file.
+    offset: u64,
+    /// The index entries written so far.
+    entries: Vec<SimulationIndexEntry>,
+}
+
+impl StoreWriter {
+    /// Append a record to the data file and register it in the index.
+    fn write(&mut self, record: &SimulationResults) -> Result<(), anyhow::Error> {
+        let buf = record.encode_length_delimited_to_vec();
+        self.data.write_all(&buf)?;
+        let length = u64::try_from(buf.len())?;
+        self.entries.push(SimulationIndexEntry {
+            ncbi_gene_id: record.ncbi_gene_id,
+            term_count: record.term_count,
+            offset: self.offset,
+            length,
+        });
+        self.offset += length;
+        Ok(())
+    }
+}
+
+/// Main entry point for `simulate` sub command.
+///
+/// # Errors
+///
+/// In the case of simulation or I/O failure.
+pub fn run(args_common: &crate::common::Args, args: &Args) -> Result<(), anyhow::Error> {
+    tracing::info!("args_common = {:?}", &args_common);
+    tracing::info!("args = {:?}", &args);
+
+    if let Some(log::Level::Trace | log::Level::Debug) = args_common.verbose.log_level() {
+        std::env::set_var("RUST_LOG", "debug");
+        env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    }
+
+    if let Some(num_threads) = args.num_threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()?;
+    }
+
+    tracing::info!("Loading HPO...");
+    let before_loading = Instant::now();
+    let hpo = crate::common::load_hpo(&args.path_hpo_dir)?;
+    tracing::info!("...done loading HPO in {:?}", before_loading.elapsed());
+
+    // Collect the terms to draw from, excluding modifiers and the like.
+    let root = hpo
+        .hpo(HpoTermId::from(PHENOTYPIC_ABNORMALITY.to_string()))
+        .ok_or_else(|| anyhow::anyhow!("could not find {}", PHENOTYPIC_ABNORMALITY))?;
+    let term_ids = hpo
+        .hpos()
+        .filter(|term| term.child_of(&root) && !term.is_obsolete())
+        .map(|term| term.id())
+        .collect::<Vec<_>>();
+    tracing::info!("Drawing from {} terms", term_ids.len());
+    if term_ids.len() < args.max_terms {
+        anyhow::bail!(
+            "cannot draw {} terms from {} terms",
+            args.max_terms,
+            term_ids.len()
+        );
+    }
+
+    let genes = if let Some(gene_symbol) = &args.gene_symbol {
+        vec![hpo
+            .gene_by_name(gene_symbol)
+            .ok_or_else(|| anyhow::anyhow!("could not find gene {}", gene_symbol))?]
+    } else {
+        let mut genes = hpo.genes().collect::<Vec<_>>();
+        genes.sort_by_key(|gene| gene.id().as_u32());
+        genes
+    };
+
+    tracing::info!("Running simulations for {} genes...", genes.len());
+    let before_simulation = Instant::now();
+    std::fs::create_dir_all(&args.path_out_dir)?;
+    let store_writer = Mutex::new(StoreWriter {
+        data: std::io::BufWriter::new(std::fs::File::create(
+            std::path::Path::new(&args.path_out_dir).join(store::DATA_FILE_NAME),
+        )?),
+        offset: 0,
+        entries: Vec::new(),
+    });
+    let progress_bar = indicatif::ProgressBar::new(genes.len() as u64);
+    genes.par_iter().progress_with(progress_bar).try_for_each(
+        |gene| -> Result<(), anyhow::Error> {
+            let records = simulate_gene(&hpo, gene, &term_ids, args);
+            let mut store_writer = store_writer
+                .lock()
+                .map_err(|e| anyhow::anyhow!("could not lock writer: {}", e))?;
+            for record in &records {
+                store_writer.write(record)?;
+            }
+            Ok(())
+        },
+    )?;
+    let mut store_writer = store_writer
+        .into_inner()
+        .map_err(|e| anyhow::anyhow!("could not unwrap writer: {}", e))?;
+    store_writer.data.flush()?;
+    tracing::info!(
+        "... done with simulations in {:?}",
+        before_simulation.elapsed()
+    );
+
+    tracing::info!("Writing index...");
+    let mut entries = store_writer.entries;
+    entries.sort_by_key(|entry| (entry.ncbi_gene_id, entry.term_count));
+    let index = SimulationIndex {
+        hpo_version: hpo.hpo_version(),
+        viguno_version: crate::common::version().to_string(),
+        ic_base: args.scoring.ic_base.to_string(),
+        similarity: args.scoring.similarity.to_string(),
+        combiner: args.scoring.combiner.to_string(),
+        num_simulations: u32::try_from(args.num_simulations)?,
+        max_terms: u32::try_from(args.max_terms)?,
+        entries,
+    };
+    std::fs::write(
+        std::path::Path::new(&args.path_out_dir).join(store::INDEX_FILE_NAME),
+        index.encode_to_vec(),
+    )?;
+    tracing::info!("... done writing index");
+
+    tracing::info!("All done. Have a nice day!");
+    Ok(())
+}
+
+/// Read access to the simulation results written by `run`.
+pub mod store {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use hpo::Ontology;
+    use prost::Message as _;
+
+    use crate::pbs::simulation::{SimulationIndex, SimulationResults};
+    use crate::query::ScoringArgs;
+
+    /// Name of the file with the length-delimited `SimulationResults` records.
+    pub const DATA_FILE_NAME: &str = "simulation.bin";
+    /// Name of the file with the `SimulationIndex`.
+    pub const INDEX_FILE_NAME: &str = "simulation-index.bin";
+
+    /// On-disk store of simulated score distributions, keyed by gene and term count.
+    ///
+    /// The data file is memory-mapped once on opening, such that the records can be
+    /// read concurrently without reopening the file.
+    #[derive(Debug)]
+    pub struct Store {
+        /// The memory-mapped data file.
+        data: memmap2::Mmap,
+        /// The similarity computation configuration used for simulation.
+        scoring: ScoringArgs,
+        /// The HPO version used for simulation.
+        hpo_version: String,
+        /// The maximal number of terms simulated.
+        max_terms: u32,
+        /// Map from NCBI gene ID and term count to offset and length in the data file.
+        locations: HashMap<(u32, u32), (u64, usize)>,
+    }
+
+    impl Store {
+        /// Open the store in the directory `path`.
+        ///
+        /// # Errors
+        ///
+        /// In the case that the index cannot be read or is invalid, e.g., has no
+        /// simulated terms.
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+            let buf = std::fs::read(path.as_ref().join(INDEX_FILE_NAME))?;
+            let index = SimulationIndex::decode(buf.as_slice())?;
+            if index.max_terms == 0 {
+                anyhow::bail!(
+                    "simulation store in {:?} has no simulated terms",
+                    path.as_ref()
+                );
+            }
+            let scoring = ScoringArgs {
+                ic_base: index.ic_base.parse()?,
+                similarity: index.similarity.parse()?,
+                combiner: index.combiner.parse()?,
+            };
+            let locations = index
+                .entries
+                .iter()
+                .map(|entry| {
+                    Ok((
+                        (entry.ncbi_gene_id, entry.term_count),
+                        (entry.offset, usize::try_from(entry.length)?),
+                    ))
+                })
+                .collect::<Result<HashMap<_, _>, anyhow::Error>>()?;
+
+            let file = std::fs::File::open(path.as_ref().join(DATA_FILE_NAME))?;
+            // SAFETY: the data file is written once by `simulate` and must not be
+            // modified while the store is open.
+            let data = unsafe { memmap2::Mmap::map(&file)? };
+
+            Ok(Self {
+                data,
+                scoring,
+                hpo_version: index.hpo_version,
+                max_terms: index.max_terms,
+                locations,
+            })
+        }
+
+        /// Get the similarity computation configuration used for simulation.
+        pub fn scoring(&self) -> ScoringArgs {
+            self.scoring
+        }
+
+        /// Get the HPO version used for simulation.
+        pub fn hpo_version(&self) -> &str {
+            &self.hpo_version
+        }
+
+        /// Get the maximal number of terms simulated.
+        pub fn max_terms(&self) -> u32 {
+            self.max_terms
+        }
+
+        /// Load the simulation results for the given gene and term count.
+        ///
+        /// # Errors
+        ///
+        /// In the case that the record cannot be read from the data file.
+        pub fn get(
+            &self,
+            ncbi_gene_id: u32,
+            term_count: u32,
+        ) -> Result<Option<SimulationResults>, anyhow::Error> {
+            let Some((offset, length)) = self.locations.get(&(ncbi_gene_id, term_count)) else {
+                return Ok(None);
+            };
+            let buf = usize::try_from(*offset)
+                .ok()
+                .and_then(|offset| self.data.get(offset..offset.checked_add(*length)?))
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "simulation record for gene {} and {} terms is out of bounds",
+                        ncbi_gene_id,
+                        term_count
+                    )
+                })?;
+            Ok(Some(SimulationResults::decode_length_delimited(buf)?))
+        }
+
+        /// Compute the empirical P-value of `score` for the given gene and term count.
+        ///
+        /// The term count is capped at the maximal number of simulated terms.  Returns
+        /// `None` if there are no simulation results for the gene.
+        ///
+        /// # Errors
+        ///
+        /// In the case that the record cannot be read from the data file.
+        pub fn p_value(
+            &self,
+            ncbi_gene_id: u32,
+            term_count: usize,
+            score: f32,
+        ) -> Result<Option<f64>, anyhow::Error> {
+            let term_count = u32::try_from(term_count)?.clamp(1, self.max_terms);
+            Ok(self
+                .get(ncbi_gene_id, term_count)?
+                .map(|record| empirical_p_value(&record.scores, score)))
+        }
+    }
+
+    /// Open the store in the directory `path`, warning if it was created for another
+    /// HPO version than `hpo`.
+    ///
+    /// # Errors
+    ///
+    /// In the case that the store could not be opened.
+    pub fn load_store<P: AsRef<Path>>(path: P, hpo: &Ontology) -> Result<Store, anyhow::Error> {
+        tracing::info!("Opening simulation store...");
+        let before_open = std::time::Instant::now();
+        let store = Store::open(path)?;
+        if store.hpo_version() != hpo.hpo_version() {
+            tracing::warn!(
+                "simulation store is for HPO {}, but HPO is {}",
+                store.hpo_version(),
+                hpo.hpo_version()
+            );
+        }
+        tracing::info!(
+            "... done opening simulation store in {:?}",
+            before_open.elapsed()
+        );
+        Ok(store)
+    }
+
+    /// Compute empirical P-value of `score` given the ascendingly sorted `scores`.
+    ///
+    /// We use a pseudo count of one so that the P-value is never zero.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn empirical_p_value(scores: &[f32], score: f32) -> f64 {
+        let num_greater_equal = scores.len() - scores.partition_point(|s| *s < score);
+        (num_greater_equal + 1) as f64 / (scores.len() + 1) as f64
+    }
+
+    #[cfg(test)]
+    mod test {
+        #[test]
+        fn open_without_terms() -> Result<(), anyhow::Error> {
+            let tmpdir = tempdir::TempDir::new("viguno")?;
+            let index = crate::pbs::simulation::SimulationIndex {
+                ic_base: "gene".to_string(),
+                similarity: "resnik".to_string(),
+                combiner: "fun-sim-avg".to_string(),
+                ..Default::default()
+            };
+            std::fs::write(
+                tmpdir.path().join(super::INDEX_FILE_NAME),
+                prost::Message::encode_to_vec(&index),
+            )?;
+            std::fs::write(tmpdir.path().join(super::DATA_FILE_NAME), [])?;
+
+            let err = super::Store::open(tmpdir.path())
+                .err()
+                .ok_or_else(|| anyhow::anyhow!("store without terms must not open"))?;
+            assert!(err.to_string().contains("no simulated terms"), "{err}");
+            Ok(())
+        }
+
+        #[test]
+        fn empirical_p_value() {
+            let scores = [0.1, 0.2, 0.2, 0.5, 1.0];
+            assert!((super::empirical_p_value(&scores, 0.2) - 5.0 / 6.0).abs() < 1e-9);
+            assert!((super::empirical_p_value(&scores, 0.7) - 2.0 / 6.0).abs() < 1e-9);
+            assert!((super::empirical_p_value(&scores, 2.0) - 1.0 / 6.0).abs() < 1e-9);
+        }
+    }
+}